@@ -1,36 +1,85 @@
 #![feature(btree_cursors)]
 mod pyo3_utils;
 
-use std::{cmp::max, fs::{File, OpenOptions}, io::{BufReader, Cursor, Error, ErrorKind, Read, Seek}, path::PathBuf};
+use std::{cmp::{max, min}, fs::{File, OpenOptions}, io::BufReader, path::PathBuf, sync::Arc};
 
-use pyo3::{prelude::*, types::PyList};
+use arrow::{array::{Array, ArrayRef, Float64Builder, Int64Builder, LargeStringBuilder, StructArray}, compute::concat_batches, datatypes::{DataType, Field, Schema, SchemaRef}, error::ArrowError, ffi::{to_ffi, FFI_ArrowArray, FFI_ArrowSchema}, ffi_stream::FFI_ArrowArrayStream, record_batch::{RecordBatch, RecordBatchReader}};
+use pyo3::{prelude::*, types::{PyBytes, PyDict, PyList}};
 use pyo3_utils::new_from_iter;
-use zygos_db::{compression::{CompressionAlgorithm, RowDecompressor}, deserialize, ColumnType};
-use rhexdump::prelude::*;
+use zygos_db::{compression::CompressionAlgorithm, query::{BlockBounds, BlockCache, CellCoercion, CellRef}, CellValue, ColumnType};
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 #[derive(Clone, Debug)]
 pub struct DatabaseHeader {
     #[pyo3(get)]
     pub version: u8,
     #[pyo3(get)]
+    pub build_timestamp: u64,
+    #[pyo3(get)]
+    pub build_version: String,
+    #[pyo3(get)]
+    pub assembly: Option<String>,
+    #[pyo3(get)]
     pub datasets: Vec<DatasetHeader>,
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
+#[derive(Clone, Debug)]
+pub struct DatabaseSummary {
+    #[pyo3(get)]
+    pub num_datasets: usize,
+    #[pyo3(get)]
+    pub num_chromosomes: usize,
+    #[pyo3(get)]
+    pub num_tables: usize,
+}
+
+#[pyclass(module = "zygos_db")]
+#[derive(Clone, Debug)]
+pub struct TableDescription {
+    #[pyo3(get)]
+    pub dataset_name: String,
+    #[pyo3(get)]
+    pub chromosome: u8,
+    #[pyo3(get)]
+    pub columns: Vec<ColumnHeader>,
+    #[pyo3(get)]
+    pub min_position: u64,
+    #[pyo3(get)]
+    pub max_position: u64,
+    #[pyo3(get)]
+    pub num_rows: u64,
+    #[pyo3(get)]
+    pub num_blocks: usize,
+    pub compression_algorithm: CompressionAlgorithm,
+    pub index_compression_algorithm: CompressionAlgorithm,
+    #[pyo3(get)]
+    pub index_size: u64,
+}
+
+#[pyclass(module = "zygos_db")]
 #[derive(Clone, Debug)]
 pub struct DatasetHeader {
     #[pyo3(get)]
     pub name: String,
     pub compression_algorithm: CompressionAlgorithm,
     #[pyo3(get)]
+    pub rows_per_index: u64,
+    #[pyo3(get)]
+    pub position_decimals: Option<u8>,
+    #[pyo3(get)]
+    pub coordinate_base: Option<u8>,
+    #[pyo3(get)]
+    pub columnar: bool,
+    #[pyo3(get)]
     pub columns: Vec<ColumnHeader>,
     #[pyo3(get)]
     pub tables: Vec<TableHeader>,
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 #[derive(Clone, Debug)]
 pub struct ColumnHeader {
     pub type_: ColumnType,
@@ -38,29 +87,64 @@ pub struct ColumnHeader {
     pub name: String,
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 #[derive(Clone, Debug)]
 pub struct TableHeader {
     #[pyo3(get)]
     pub chromosome: u8,
     #[pyo3(get)]
     pub offset: u64,
+    pub index_compression_algorithm: CompressionAlgorithm,
 }
 
 impl From<zygos_db::query::DatabaseHeader> for DatabaseHeader {
     fn from(header: zygos_db::query::DatabaseHeader) -> Self {
         Self {
             version: header.version,
+            build_timestamp: header.build_timestamp,
+            build_version: header.build_version,
+            assembly: header.assembly,
             datasets: header.datasets.into_iter().map(DatasetHeader::from).collect(),
         }
     }
 }
 
+impl From<zygos_db::query::DatabaseSummary> for DatabaseSummary {
+    fn from(summary: zygos_db::query::DatabaseSummary) -> Self {
+        Self {
+            num_datasets: summary.num_datasets,
+            num_chromosomes: summary.num_chromosomes,
+            num_tables: summary.num_tables,
+        }
+    }
+}
+
+impl From<zygos_db::query::TableDescription> for TableDescription {
+    fn from(description: zygos_db::query::TableDescription) -> Self {
+        Self {
+            dataset_name: description.dataset_name,
+            chromosome: description.chromosome,
+            columns: description.columns.into_iter().map(ColumnHeader::from).collect(),
+            min_position: description.min_position,
+            max_position: description.max_position,
+            num_rows: description.num_rows,
+            num_blocks: description.num_blocks,
+            compression_algorithm: description.compression_algorithm,
+            index_compression_algorithm: description.index_compression_algorithm,
+            index_size: description.index_size,
+        }
+    }
+}
+
 impl From<zygos_db::query::DatasetHeader> for DatasetHeader {
     fn from(header: zygos_db::query::DatasetHeader) -> Self {
         Self {
             name: header.name,
             compression_algorithm: header.compression_algorithm,
+            rows_per_index: header.rows_per_index,
+            position_decimals: header.position_decimals,
+            coordinate_base: header.coordinate_base,
+            columnar: header.columnar,
             columns: header.columns.into_iter().map(ColumnHeader::from).collect(),
             tables: header.tables.into_iter().map(TableHeader::from).collect(),
         }
@@ -81,6 +165,41 @@ impl From<zygos_db::query::TableHeader> for TableHeader {
         Self {
             chromosome: header.chromosome,
             offset: header.offset,
+            index_compression_algorithm: header.index_compression_algorithm,
+        }
+    }
+}
+
+impl From<&DatasetHeader> for zygos_db::query::DatasetHeader {
+    fn from(header: &DatasetHeader) -> Self {
+        Self {
+            name: header.name.clone(),
+            compression_algorithm: header.compression_algorithm,
+            rows_per_index: header.rows_per_index,
+            position_decimals: header.position_decimals,
+            coordinate_base: header.coordinate_base,
+            columnar: header.columnar,
+            columns: header.columns.iter().map(zygos_db::query::ColumnHeader::from).collect(),
+            tables: header.tables.iter().map(zygos_db::query::TableHeader::from).collect(),
+        }
+    }
+}
+
+impl From<&ColumnHeader> for zygos_db::query::ColumnHeader {
+    fn from(header: &ColumnHeader) -> Self {
+        Self {
+            type_: header.type_,
+            name: header.name.clone(),
+        }
+    }
+}
+
+impl From<&TableHeader> for zygos_db::query::TableHeader {
+    fn from(header: &TableHeader) -> Self {
+        Self {
+            chromosome: header.chromosome,
+            offset: header.offset,
+            index_compression_algorithm: header.index_compression_algorithm,
         }
     }
 }
@@ -92,6 +211,30 @@ impl DatabaseHeader {
     }
 }
 
+#[pymethods]
+impl DatabaseSummary {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+}
+
+#[pymethods]
+impl TableDescription {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("{:?}", self))
+    }
+
+    #[getter]
+    fn compression_algorithm(&self) -> String {
+        format!("{:?}", self.compression_algorithm)
+    }
+
+    #[getter]
+    fn index_compression_algorithm(&self) -> String {
+        format!("{:?}", self.index_compression_algorithm)
+    }
+}
+
 #[pymethods]
 impl DatasetHeader {
     fn __repr__(&self) -> PyResult<String> {
@@ -116,21 +259,48 @@ impl TableHeader {
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!("{:?}", self))
     }
+
+    #[getter]
+    fn index_compression_algorithm(&self) -> String {
+        format!("{:?}", self.index_compression_algorithm)
+    }
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 struct DatabaseQueryClient {
     inner: zygos_db::query::DatabaseQueryClient<std::fs::File>,
     #[pyo3(get)]
     path: PathBuf,
     #[pyo3(get)]
     header: DatabaseHeader,
+    /// Queries of ranges that could contain more rows than this are rejected before any block is read
+    #[pyo3(get, set)]
+    max_rows: Option<u64>,
+    /// Blocks that decompress into more bytes than this are rejected with an error instead of being read into memory
+    #[pyo3(get, set)]
+    max_decompressed_size: Option<u64>,
+    /// Whether the query bounds are 0-based or 1-based. They are translated to the coordinate base of the dataset.
+    #[pyo3(get)]
+    coordinate_base: Option<u8>,
 }
 
 #[pymethods]
 impl DatabaseQueryClient {
+    /// Open a database. If `assembly` is given, the database must have been built for that genome assembly. If
+    /// `max_rows` is given, queries of ranges that could contain more rows are rejected before any block is read. If
+    /// `coordinate_base` is given, the query bounds are treated as 0-based or 1-based and translated to the coordinate
+    /// base of each dataset. If `max_decompressed_size` is given, queries fail on blocks that decompress into more bytes.
     #[new]
-    fn new(path: PathBuf) -> PyResult<Self> {
+    #[pyo3(signature = (path, assembly=None, max_rows=None, coordinate_base=None, max_decompressed_size=None))]
+    fn new(
+        path: PathBuf,
+        assembly: Option<&str>,
+        max_rows: Option<u64>,
+        coordinate_base: Option<u8>,
+        max_decompressed_size: Option<u64>,
+    ) -> PyResult<Self> {
+        check_coordinate_base(coordinate_base)?;
+
         let file = OpenOptions::new()
             .read(true)
             .open(&path)
@@ -138,6 +308,11 @@ impl DatabaseQueryClient {
 
         let mut inner = zygos_db::query::DatabaseQueryClient::new(file);
 
+        if let Some(assembly) = assembly {
+            inner.check_assembly(assembly)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        }
+
         let header = inner.read_database_header()
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e))?;
 
@@ -145,9 +320,19 @@ impl DatabaseQueryClient {
             inner,
             path,
             header: header.into(),
+            max_rows,
+            max_decompressed_size,
+            coordinate_base,
         })
     }
 
+    #[setter]
+    fn set_coordinate_base(&mut self, coordinate_base: Option<u8>) -> PyResult<()> {
+        check_coordinate_base(coordinate_base)?;
+        self.coordinate_base = coordinate_base;
+        Ok(())
+    }
+
     fn read_table_index(&mut self, dataset_name: &str, chromosome: u8) -> PyResult<TableIndex> {
         let dataset = self.header.datasets.iter()
             .find(|dataset| dataset.name == dataset_name)
@@ -157,35 +342,127 @@ impl DatabaseQueryClient {
             .find(|table| table.chromosome == chromosome)
             .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Table not found: {}", chromosome)))?;
 
-        let index = self.inner.read_table_index(table.offset)
+        let index = self.inner.read_table_index(&table.into())
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e))?;
 
         Ok(TableIndex {
             inner: index,
             dataset_name: dataset_name.to_string(),
             chromosome,
-            columns: dataset.columns.clone(),
+            dataset: dataset.into(),
             path: self.path.clone(),
-            compression_algorithm: dataset.compression_algorithm,
+            max_rows: self.max_rows,
+            max_decompressed_size: self.max_decompressed_size,
+            coordinate_base: self.coordinate_base,
+            column_names: dataset.columns.iter().map(|column| column.name.clone()).collect(),
         })
     }
 
+    /// Read and cache the indices of all tables of a dataset in parallel, so later calls to `read_table_index` don't
+    /// read the file.
+    fn prefetch_indices(&mut self, py: Python<'_>, dataset_name: &str) -> PyResult<()> {
+        let dataset = self.header.datasets.iter()
+            .find(|dataset| dataset.name == dataset_name)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Dataset not found: {}", dataset_name)))?;
+
+        let dataset: zygos_db::query::DatasetHeader = dataset.into();
+        let path = self.path.clone();
+        let inner = &mut self.inner;
+
+        py.allow_threads(|| inner.prefetch_indices(&dataset, || File::open(&path)))
+            .map_err(PyErr::new::<pyo3::exceptions::PyIOError, _>)
+    }
+
+    /// Drop the indices that were cached by `read_table_index` and `prefetch_indices`, to free their memory
+    fn clear_index_cache(&mut self) {
+        self.inner.clear_index_cache();
+    }
+
+    fn has_chromosome(&mut self, dataset_name: &str, chromosome: u8) -> PyResult<bool> {
+        self.inner.has_chromosome(dataset_name, chromosome)
+            .map_err(PyErr::new::<pyo3::exceptions::PyIOError, _>)
+    }
+
+    /// Count the datasets, distinct chromosomes and tables of the database
+    fn summary(&mut self) -> PyResult<DatabaseSummary> {
+        self.inner.summary()
+            .map(DatabaseSummary::from)
+            .map_err(PyErr::new::<pyo3::exceptions::PyIOError, _>)
+    }
+
+    /// The compression algorithm of the blocks of a dataset, as stored in the file
+    fn compression_algorithm(&mut self, dataset_name: &str) -> PyResult<String> {
+        self.inner.compression_algorithm(dataset_name)
+            .map(|compression_algorithm| format!("{:?}", compression_algorithm))
+            .map_err(io_error_into_py)
+    }
+
+    /// Describe the columns, bounds, number of rows, compression and index size of the table of a chromosome
+    fn describe_table(&mut self, dataset_name: &str, chromosome: u8) -> PyResult<TableDescription> {
+        self.inner.describe_table(dataset_name, chromosome)
+            .map(TableDescription::from)
+            .map_err(io_error_into_py)
+    }
+
+    /// Check the checksum of the blocks and index of every table against the checksum stored after its index. Returns
+    /// the number of tables that were checked, or raises an `IOError` for the first corrupted table.
+    fn verify_table_checksums(&mut self) -> PyResult<usize> {
+        self.inner.verify_table_checksums()
+            .map_err(io_error_into_py)
+    }
+
+    /// Overwrite the value of a float column in the first row at a position, without rebuilding the database. Only
+    /// uncompressed datasets can be updated. Readers that already read the block may keep seeing the old value.
+    ///
+    /// The position is translated from the coordinate base of the client like the bounds of queries. Float positions
+    /// must be given as the integers they are stored as, scaled by `10^position_decimals`.
+    fn update_cell(&mut self, dataset_name: &str, chromosome: u8, position: u64, column: &str, value: f64) -> PyResult<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)
+            .map_err(PyErr::new::<pyo3::exceptions::PyIOError, _>)?;
+
+        let mut client = zygos_db::query::DatabaseQueryClient::new(file);
+
+        // A dataset that isn't found is reported by `update_cell`
+        let position = match self.coordinate_base {
+            Some(coordinate_base) => client.read_database_header().map_err(io_error_into_py)?
+                .datasets.iter()
+                .find(|dataset| dataset.name == dataset_name)
+                .map_or(Ok(position), |dataset| dataset.translate_position(position, coordinate_base))
+                .map_err(io_error_into_py)?,
+            None => position,
+        };
+
+        client.update_cell(dataset_name, chromosome, position, column, value).map_err(io_error_into_py)?;
+
+        // The checksum of the updated block changed, so the cached index of its table is outdated
+        self.inner.clear_index_cache();
+
+        Ok(())
+    }
+
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!("{:?}", self.header))
     }
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 #[derive(Clone)]
 struct TableIndex {
-    inner: zygos_db::query::TableIndex,
+    inner: Arc<zygos_db::query::TableIndex>,
     #[pyo3(get)]
     dataset_name: String,
     #[pyo3(get)]
     chromosome: u8,
-    columns: Vec<ColumnHeader>,
+    dataset: zygos_db::query::DatasetHeader,
     path: PathBuf,
-    compression_algorithm: CompressionAlgorithm,
+    max_rows: Option<u64>,
+    max_decompressed_size: Option<u64>,
+    coordinate_base: Option<u8>,
+    /// The names of the columns, shared by the rows of every query of the table
+    column_names: Arc<[String]>,
 }
 
 impl std::fmt::Debug for TableIndex {
@@ -193,6 +470,9 @@ impl std::fmt::Debug for TableIndex {
         f.debug_struct("TableIndex")
             .field("dataset_name", &self.dataset_name)
             .field("chromosome", &self.chromosome)
+            .field("max_position", &self.inner.max_position)
+            .field("index_start_offset", &self.inner.index_start_offset)
+            .field("index_end_offset", &self.inner.index_end_offset)
             .finish()
     }
 }
@@ -209,7 +489,7 @@ impl TableIndex {
 
     #[getter]
     fn min_position(&self) -> u64 {
-        self.inner.inner.keys().next().copied().unwrap_or(0)
+        self.inner.min_position()
     }
 
     #[getter]
@@ -227,23 +507,55 @@ impl TableIndex {
         self.inner.index_end_offset
     }
 
-    fn create_query(&self) -> PyResult<RowReader> {
-        Ok(RowReader::new(
+    /// Format a region of the database file as a hex and ASCII view, for debugging the file format.
+    fn hexdump_region(&self, offset: u64, length: u64) -> PyResult<String> {
+        let file = File::open(&self.path)
+            .map_err(PyErr::new::<pyo3::exceptions::PyIOError, _>)?;
+
+        zygos_db::query::DatabaseQueryClient::new(file).hexdump_region(offset, length)
+            .map_err(PyErr::new::<pyo3::exceptions::PyIOError, _>)
+    }
+
+    /// Create a reader for the rows of the table. With `read_ahead`, `query_range` decompresses the next block on a
+    /// background thread while the current block is deserialized, which speeds up scans of many blocks.
+    #[pyo3(signature = (read_ahead=false))]
+    fn create_query(&self, read_ahead: bool) -> PyResult<RowReader> {
+        let mut row_reader = RowReader::new(
             self.path.clone(),
             self.clone(),
-        )?)
+        )?;
+        row_reader.inner.set_read_ahead(read_ahead);
+
+        Ok(row_reader)
     }
 
-    fn create_query_parallel(&self, num_threads: Option<usize>) -> PyResult<ParallelRowReader> {
-        let row_readers = (0..num_threads.unwrap_or_else(rayon::current_num_threads))
-            .map(|_| RowReader::new(
-                self.path.clone(),
-                self.clone(),
-            ));
+    /// Create a reader that reads the blocks of a range in parallel. Ranges with fewer blocks than `min_parallel_blocks`
+    /// are read by a single thread, as the coordination would cost more than it saves. Without the `parallel` feature,
+    /// the blocks are read sequentially. If `block_cache_size` is set, the readers share a cache of that many decompressed
+    /// blocks, so blocks of overlapping ranges aren't decompressed again by another thread.
+    #[pyo3(signature = (num_threads=None, min_parallel_blocks=DEFAULT_MIN_PARALLEL_BLOCKS, block_cache_size=None))]
+    fn create_query_parallel(&self, num_threads: Option<usize>, min_parallel_blocks: usize, block_cache_size: Option<usize>) -> PyResult<ParallelRowReader> {
+        #[cfg(feature = "parallel")]
+        let default_num_threads = rayon::current_num_threads;
+        #[cfg(not(feature = "parallel"))]
+        let default_num_threads = || 1;
+
+        let block_cache = block_cache_size.map(BlockCache::shared);
+
+        let row_readers = (0..num_threads.unwrap_or_else(default_num_threads))
+            .map(|_| {
+                let mut row_reader = RowReader::new(
+                    self.path.clone(),
+                    self.clone(),
+                )?;
+                row_reader.inner.set_block_cache(block_cache.clone());
+                Ok(row_reader)
+            });
 
         Ok(ParallelRowReader {
             index: self.clone(),
-            row_readers: row_readers.collect::<Result<Vec<_>, _>>()?,
+            row_readers: row_readers.collect::<PyResult<Vec<_>>>()?,
+            min_parallel_blocks,
         })
     }
 
@@ -252,9 +564,9 @@ impl TableIndex {
     }
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 struct RowReader {
-    reader: BufReader<File>,
+    inner: zygos_db::query::RowReader<BufReader<File>>,
     index: TableIndex,
 }
 
@@ -265,141 +577,140 @@ impl RowReader {
             .open(path)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e))?;
 
-        let reader = BufReader::new(file);
+        let mut inner = zygos_db::query::RowReader::new(BufReader::new(file), &index.dataset, Arc::clone(&index.inner));
+        inner.set_max_rows(index.max_rows);
+        inner.set_max_decompressed_size(index.max_decompressed_size);
 
         Ok(Self {
-            reader,
+            inner,
             index,
         })
     }
 
-    /// Deserialize a range of bytes from the reader using raw offsets. Unless you know what you're doing, use `query_range` instead.
+    /// Translate a position from the coordinate base of the client to the coordinate base of the dataset
+    fn translate_position(&self, position: u64) -> std::io::Result<u64> {
+        match self.index.coordinate_base {
+            Some(coordinate_base) => self.inner.translate_position(position, coordinate_base),
+            None => Ok(position),
+        }
+    }
+
+    /// Translate the bounds of a query, keeping unbounded ends unbounded
+    fn translate_bounds(&self, position_value_start: Option<u64>, position_value_end: Option<u64>) -> std::io::Result<(Option<u64>, Option<u64>)> {
+        Ok((
+            position_value_start.map(|position| self.translate_position(position)).transpose()?,
+            position_value_end.map(|position| self.translate_position(position)).transpose()?,
+        ))
+    }
+
+    /// Wrap the cells of queried rows into rows that can also be indexed by the names of the columns of the table
+    fn rows(&self, rows: Vec<Vec<CellValue>>) -> Vec<Row> {
+        rows.into_iter().map(|cells| Row::new(cells, &self.index.column_names)).collect()
+    }
+}
+
+#[pymethods]
+impl RowReader {
+    /// Query a range of rows from the database
     /// 
     /// # Arguments
     /// 
-    /// * `bytes` - The bytes to deserialize
-    /// * `position_value_start` - Skip rows until the position value is greater than or equal to this value
-    /// * `position_value_end` - Stop if the position value is greater than this value
+    /// * `position_value_start` - The start of the range (inclusive), or `None` to start at the first row of the table
+    /// * `position_value_end` - The end of the range (exclusive), or `None` to end after the last row of the table
+    /// * `by_end` - Query the rows of an interval table by their end position instead of their start, using the end
+    ///   index of the table
     /// 
     /// # Returns
     /// 
-    /// A vector of bytes
-    pub fn deserialize_range(
-        &self,
-        bytes: &[u8],
-        position_value_start: u64,
-        position_value_end: u64,
-        out_rows: &mut Vec<Row>,
-    ) -> std::io::Result<()> {
-        // println!("Deserializing range: {}:{}-{}", self.index.chromosome, position_value_start, position_value_end);
-
-        let offset_start: u64 = 0;
-        let offset_end = bytes.len() as u64;
-
-        let mut cursor: Cursor<&[u8]> = Cursor::new(bytes);
-
-        let skip_lambdas: Vec<_> = self.index.columns.iter()
-            .skip(1) // Skip the first position column, as we always want to read it
-            .map(|column| {
-                match column.type_ {
-                    ColumnType::Integer => {
-                        |cursor: &mut Cursor<&[u8]>| {
-                            deserialize::skip_zigzag_i64(cursor).unwrap()
-                        }
-                    },
-                    ColumnType::Float => {
-                        |cursor: &mut Cursor<&[u8]>| {
-                            deserialize::skip_f64(cursor).unwrap()
-                        }
-                    },
-                    ColumnType::VolatileString => {
-                        |cursor: &mut Cursor<&[u8]>| {
-                            deserialize::skip_string_u8(cursor).unwrap()
-                        }
-                    },
-                    ColumnType::HashtableString => {
-                        todo!("HashtableString has not been implemented yet!");
-                    },
-                }
-            }).collect();
-
-        let read_lambdas: Vec<_> = self.index.columns.iter().map(|column| {
-            match column.type_ {
-                ColumnType::Integer => {
-                    |cursor: &mut Cursor<&[u8]>| {
-                        let (value, len) = deserialize::read_zigzag_i64(cursor)?;
-                        Ok((CellValue::I64(value), len))
-                    }
-                },
-                ColumnType::Float => {
-                    |cursor: &mut Cursor<&[u8]>| Ok((CellValue::F64(deserialize::read_f64(cursor)?), 8))
-                },
-                ColumnType::VolatileString => {
-                    |cursor: &mut Cursor<&[u8]>| {
-                        let string = match deserialize::read_string_u8(cursor) {
-                            Ok(string) => string,
-                            Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!(
-                                "Reading string failed: {:?}", e
-                            ))),
-                        };
-                        let bytes_read = string.len() as usize + 1;
-                        Ok((CellValue::String(string), bytes_read))
-                    }
-                },
-                ColumnType::HashtableString => {
-                    todo!("HashtableString has not been implemented yet!");
-                },
-            }
-        }).collect();
+    /// A vector of rows
+    #[pyo3(signature = (position_value_start=None, position_value_end=None, by_end=false))]
+    fn query_range(&mut self, position_value_start: Option<u64>, position_value_end: Option<u64>, by_end: bool) -> std::io::Result<Vec<Row>> {
+        let (position_value_start, position_value_end) = self.translate_bounds(position_value_start, position_value_end)?;
+        let rows = if by_end {
+            self.inner.query_end_range(position_value_start.unwrap_or(0), position_value_end.unwrap_or(u64::MAX))?
+        } else {
+            self.inner.query_open_range(position_value_start, position_value_end)?
+        };
+        Ok(self.rows(rows))
+    }
 
-        let mut offset_in_block = offset_start;
-        'row_loop: loop {
-            if offset_in_block >= offset_end {
-                break;
-            }
+    /// Query the rows of an interval table of which the interval overlaps a range, using the end index of the table
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// 
+    /// # Returns
+    /// 
+    /// A vector of rows of which the start is before the end of the range and the end (inclusive) is at or after the
+    /// start of the range
+    fn query_overlapping(&mut self, position_value_start: u64, position_value_end: u64) -> PyResult<Vec<Row>> {
+        let position_value_start = self.translate_position(position_value_start)?;
+        let position_value_end = self.translate_position(position_value_end)?;
+        let rows = self.inner.query_overlapping(position_value_start, position_value_end)
+            .map_err(io_error_into_py)?;
+        Ok(self.rows(rows))
+    }
 
-            let mut cells = Vec::new();
-            let mut i = 0;
-            for lambda in &read_lambdas {
-                let (value, bytes_read) = lambda(&mut cursor).map_err(|e| Error::new(ErrorKind::InvalidData, format!(
-                    "Failed to read column {} of after successfully reading row at position {:?} of chromosome {:?}, before stopping at {:?}: {:?}",
-                    i, offset_in_block, self.index.chromosome, offset_end, e,
-                )))?;
-
-                offset_in_block += bytes_read as u64;
-
-                if i == 0 {
-                    match value {
-                        CellValue::I64(i) => {
-                            if i > position_value_end as i64 {
-                                break 'row_loop;
-                            } else if i < position_value_start as i64 {
-                                // Skip this row
-                                for lambda in &skip_lambdas {
-                                    let bytes_skipped = lambda(&mut cursor);
-                                    offset_in_block += bytes_skipped as u64;
-                                }
-                                continue 'row_loop;
-                            }
-                        },
-                        _ => panic!("First column must be an integer"),
-                    }
-                }
-                i += 1;
+    /// Read the row at an ordinal in the order of the table, such as 0 for its first row. Only the block containing the
+    /// row is read. Raises an `IndexError` if the table has no more than `ordinal` rows.
+    fn row_at(&mut self, ordinal: u64) -> PyResult<Row> {
+        let row = self.inner.row_at(ordinal)?.ok_or_else(|| PyErr::new::<pyo3::exceptions::PyIndexError, _>(format!(
+            "Row {} is out of bounds for {} rows", ordinal, self.inner.index().num_rows(),
+        )))?;
+        Ok(Row::new(row, &self.index.column_names))
+    }
 
-                cells.push(value);
-            }
-            out_rows.push(Row { cells });
-        }
+    /// Check that the index is consistent with the blocks it points to. Raises an `IOError` for the first entry of which
+    /// the position doesn't match the first row of its block.
+    fn validate_index(&mut self) -> std::io::Result<()> {
+        self.inner.validate_index()
+    }
 
-        Ok(())
+    /// Query a range of rows, but only read the cells of some columns
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// * `columns` - The names of the columns to read
+    /// 
+    /// # Returns
+    /// 
+    /// A vector of rows, containing the cells of the columns in the order of `columns`
+    fn query_range_projected(&mut self, position_value_start: u64, position_value_end: u64, columns: Vec<String>) -> PyResult<Vec<Row>> {
+        let column_indices = self.inner.column_indices(&columns)
+            .map_err(io_error_into_py)?;
+
+        let position_value_start = self.translate_position(position_value_start)?;
+        let position_value_end = self.translate_position(position_value_end)?;
+        let rows = self.inner.query_range_projected(position_value_start, position_value_end, column_indices)
+            .map_err(io_error_into_py)?;
+        let column_names: Arc<[String]> = columns.into();
+        Ok(rows.into_iter().map(|cells| Row::new(cells, &column_names)).collect())
     }
 
-}
+    /// Query the rows around each of many anchor positions. Blocks that are shared by the windows of several anchors are
+    /// only read once.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `anchors` - The positions to query around
+    /// * `window` - The distance around each anchor, so the range of an anchor is `[anchor - window, anchor + window)`
+    /// 
+    /// # Returns
+    /// 
+    /// A list of the rows around each anchor, in the order of the anchors
+    fn query_windows(&mut self, anchors: Vec<u64>, window: u64) -> std::io::Result<Vec<Vec<Row>>> {
+        let anchors = anchors.into_iter()
+            .map(|anchor| self.translate_position(anchor))
+            .collect::<std::io::Result<Vec<u64>>>()?;
+        let windows = self.inner.query_windows(&anchors, window)?;
+        Ok(windows.into_iter().map(|rows| self.rows(rows)).collect())
+    }
 
-#[pymethods]
-impl RowReader {
-    /// Query a range of rows from the database
+    /// Query a range of rows of a dataset with float positions, such as genetic map positions
     /// 
     /// # Arguments
     /// 
@@ -408,54 +719,487 @@ impl RowReader {
     /// 
     /// # Returns
     /// 
-    /// A vector of rows
-    fn query_range(&mut self, position_value_start: u64, position_value_end: u64) -> std::io::Result<Vec<Row>> {
-        let mut range: Vec<(u64, u64)> = self.index.get_range(position_value_start, position_value_end)?;
+    /// A vector of rows, with the positions as floats
+    fn query_float_range(&mut self, position_value_start: f64, position_value_end: f64) -> std::io::Result<Vec<Row>> {
+        let rows = self.inner.query_float_range(position_value_start, position_value_end)?;
+        Ok(self.rows(rows))
+    }
 
-        let start_offset = match range.first() {
-            Some((_position, offset)) => *offset,
-            None => return Ok(Vec::new()),
+    /// Query a range of rows grouped by the value of a column
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// * `group_column` - The name of the integer or string column to group by
+    /// 
+    /// # Returns
+    /// 
+    /// A dictionary of the value of each group to its rows
+    fn query_range_grouped(&mut self, py: Python<'_>, position_value_start: u64, position_value_end: u64, group_column: &str) -> PyResult<PyObject> {
+        let group_column_index = self.inner.columns().iter()
+            .position(|column| column.name == group_column)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Column not found: {}", group_column)))?;
+
+        let position_value_start = self.translate_position(position_value_start)?;
+        let position_value_end = self.translate_position(position_value_end)?;
+        let groups = self.inner.query_range_grouped(position_value_start, position_value_end, group_column_index)?;
+
+        let dict = PyDict::new_bound(py);
+        for (value, rows) in groups {
+            let rows = PyList::new_bound(py, self.rows(rows).into_iter().map(|row| row.into_py(py)));
+            dict.set_item(cell_value_into_py(value, py), rows)?;
+        }
+
+        Ok(dict.into())
+    }
+
+    /// Get the distinct values of a column in a range, in order of their first occurrence
+    /// 
+    /// # Arguments
+    /// 
+    /// * `column` - The name of the integer or string column
+    /// * `position_value_start` - The start of the range (inclusive), or `None` to start at the first row of the table
+    /// * `position_value_end` - The end of the range (exclusive), or `None` to end after the last row of the table
+    #[pyo3(signature = (column, position_value_start=None, position_value_end=None))]
+    fn distinct_values(&mut self, py: Python<'_>, column: &str, position_value_start: Option<u64>, position_value_end: Option<u64>) -> PyResult<PyObject> {
+        let column_index = self.inner.columns().iter()
+            .position(|header| header.name == column)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Column not found: {}", column)))?;
+
+        let (position_value_start, position_value_end) = self.translate_bounds(position_value_start, position_value_end)?;
+        let (position_value_start, position_value_end) = self.inner.resolve_bounds(position_value_start, position_value_end);
+        let values = self.inner.distinct_values(column_index, position_value_start, position_value_end)?;
+
+        Ok(PyList::new_bound(py, values.into_iter().map(|value| cell_value_into_py(value, py))).into())
+    }
+
+    /// Whether the positions of the rows returned by `query_range` are verified to be within the requested range.
+    /// Enabled by default in debug builds.
+    #[getter]
+    fn get_strict_bounds(&self) -> bool {
+        self.inner.strict_bounds()
+    }
+
+    #[setter]
+    fn set_strict_bounds(&mut self, strict_bounds: bool) {
+        self.inner.set_strict_bounds(strict_bounds);
+    }
+
+    /// Whether `query_range` decompresses the next block on a background thread
+    #[getter]
+    fn get_read_ahead(&self) -> bool {
+        self.inner.read_ahead()
+    }
+
+    #[setter]
+    fn set_read_ahead(&mut self, read_ahead: bool) {
+        self.inner.set_read_ahead(read_ahead);
+    }
+
+    /// Convert the values of a column to another type in the rows returned by `query_range`
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - The name of the column
+    /// * `coercion` - `"float"` to return integers as floats, `"string"` to return numbers as strings, or `None` to
+    ///   return the values as they are stored
+    #[pyo3(signature = (column, coercion))]
+    fn set_column_coercion(&mut self, column: &str, coercion: Option<&str>) -> PyResult<()> {
+        let column_index = self.inner.columns().iter()
+            .position(|header| header.name == column)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Column not found: {}", column)))?;
+
+        let coercion = match coercion {
+            Some("float") => Some(CellCoercion::Float),
+            Some("string") => Some(CellCoercion::String),
+            Some(coercion) => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown coercion: {}", coercion))),
+            None => None,
         };
-        self.reader.seek(std::io::SeekFrom::Start(start_offset))?;
 
-        // Append the end of the index to the range
-        range.push((position_value_end, self.index.inner.index_start_offset));
+        self.inner.set_column_coercion(column_index, coercion)
+            .map_err(io_error_into_py)
+    }
 
-        let blocks = range.windows(2).map(|window| {
-            let [start, end] = window else { unreachable!() };
-            (start, end)
-        });
+    /// The compression algorithm the blocks are decompressed with, as stored in the header of the dataset
+    #[getter]
+    fn compression_algorithm(&self) -> String {
+        format!("{:?}", self.inner.compression_algorithm())
+    }
 
-        let mut compressed: Vec<u8> = Vec::new();
-        let mut decompressed: Vec<u8> = Vec::new();
-        let decompressor = RowDecompressor::new(self.index.compression_algorithm);
+    /// Iterate over the rows of a range, reading the blocks one at a time as the iteration reaches them. Only the rows of
+    /// the current block are kept in memory, and the blocks after the iteration stops are never read.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive), or `None` to start at the first row of the table
+    /// * `position_value_end` - The end of the range (exclusive), or `None` to end after the last row of the table
+    /// 
+    /// # Returns
+    /// 
+    /// An iterator of rows
+    #[pyo3(signature = (position_value_start=None, position_value_end=None))]
+    fn iter_range(&self, position_value_start: Option<u64>, position_value_end: Option<u64>) -> PyResult<RowIterator> {
+        let row_reader = RowReader::new(self.index.path.clone(), self.index.clone())?;
+        let (position_value_start, position_value_end) = row_reader.translate_bounds(position_value_start, position_value_end)?;
+        let (position_value_start, position_value_end) = row_reader.inner.resolve_bounds(position_value_start, position_value_end);
+        row_reader.inner.check_row_limit(position_value_start, position_value_end)?;
+
+        Ok(RowIterator::new(row_reader, position_value_start, position_value_end))
+    }
 
-        let mut rows = Vec::new();
-        for (start, end) in blocks {
-            compressed.clear();
-            self.reader.by_ref().take(end.1 - start.1).read_to_end(&mut compressed)?;
-
-            let slice = match decompressor.decompress(&compressed, &mut decompressed) {
-                Ok(res) => res,
-                Err(e) => {
-                    eprintln!("Decompression failed: {:?}", e);
-                    rhexdump!(&compressed[..], start.1);
-                    return Err(e);
-                },
+    /// Query a range of rows from the database as a stream of Arrow record batches, one for each block.
+    /// Blocks are only read and decompressed when the next record batch is requested.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive), or `None` to start at the first row of the table
+    /// * `position_value_end` - The end of the range (exclusive), or `None` to end after the last row of the table
+    /// 
+    /// # Returns
+    /// 
+    /// A `pyarrow.RecordBatchReader`
+    #[pyo3(signature = (position_value_start=None, position_value_end=None))]
+    fn query_arrow(&self, py: Python<'_>, position_value_start: Option<u64>, position_value_end: Option<u64>) -> PyResult<PyObject> {
+        let row_reader = RowReader::new(self.index.path.clone(), self.index.clone())?;
+        let (position_value_start, position_value_end) = row_reader.translate_bounds(position_value_start, position_value_end)?;
+        let (position_value_start, position_value_end) = row_reader.inner.resolve_bounds(position_value_start, position_value_end);
+        row_reader.inner.check_row_limit(position_value_start, position_value_end)?;
+        let batch_reader = ArrowBlockReader::new(row_reader, position_value_start, position_value_end);
+
+        let mut stream = Box::new(FFI_ArrowArrayStream::new(Box::new(batch_reader)));
+
+        // pyarrow moves the stream out of the pointer, leaving a released stream behind that is safe to drop
+        let pyarrow = py.import_bound("pyarrow")?;
+        let res = pyarrow
+            .getattr("RecordBatchReader")?
+            .call_method1("_import_from_c", (&mut *stream as *mut FFI_ArrowArrayStream as usize,))?;
+
+        Ok(res.into())
+    }
+
+    /// Query a range of rows into a single Arrow record batch. The cells are appended to the arrays of their columns one
+    /// block at a time, so no Python object is created per cell.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive), or `None` to start at the first row of the table
+    /// * `position_value_end` - The end of the range (exclusive), or `None` to end after the last row of the table
+    /// 
+    /// # Returns
+    /// 
+    /// A `pyarrow.RecordBatch`
+    #[pyo3(signature = (position_value_start=None, position_value_end=None))]
+    fn query_range_arrow(&self, py: Python<'_>, position_value_start: Option<u64>, position_value_end: Option<u64>) -> PyResult<PyObject> {
+        let row_reader = RowReader::new(self.index.path.clone(), self.index.clone())?;
+        let (position_value_start, position_value_end) = row_reader.translate_bounds(position_value_start, position_value_end)?;
+        let (position_value_start, position_value_end) = row_reader.inner.resolve_bounds(position_value_start, position_value_end);
+        row_reader.inner.check_row_limit(position_value_start, position_value_end)?;
+        let batch_reader = ArrowBlockReader::new(row_reader, position_value_start, position_value_end);
+        let schema = batch_reader.schema();
+
+        let batches = batch_reader.collect::<Result<Vec<_>, _>>().map_err(arrow_error_into_py)?;
+        let batch = concat_batches(&schema, &batches).map_err(arrow_error_into_py)?;
+        let (mut array, mut schema) = to_ffi(&StructArray::from(batch).into_data()).map_err(arrow_error_into_py)?;
+
+        // pyarrow moves the array and the schema out of the pointers, leaving released structs behind that are safe to drop
+        let pyarrow = py.import_bound("pyarrow")?;
+        let res = pyarrow
+            .getattr("RecordBatch")?
+            .call_method1("_import_from_c", (
+                &mut array as *mut FFI_ArrowArray as usize,
+                &mut schema as *mut FFI_ArrowSchema as usize,
+            ))?;
+
+        Ok(res.into())
+    }
+
+    /// Read all rows of the table into a numpy array per column. The blocks are scanned once, appending the cells of
+    /// every row to the column they belong to.
+    /// 
+    /// # Returns
+    /// 
+    /// A dictionary of the name of each column to a `numpy.ndarray`. Integer columns have dtype `int64`, float columns
+    /// have dtype `float64` and string columns have dtype `object`.
+    fn to_arrays(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut row_reader = RowReader::new(self.index.path.clone(), self.index.clone())?;
+        let (position_value_start, position_value_end) = row_reader.inner.resolve_bounds(None, None);
+        let dict = query_column_arrays(py, &mut row_reader.inner, position_value_start, position_value_end)?;
+        Ok(dict.into())
+    }
+
+    /// Query a range of rows as a `pandas.DataFrame`. The cells are collected into a numpy array per column like
+    /// `to_arrays`, so no `Row` is created for every row. pandas is only imported when this is called.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive), or `None` to start at the first row of the table
+    /// * `position_value_end` - The end of the range (exclusive), or `None` to end after the last row of the table
+    /// 
+    /// # Returns
+    /// 
+    /// A `pandas.DataFrame` with a column for every column of the table. Integer columns have dtype `int64`, float columns
+    /// have dtype `float64` and string columns have dtype `object`.
+    #[pyo3(signature = (position_value_start=None, position_value_end=None))]
+    fn query_range_dataframe(&mut self, py: Python<'_>, position_value_start: Option<u64>, position_value_end: Option<u64>) -> PyResult<PyObject> {
+        let (position_value_start, position_value_end) = self.translate_bounds(position_value_start, position_value_end)?;
+        let (position_value_start, position_value_end) = self.inner.resolve_bounds(position_value_start, position_value_end);
+        let dict = query_column_arrays(py, &mut self.inner, position_value_start, position_value_end)?;
+
+        let pandas = py.import_bound("pandas")?;
+        Ok(pandas.call_method1("DataFrame", (dict,))?.into())
+    }
+}
+
+/// Query a range of rows into a dictionary of the name of each column to a numpy array, for `RowReader.to_arrays` and
+/// `RowReader.query_range_dataframe`
+fn query_column_arrays<'py>(
+    py: Python<'py>,
+    row_reader: &mut zygos_db::query::RowReader<BufReader<File>>,
+    position_value_start: u64,
+    position_value_end: u64,
+) -> PyResult<Bound<'py, PyDict>> {
+    let mut columns: Vec<ColumnValues> = row_reader.columns().iter()
+        .map(|column| match column.type_ {
+            ColumnType::Integer | ColumnType::Boolean => ColumnValues::Integer(Vec::new()),
+            ColumnType::Float => ColumnValues::Float(Vec::new()),
+            ColumnType::VolatileString | ColumnType::HashtableString | ColumnType::InternedString | ColumnType::Char => ColumnValues::String(Vec::new()),
+        })
+        .collect();
+
+    let mut type_mismatch = None;
+
+    row_reader.query_range_with(position_value_start, position_value_end, |cells| {
+        for (values, cell) in columns.iter_mut().zip(cells) {
+            match (values, cell) {
+                (ColumnValues::Integer(values), CellRef::Integer(value)) => values.push(*value),
+                (ColumnValues::Float(values), CellRef::Float(value)) => values.push(*value),
+                (ColumnValues::String(values), CellRef::String(value)) => values.push(value.to_string()),
+                (_, cell) => { type_mismatch.get_or_insert(cell.to_cell_value()); },
+            }
+        }
+    })?;
+
+    if let Some(cell) = type_mismatch {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Value {:?} does not match the type of its column", cell)));
+    }
+
+    let numpy = py.import_bound("numpy")?;
+    let dict = PyDict::new_bound(py);
+
+    for (column, values) in row_reader.columns().iter().zip(columns) {
+        // Numeric columns are copied from a buffer of native-endian values, which is much faster than a list
+        let array = match values {
+            ColumnValues::Integer(values) => {
+                let bytes = PyBytes::new_bound(py, &values.iter().flat_map(|value| value.to_ne_bytes()).collect::<Vec<u8>>());
+                numpy.call_method1("frombuffer", (bytes, numpy.getattr("int64")?))?.call_method0("copy")?
+            },
+            ColumnValues::Float(values) => {
+                let bytes = PyBytes::new_bound(py, &values.iter().flat_map(|value| value.to_ne_bytes()).collect::<Vec<u8>>());
+                numpy.call_method1("frombuffer", (bytes, numpy.getattr("float64")?))?.call_method0("copy")?
+            },
+            ColumnValues::String(values) => {
+                numpy.call_method1("array", (PyList::new_bound(py, values), numpy.getattr("object_")?))?
+            },
+        };
+
+        dict.set_item(&column.name, array)?;
+    }
+
+    Ok(dict)
+}
+
+/// The values of a column of a table, collected by `query_column_arrays`.
+enum ColumnValues {
+    Integer(Vec<i64>),
+    Float(Vec<f64>),
+    String(Vec<String>),
+}
+
+/// Lazily reads the blocks of a range and yields their rows, created by `RowReader.iter_range`.
+#[pyclass(module = "zygos_db")]
+struct RowIterator {
+    row_reader: RowReader,
+    blocks: std::vec::IntoIter<BlockBounds>,
+    position_value_start: u64,
+    position_value_end: u64,
+    is_positioned: bool,
+    compressed: Vec<u8>,
+    decompressed: Vec<u8>,
+    /// The rows of the current block that haven't been yielded yet
+    rows: std::vec::IntoIter<Row>,
+}
+
+impl RowIterator {
+    fn new(row_reader: RowReader, position_value_start: u64, position_value_end: u64) -> Self {
+        let blocks = row_reader.inner.get_blocks(position_value_start, position_value_end);
+
+        Self {
+            row_reader,
+            blocks: blocks.into_iter(),
+            position_value_start,
+            position_value_end,
+            is_positioned: false,
+            compressed: Vec::new(),
+            decompressed: Vec::new(),
+            rows: Vec::new().into_iter(),
+        }
+    }
+}
+
+#[pymethods]
+impl RowIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> std::io::Result<Option<Row>> {
+        // Blocks can have no rows in the range, so keep reading until a block has some
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Ok(Some(row));
+            }
+
+            let Some(block) = self.blocks.next() else {
+                return Ok(None);
             };
 
-            self.deserialize_range(
-                &slice,
-                max(start.0, position_value_start),
-                end.0,
-                &mut rows,
-            )?;
+            // The blocks of a range are consecutive, so only the first one has to be seeked to
+            if !self.is_positioned {
+                self.row_reader.inner.seek_to_block(block)?;
+                self.is_positioned = true;
+            }
+
+            let mut rows = Vec::new();
+            self.row_reader.inner.read_block(block, self.position_value_start, self.position_value_end, &mut self.compressed, &mut self.decompressed, &mut rows)?;
+            self.rows = self.row_reader.rows(rows).into_iter();
         }
+    }
+}
 
+/// Lazily reads blocks of a range and converts them to Arrow record batches.
+struct ArrowBlockReader {
+    row_reader: RowReader,
+    blocks: std::vec::IntoIter<BlockBounds>,
+    position_value_start: u64,
+    position_value_end: u64,
+    schema: SchemaRef,
+    is_positioned: bool,
+    compressed: Vec<u8>,
+    decompressed: Vec<u8>,
+}
+
+impl ArrowBlockReader {
+    fn new(row_reader: RowReader, position_value_start: u64, position_value_end: u64) -> Self {
+        let blocks = row_reader.inner.get_blocks(position_value_start, position_value_end);
+        let schema = arrow_schema(row_reader.inner.columns());
+
+        Self {
+            row_reader,
+            blocks: blocks.into_iter(),
+            position_value_start,
+            position_value_end,
+            schema,
+            is_positioned: false,
+            compressed: Vec::new(),
+            decompressed: Vec::new(),
+        }
+    }
+
+    fn read_next_block(&mut self, block: BlockBounds) -> std::io::Result<Vec<Vec<CellValue>>> {
+        if !self.is_positioned {
+            self.row_reader.inner.seek_to_block(block)?;
+            self.is_positioned = true;
+        }
+
+        let mut rows = Vec::new();
+        self.row_reader.inner.read_block(block, self.position_value_start, self.position_value_end, &mut self.compressed, &mut self.decompressed, &mut rows)?;
         Ok(rows)
     }
 }
 
+impl Iterator for ArrowBlockReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block = self.blocks.next()?;
+
+        let res = self.read_next_block(block)
+            .map_err(ArrowError::from)
+            .and_then(|rows| rows_to_record_batch(self.schema.clone(), &rows));
+
+        Some(res)
+    }
+}
+
+impl RecordBatchReader for ArrowBlockReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+fn arrow_schema(columns: &[zygos_db::query::ColumnHeader]) -> SchemaRef {
+    let fields: Vec<Field> = columns.iter().map(|column| {
+        let data_type = match column.type_ {
+            ColumnType::Integer | ColumnType::Boolean => DataType::Int64,
+            ColumnType::Float => DataType::Float64,
+            ColumnType::VolatileString | ColumnType::HashtableString | ColumnType::InternedString | ColumnType::Char => DataType::LargeUtf8,
+        };
+
+        Field::new(&column.name, data_type, false)
+    }).collect();
+
+    Arc::new(Schema::new(fields))
+}
+
+fn rows_to_record_batch(schema: SchemaRef, rows: &[Vec<CellValue>]) -> Result<RecordBatch, ArrowError> {
+    let type_mismatch = |field: &Field, cell: &CellValue| ArrowError::InvalidArgumentError(format!(
+        "Value {:?} does not match the type {} of column '{}'",
+        cell, field.data_type(), field.name(),
+    ));
+
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+
+    for (i, field) in schema.fields().iter().enumerate() {
+        let array: ArrayRef = match field.data_type() {
+            DataType::Int64 => {
+                let mut builder = Int64Builder::with_capacity(rows.len());
+                for row in rows {
+                    match &row[i] {
+                        CellValue::Integer(value) => builder.append_value(*value),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+            DataType::Float64 => {
+                let mut builder = Float64Builder::with_capacity(rows.len());
+                for row in rows {
+                    match &row[i] {
+                        CellValue::Float(value) => builder.append_value(*value),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+            _ => {
+                let mut builder = LargeStringBuilder::new();
+                for row in rows {
+                    match &row[i] {
+                        CellValue::String(value) => builder.append_value(value),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+        };
+
+        arrays.push(array);
+    }
+
+    RecordBatch::try_new(schema, arrays)
+}
+
 fn divide_into_parts<I, T>(mut iter: I, num_parts: usize, len: usize) -> Vec<Vec<T>>
 where
     I: Iterator<Item = T>,
@@ -500,44 +1244,69 @@ where
     result
 }
 
-#[pyclass]
+#[pyclass(module = "zygos_db")]
 struct ParallelRowReader {
     #[allow(dead_code)]
     index: TableIndex,
     row_readers: Vec<RowReader>,
+    /// Ranges with fewer blocks than this are read by a single reader
+    #[pyo3(get, set)]
+    min_parallel_blocks: usize,
+}
+
+const DEFAULT_MIN_PARALLEL_BLOCKS: usize = 2;
+
+impl ParallelRowReader {
+    /// The number of readers that read a range of blocks in parallel, which is 1 if a single reader reads the range
+    fn num_parallel_readers(&self, num_blocks: usize) -> usize {
+        if num_blocks < self.min_parallel_blocks {
+            1
+        } else {
+            num_blocks.min(self.row_readers.len()).max(1)
+        }
+    }
 }
 
 #[pymethods]
 impl ParallelRowReader {
-    fn query_range(&mut self, py: Python<'_>, position_value_start: u64, position_value_end: u64) -> std::io::Result<PyObject> {
-        let mut range: Vec<(u64, u64)> = self.index.get_range(position_value_start, position_value_end)?;
-        if range.is_empty() {
+    #[pyo3(signature = (position_value_start=None, position_value_end=None))]
+    fn query_range(&mut self, py: Python<'_>, position_value_start: Option<u64>, position_value_end: Option<u64>) -> std::io::Result<PyObject> {
+        let (position_value_start, position_value_end) = self.row_readers[0].translate_bounds(position_value_start, position_value_end)?;
+        let (position_value_start, position_value_end) = self.row_readers[0].inner.resolve_bounds(position_value_start, position_value_end);
+        self.row_readers[0].inner.check_row_limit(position_value_start, position_value_end)?;
+        let blocks = self.row_readers[0].inner.get_blocks(position_value_start, position_value_end);
+        if blocks.is_empty() {
             return Ok(PyList::empty_bound(py).into());
         }
 
-        let range_len = range.len();
-
-        // Append the end of the index to the range
-        range.push((position_value_end, self.index.inner.index_start_offset));
-
-        let blocks = range.windows(2).map(|window| {
-            let [start, end] = window else { unreachable!() };
-            (start, end)
-        });
+        let num_blocks = blocks.len();
+        if self.num_parallel_readers(num_blocks) == 1 {
+            let rows = self.row_readers[0].inner.query_range(position_value_start, position_value_end)?;
+            let len = rows.len();
+            let mut rows = self.row_readers[0].rows(rows).into_iter().map(|row| row.into_py(py));
+            return Ok(new_from_iter(py, len, &mut rows).into());
+        }
 
-        let block_jobs = divide_into_parts(blocks, self.row_readers.len(), range_len);
+        let block_jobs = divide_into_parts(blocks.into_iter(), self.row_readers.len(), num_blocks);
         let num_non_empty_blocks = block_jobs.iter().filter(|blocks| !blocks.is_empty()).count();
 
-        let res = self.row_readers[..num_non_empty_blocks].par_iter_mut().enumerate().map(|(i, reader)| {
+        #[cfg(feature = "parallel")]
+        let row_readers = self.row_readers[..num_non_empty_blocks].par_iter_mut();
+        #[cfg(not(feature = "parallel"))]
+        let row_readers = self.row_readers[..num_non_empty_blocks].iter_mut();
+
+        let res = row_readers.enumerate().map(|(i, reader)| {
             let blocks = &block_jobs[i];
             if blocks.is_empty() {
                 return Ok(Vec::new());
             }
 
-            let (position_value_start, _) = blocks.first().unwrap().0;
-            let (position_value_end, _) = blocks.last().unwrap().1;
-            reader.query_range(*position_value_start, *position_value_end)
-        }).collect::<Result<Vec<_>, _>>()?;
+            // The first block can start before the queried range, and the last block can end after it
+            let (part_start, _) = blocks.first().unwrap().0;
+            let (part_end, _) = blocks.last().unwrap().1;
+            let rows = reader.inner.query_range(max(part_start, position_value_start), min(part_end, position_value_end))?;
+            Ok(reader.rows(rows))
+        }).collect::<std::io::Result<Vec<Vec<Row>>>>()?;
 
         let len = res.iter().map(Vec::len).sum();
         let flattened = res
@@ -548,27 +1317,51 @@ impl ParallelRowReader {
     }
 }
 
-#[derive(Clone, Debug)]
-enum CellValue {
-    I64(i64),
-    F64(f64),
-    String(String),
-}
-
-impl IntoPy<PyObject> for CellValue {
-    fn into_py(self, py: Python) -> PyObject {
-        match self {
-            CellValue::I64(i) => i.into_py(py),
-            CellValue::F64(f) => f.into_py(py),
-            CellValue::String(s) => s.into_py(py),
-        }
+fn check_coordinate_base(coordinate_base: Option<u8>) -> PyResult<()> {
+    match coordinate_base {
+        Some(coordinate_base) if coordinate_base > 1 => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Coordinate base must be 0 or 1, got {}", coordinate_base,
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Convert an error of the database into a `ValueError` if it was caused by the arguments, or an `IOError` otherwise
+fn io_error_into_py(e: std::io::Error) -> PyErr {
+    match e.kind() {
+        std::io::ErrorKind::InvalidInput => PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()),
+        _ => PyErr::new::<pyo3::exceptions::PyIOError, _>(e),
+    }
+}
+
+/// Convert an error of building Arrow arrays into the error of the database that caused it, or a `ValueError` otherwise
+fn arrow_error_into_py(e: ArrowError) -> PyErr {
+    match e {
+        ArrowError::IoError(_, e) => io_error_into_py(e),
+        e => PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()),
     }
 }
 
-#[pyclass]
+fn cell_value_into_py(cell: CellValue, py: Python) -> PyObject {
+    match cell {
+        CellValue::Integer(i) => i.into_py(py),
+        CellValue::Float(f) => f.into_py(py),
+        CellValue::String(s) => s.into_py(py),
+    }
+}
+
+#[pyclass(module = "zygos_db")]
 #[derive(Clone, Debug)]
 struct Row {
     cells: Vec<CellValue>,
+    /// The names of the columns of the cells, shared by all rows of a query
+    column_names: Arc<[String]>,
+}
+
+impl Row {
+    fn new(cells: Vec<CellValue>, column_names: &Arc<[String]>) -> Self {
+        Self { cells, column_names: column_names.clone() }
+    }
 }
 
 #[pymethods]
@@ -577,14 +1370,32 @@ impl Row {
         Ok(format!("{:?}", self.cells))
     }
 
-    fn get(&self, index: usize) -> PyResult<CellValue> {
+    fn get(&self, py: Python<'_>, index: usize) -> PyResult<PyObject> {
         self.cells.get(index)
             .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyIndexError, _>(format!("Index out of bounds: {}", index)))
-            .cloned()
+            .map(|cell| cell_value_into_py(cell.clone(), py))
+    }
+
+    fn __getitem__(&self, py: Python<'_>, index: usize) -> PyResult<PyObject> {
+        self.get(py, index)
+    }
+
+    /// Get a cell by the name of its column. Raises a `KeyError` if the row has no column with that name.
+    fn get_by_name(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        self.column_names.iter()
+            .position(|column_name| column_name == name)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("Column not found: {}", name)))
+            .map(|index| cell_value_into_py(self.cells[index].clone(), py))
     }
 
-    fn __getitem__(&self, index: usize) -> PyResult<CellValue> {
-        self.get(index)
+    /// The cells of the row in a dictionary by the names of their columns
+    fn as_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let dict = PyDict::new_bound(py);
+        for (name, cell) in self.column_names.iter().zip(&self.cells) {
+            dict.set_item(name, cell_value_into_py(cell.clone(), py))?;
+        }
+
+        Ok(dict.into())
     }
 
     fn len(&self) -> usize {
@@ -597,5 +1408,77 @@ impl Row {
 #[pyo3(name = "zygos_db")]
 fn register_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<DatabaseQueryClient>()?;
+    m.add_class::<DatabaseHeader>()?;
+    m.add_class::<DatabaseSummary>()?;
+    m.add_class::<DatasetHeader>()?;
+    m.add_class::<ColumnHeader>()?;
+    m.add_class::<TableHeader>()?;
+    m.add_class::<TableDescription>()?;
+    m.add_class::<TableIndex>()?;
+    m.add_class::<RowReader>()?;
+    m.add_class::<ParallelRowReader>()?;
+    m.add_class::<RowIterator>()?;
+    m.add_class::<Row>()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+
+    /// Create a reader of a table with only a position column, of which the blocks start at the offsets in the index
+    fn row_reader(blocks: &[(u64, u64)]) -> RowReader {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+        let index = TableIndex {
+            inner: Arc::new(zygos_db::query::TableIndex {
+                inner: BTreeMap::from_iter(blocks.iter().copied()),
+                compressed_block_sizes: HashMap::new(),
+                block_row_counts: HashMap::new(),
+                block_checksums: HashMap::new(),
+                chromosome: 1,
+                max_position: blocks.last().map_or(0, |(position, _)| *position),
+                index_start_offset: 1000,
+                index_end_offset: 1100,
+                end_index: None,
+            }),
+            dataset_name: "scores".to_string(),
+            chromosome: 1,
+            column_names: Arc::from(vec!["pos".to_string()]),
+            dataset: zygos_db::query::DatasetHeader {
+                name: "scores".to_string(),
+                compression_algorithm: CompressionAlgorithm::None,
+                rows_per_index: 1,
+                position_decimals: None,
+                coordinate_base: None,
+                columnar: false,
+                columns: vec![zygos_db::query::ColumnHeader { type_: ColumnType::Integer, name: "pos".to_string() }],
+                tables: Vec::new(),
+            },
+            path: path.clone(),
+            max_rows: None,
+            max_decompressed_size: None,
+            coordinate_base: None,
+        };
+
+        RowReader::new(path, index).unwrap()
+    }
+
+    #[test]
+    fn small_range_bypasses_the_parallel_readers() {
+        let blocks = [(0, 0), (10, 100), (20, 200), (30, 300), (40, 400), (50, 500)];
+        let reader = ParallelRowReader {
+            index: row_reader(&blocks).index,
+            row_readers: (0..4).map(|_| row_reader(&blocks)).collect(),
+            min_parallel_blocks: 3,
+        };
+        let num_parallel_readers = |start, end| reader.num_parallel_readers(reader.row_readers[0].inner.get_blocks(start, end).len());
+
+        assert_eq!(num_parallel_readers(0, 5), 1);
+        assert_eq!(num_parallel_readers(15, 25), 1);
+        assert_eq!(num_parallel_readers(15, 35), 3);
+        assert_eq!(num_parallel_readers(0, 60), 4);
+    }
+}
@@ -0,0 +1,57 @@
+"""Helpers for tests that build small databases with the ZygosDB CLI in a temporary directory."""
+
+import os
+import shlex
+import subprocess
+import tempfile
+from pathlib import Path
+
+ROOT = Path(__file__).resolve().parents[2]
+
+# The command that runs the ZygosDB CLI. Builds and runs it with cargo, unless set in the environment.
+CLI = shlex.split(os.environ.get("ZYGOS_DB_CLI", f"cargo run --quiet --release --manifest-path {ROOT / 'Cargo.toml'} --"))
+
+
+class TestDatabase:
+    """A database that was built in a temporary directory. The directory is removed when the database is closed."""
+
+    def __init__(self, config: str, files: dict[str, str], build_args: tuple[str, ...] = ()):
+        self.dir = tempfile.TemporaryDirectory()
+        self.path = os.path.join(self.dir.name, "test.zygosdb")
+
+        for name, content in files.items():
+            with open(os.path.join(self.dir.name, name), "w") as f:
+                f.write(content)
+
+        config_path = os.path.join(self.dir.name, "config.toml")
+        with open(config_path, "w") as f:
+            f.write(config)
+
+        build = subprocess.run(CLI + ["build", config_path, "--output", self.path, *build_args], check=True, capture_output=True, text=True)
+        self.stdout = build.stdout
+        self.stderr = build.stderr
+
+    def close(self):
+        self.dir.cleanup()
+
+
+# A dataset of 500 rows in blocks of 4 rows on chromosome 1, with a position, a float and a string column
+SCORES_CONFIG = """
+[datasets.scores]
+file_per_chromosome = true
+chromosomes = [1]
+path = "scores_chr{chromosome}.tsv"
+rows_per_index = 4
+compression_algorithm = "gzip"
+columns = [
+    { name = "pos", type = "integer", role = "position" },
+    { name = "score", type = "float" },
+    { name = "gene", type = "volatile-string" },
+]
+"""
+
+SCORES = [(i * 7, i / 4, f"GENE{i % 13}") for i in range(500)]
+
+SCORES_FILES = {
+    "scores_chr1.tsv": "pos\tscore\tgene\n" + "".join(f"{pos}\t{score}\t{gene}\n" for pos, score, gene in SCORES),
+}
@@ -0,0 +1,27 @@
+import unittest
+
+from zygos_db import DatabaseQueryClient
+
+from database import SCORES_CONFIG, SCORES_FILES, TestDatabase
+
+
+class TestAssembly(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.database = TestDatabase('assembly = "GRCh38"\n' + SCORES_CONFIG, SCORES_FILES)
+
+    @classmethod
+    def tearDownClass(cls):
+        cls.database.close()
+
+    def test_matching_assembly_opens_the_database(self):
+        client = DatabaseQueryClient(self.database.path, assembly="GRCh38")
+        self.assertEqual(client.header.assembly, "GRCh38")
+
+    def test_mismatched_assembly_is_rejected(self):
+        with self.assertRaisesRegex(ValueError, "built for assembly 'GRCh38', but 'GRCh37' was expected"):
+            DatabaseQueryClient(self.database.path, assembly="GRCh37")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,53 @@
+import json
+import unittest
+
+from database import SCORES, TestDatabase
+
+CONFIG = """
+[datasets.scores]
+file_per_chromosome = true
+chromosomes = [1, 2]
+path = "scores_chr{chromosome}.tsv"
+rows_per_index = 4
+compression_algorithm = "gzip"
+columns = [
+    { name = "pos", type = "integer", role = "position" },
+    { name = "score", type = "float" },
+    { name = "gene", type = "volatile-string" },
+]
+"""
+
+FILES = {
+    f"scores_chr{chromosome}.tsv": "pos\tscore\tgene\n" + "".join(f"{pos}\t{score}\t{gene}\n" for pos, score, gene in SCORES[:num_rows])
+    for chromosome, num_rows in [(1, 500), (2, 100)]
+}
+
+
+class TestProgressJson(unittest.TestCase):
+    def test_every_table_prints_a_json_line_to_stderr(self):
+        database = TestDatabase(CONFIG, FILES, ("--progress-json",))
+        self.addCleanup(database.close)
+
+        lines = [json.loads(line) for line in database.stderr.splitlines()]
+        self.assertEqual([(line["dataset"], line["chromosome"], line["rows"], line["blocks"]) for line in lines], [
+            ("scores", 1, 500, 125),
+            ("scores", 2, 100, 25),
+        ])
+
+        for line in lines:
+            self.assertEqual(set(line), {"dataset", "chromosome", "rows", "blocks", "bytes", "elapsed_ms"})
+            self.assertGreater(line["bytes"], 0)
+            self.assertGreaterEqual(line["elapsed_ms"], 0)
+
+        self.assertNotIn("compressed from", database.stdout)
+
+    def test_blocks_are_printed_without_the_flag(self):
+        database = TestDatabase(CONFIG, FILES)
+        self.addCleanup(database.close)
+
+        self.assertEqual(database.stderr, "")
+        self.assertIn("Block 0 (4 rows) compressed from", database.stdout)
+
+
+if __name__ == "__main__":
+    unittest.main()
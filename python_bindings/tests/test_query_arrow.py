@@ -0,0 +1,63 @@
+import unittest
+
+from zygos_db import DatabaseQueryClient
+
+from database import SCORES, SCORES_CONFIG, SCORES_FILES, TestDatabase
+
+try:
+    import polars
+    import pyarrow
+except ImportError:
+    polars = pyarrow = None
+
+
+@unittest.skipIf(polars is None, "polars and pyarrow are required to read Arrow data")
+class TestQueryArrow(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.database = TestDatabase(SCORES_CONFIG, SCORES_FILES)
+        cls.client = DatabaseQueryClient(cls.database.path)
+
+    @classmethod
+    def tearDownClass(cls):
+        cls.database.close()
+
+    def test_query_arrow_reads_into_polars(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        df = polars.from_arrow(reader.query_arrow(84, 896).read_all())
+
+        self.assertEqual(df.columns, ["pos", "score", "gene"])
+        self.assertEqual(df.rows(), [row for row in SCORES if 84 <= row[0] < 896])
+        self.assertEqual(df.rows(), [(row[0], row[1], row[2]) for row in reader.query_range(84, 896)])
+
+    def test_query_arrow_streams_one_batch_per_block(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        batches = list(reader.query_arrow(0, 3500))
+
+        self.assertEqual(len(batches), 125)
+        self.assertTrue(all(batch.num_rows == 4 for batch in batches))
+        self.assertEqual(batches[0].schema.field("pos").type, pyarrow.int64())
+
+    def test_query_range_arrow_returns_one_record_batch(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        batch = reader.query_range_arrow(84, 896)
+
+        self.assertIsInstance(batch, pyarrow.RecordBatch)
+        self.assertEqual(
+            [field.type for field in batch.schema],
+            [pyarrow.int64(), pyarrow.float64(), pyarrow.large_utf8()],
+        )
+        self.assertEqual(polars.from_arrow(batch).rows(), [row for row in SCORES if 84 <= row[0] < 896])
+        self.assertEqual(reader.query_range_arrow(10_000, 20_000).num_rows, 0)
+
+    def test_query_arrow_outside_table_is_empty(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        self.assertEqual(reader.query_arrow(10_000, 20_000).read_all().num_rows, 0)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,165 @@
+import unittest
+
+from zygos_db import DatabaseQueryClient
+
+from database import SCORES, SCORES_CONFIG, SCORES_FILES, TestDatabase
+
+
+class TestQueryRange(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.database = TestDatabase(SCORES_CONFIG, SCORES_FILES)
+        cls.client = DatabaseQueryClient(cls.database.path)
+
+    @classmethod
+    def tearDownClass(cls):
+        cls.database.close()
+
+    def query_range(self, start, end, strict_bounds=False):
+        reader = self.client.read_table_index("scores", 1).create_query()
+        reader.strict_bounds = strict_bounds
+        return [(row[0], row[1], row[2]) for row in reader.query_range(start, end)]
+
+    def test_end_of_range_is_exclusive(self):
+        self.assertEqual(self.query_range(7, 21), [row for row in SCORES if 7 <= row[0] < 21])
+        self.assertEqual(self.query_range(7, 22), [row for row in SCORES if 7 <= row[0] < 22])
+
+    def test_range_near_block_boundary_returns_no_rows_outside_the_range(self):
+        # Blocks hold 4 rows 7 positions apart, so every block starts at a multiple of 28
+        for strict_bounds in [False, True]:
+            for start, end in [(27, 28), (28, 29), (21, 28), (22, 56), (28, 57), (3486, 3500)]:
+                with self.subTest(start=start, end=end, strict_bounds=strict_bounds):
+                    rows = self.query_range(start, end, strict_bounds)
+                    self.assertEqual(rows, [row for row in SCORES if start <= row[0] < end])
+
+    def test_unbounded_sides_of_range_resolve_to_the_table_bounds(self):
+        self.assertEqual(self.query_range(None, 30), [row for row in SCORES if row[0] < 30])
+        self.assertEqual(self.query_range(3470, None), [row for row in SCORES if row[0] >= 3470])
+        self.assertEqual(self.query_range(None, None), SCORES)
+
+        reader = self.client.read_table_index("scores", 1).create_query()
+        self.assertEqual(len(reader.query_range()), len(SCORES))
+
+    def test_grouped_range_matches_grouping_the_rows_afterwards(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+        groups = reader.query_range_grouped(30, 400, "gene")
+
+        expected = {}
+        for row in SCORES:
+            if 30 <= row[0] < 400:
+                expected.setdefault(row[2], []).append(row)
+
+        self.assertEqual(list(groups), list(expected))
+        self.assertEqual({gene: [(row[0], row[1], row[2]) for row in rows] for gene, rows in groups.items()}, expected)
+
+        with self.assertRaises(ValueError):
+            reader.query_range_grouped(30, 400, "variant")
+
+    def test_parallel_reader_returns_the_rows_of_small_and_large_ranges(self):
+        reader = self.client.read_table_index("scores", 1).create_query_parallel(num_threads=4, min_parallel_blocks=3)
+
+        # 1 block is read by a single reader, 100 blocks by all readers
+        for start, end in [(28, 40), (0, 2800)]:
+            with self.subTest(start=start, end=end):
+                rows = [(row[0], row[1], row[2]) for row in reader.query_range(start, end)]
+                self.assertEqual(rows, [row for row in SCORES if start <= row[0] < end])
+
+    def test_range_exceeding_max_rows_is_rejected(self):
+        client = DatabaseQueryClient(self.database.path, max_rows=40)
+        reader = client.read_table_index("scores", 1).create_query()
+
+        self.assertEqual(len(reader.query_range(0, 280)), 40)
+        with self.assertRaisesRegex(OSError, "exceeds the limit of 40 rows"):
+            reader.query_range(0, 281)
+
+    def test_block_exceeding_max_decompressed_size_is_rejected(self):
+        client = DatabaseQueryClient(self.database.path, max_decompressed_size=16)
+        reader = client.read_table_index("scores", 1).create_query()
+
+        with self.assertRaisesRegex(OSError, "exceeds the limit of 16 bytes"):
+            reader.query_range(0, 28)
+
+        client.max_decompressed_size = 4096
+        reader = client.read_table_index("scores", 1).create_query()
+        self.assertEqual(len(reader.query_range(0, 28)), 4)
+
+    def test_index_repr_shows_the_offsets_and_max_position(self):
+        index = self.client.read_table_index("scores", 1)
+
+        self.assertEqual(index.max_position, SCORES[-1][0])
+        self.assertLess(index.index_start_offset, index.index_end_offset)
+        self.assertIn(f"max_position: {index.max_position}", repr(index))
+        self.assertIn(f"index_start_offset: {index.index_start_offset}", repr(index))
+        self.assertIn(f"index_end_offset: {index.index_end_offset}", repr(index))
+
+
+    def test_reading_ahead_returns_the_same_rows(self):
+        reader = self.client.read_table_index("scores", 1).create_query(read_ahead=True)
+        self.assertTrue(reader.read_ahead)
+
+        rows = [(row[0], row[1], row[2]) for row in reader.query_range(30, 2000)]
+        self.assertEqual(rows, [row for row in SCORES if 30 <= row[0] < 2000])
+
+    def test_cells_of_rows_are_found_by_column_name(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+        row = reader.query_range(7, 8)[0]
+
+        self.assertEqual(row.get_by_name("gene"), SCORES[1][2])
+        self.assertEqual(row.as_dict(), {"pos": SCORES[1][0], "score": SCORES[1][1], "gene": SCORES[1][2]})
+        with self.assertRaises(KeyError):
+            row.get_by_name("variant")
+
+        projected = reader.query_range_projected(7, 8, ["gene", "pos"])[0]
+        self.assertEqual(projected.as_dict(), {"gene": SCORES[1][2], "pos": SCORES[1][0]})
+        with self.assertRaises(KeyError):
+            projected.get_by_name("score")
+
+    def test_coerced_column_is_returned_as_the_new_type(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        reader.set_column_coercion("score", "string")
+        self.assertEqual(reader.query_range(35, 36)[0][1], "1.25")
+        reader.set_column_coercion("score", None)
+        self.assertEqual(reader.query_range(35, 36)[0][1], 1.25)
+
+        for column, coercion in [("pos", "float"), ("gene", "float"), ("score", "integer"), ("variant", "string")]:
+            with self.subTest(column=column, coercion=coercion):
+                with self.assertRaises(ValueError):
+                    reader.set_column_coercion(column, coercion)
+
+    def test_compression_algorithm_is_read_from_the_file(self):
+        self.assertEqual(self.client.compression_algorithm("scores"), "Gzip")
+        with self.assertRaises(ValueError):
+            self.client.compression_algorithm("variants")
+
+    def test_iterating_a_range_yields_the_rows_of_querying_it(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        for start, end in [(7, 21), (27, 28), (30, 2000), (None, None)]:
+            with self.subTest(start=start, end=end):
+                rows = [(row[0], row[1], row[2]) for row in reader.iter_range(start, end)]
+                self.assertEqual(rows, [(row[0], row[1], row[2]) for row in reader.query_range(start, end)])
+
+    def test_iteration_can_stop_early(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        rows = reader.iter_range(30, None)
+        self.assertEqual([next(rows)[0] for _ in range(2)], [35, 42])
+
+        for row in rows:
+            if row[0] >= 70:
+                break
+        self.assertEqual(next(rows)[0], 77)
+
+    def test_row_at_reads_the_nth_row_of_the_table(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        for ordinal in [0, 6, len(SCORES) - 1]:
+            row = reader.row_at(ordinal)
+            self.assertEqual((row[0], row[1], row[2]), SCORES[ordinal])
+        with self.assertRaises(IndexError):
+            reader.row_at(len(SCORES))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,43 @@
+import unittest
+
+from zygos_db import DatabaseQueryClient
+
+from database import SCORES, SCORES_CONFIG, SCORES_FILES, TestDatabase
+
+try:
+    import pandas
+except ImportError:
+    pandas = None
+
+
+@unittest.skipIf(pandas is None, "pandas is required to query a range as a DataFrame")
+class TestQueryRangeDataFrame(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.database = TestDatabase(SCORES_CONFIG, SCORES_FILES)
+        cls.client = DatabaseQueryClient(cls.database.path)
+
+    @classmethod
+    def tearDownClass(cls):
+        cls.database.close()
+
+    def test_dataframe_matches_the_rows_of_the_range(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        frame = reader.query_range_dataframe(30, 400)
+
+        self.assertEqual(list(frame.columns), ["pos", "score", "gene"])
+        self.assertEqual(frame["pos"].dtype, "int64")
+        self.assertEqual(frame["score"].dtype, "float64")
+        self.assertEqual(frame["gene"].dtype, object)
+        self.assertEqual(list(frame.itertuples(index=False, name=None)), [row for row in SCORES if 30 <= row[0] < 400])
+
+    def test_unbounded_range_returns_the_whole_table(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        self.assertEqual(len(reader.query_range_dataframe()), len(SCORES))
+        self.assertEqual(len(reader.query_range_dataframe(3486, 3500)), 2)
+
+
+if __name__ == "__main__":
+    unittest.main()
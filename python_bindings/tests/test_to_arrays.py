@@ -0,0 +1,41 @@
+import unittest
+
+from zygos_db import DatabaseQueryClient
+
+from database import SCORES, SCORES_CONFIG, SCORES_FILES, TestDatabase
+
+try:
+    import numpy
+except ImportError:
+    numpy = None
+
+
+@unittest.skipIf(numpy is None, "numpy is required to read a table into arrays")
+class TestToArrays(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.database = TestDatabase(SCORES_CONFIG, SCORES_FILES)
+        cls.client = DatabaseQueryClient(cls.database.path)
+
+    @classmethod
+    def tearDownClass(cls):
+        cls.database.close()
+
+    def test_arrays_match_reading_the_rows(self):
+        reader = self.client.read_table_index("scores", 1).create_query()
+
+        arrays = reader.to_arrays()
+        rows = reader.query_range()
+
+        self.assertEqual(list(arrays), ["pos", "score", "gene"])
+        self.assertEqual(arrays["pos"].dtype, numpy.int64)
+        self.assertEqual(arrays["score"].dtype, numpy.float64)
+        self.assertEqual(arrays["gene"].dtype, numpy.object_)
+
+        for i, name in enumerate(["pos", "score", "gene"]):
+            self.assertEqual(arrays[name].tolist(), [row[i] for row in rows])
+        self.assertEqual(list(zip(*(arrays[name].tolist() for name in arrays))), SCORES)
+
+
+if __name__ == "__main__":
+    unittest.main()
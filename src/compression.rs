@@ -1,14 +1,33 @@
-use std::io::{Read, Write};
+use std::{hash::Hasher, io::{Cursor, Error, ErrorKind, Read, Write}};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+/// Every byte of an LZ4 block decompresses into at most 255 bytes, so a larger stored length can only come from a corrupt
+/// block and is rejected before the buffer is allocated.
+const MAX_LZ4_BLOCK_RATIO: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum CompressionAlgorithm {
     None = 0,
     Gzip = 1,
     #[serde(rename = "lz4")]
     LZ4 = 2,
+    Zstd = 3,
+    /// LZ4 block format, which has less overhead than the frame format of `LZ4` for small blocks. The uncompressed length
+    /// is stored as a vint64 before the block, so it can be decompressed into a buffer of the exact size.
+    Lz4Block = 4,
+}
+
+impl CompressionAlgorithm {
+    /// Every algorithm, in the order of their ids
+    pub const ALL: [CompressionAlgorithm; 5] = [
+        CompressionAlgorithm::None,
+        CompressionAlgorithm::Gzip,
+        CompressionAlgorithm::LZ4,
+        CompressionAlgorithm::Zstd,
+        CompressionAlgorithm::Lz4Block,
+    ];
 }
 
 impl Default for CompressionAlgorithm {
@@ -25,11 +44,36 @@ impl TryFrom<u8> for CompressionAlgorithm {
             0 => Ok(CompressionAlgorithm::None),
             1 => Ok(CompressionAlgorithm::Gzip),
             2 => Ok(CompressionAlgorithm::LZ4),
+            3 => Ok(CompressionAlgorithm::Zstd),
+            4 => Ok(CompressionAlgorithm::Lz4Block),
             _ => Err(()),
         }
     }
 }
 
+/// The CRC32 checksum of the compressed bytes of a block, which is stored in its index entry
+pub fn block_checksum(compressed: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(compressed);
+    crc.sum()
+}
+
+/// The 64-bit xxHash of the blocks and index of a table, which is stored after its index. The region is read in chunks,
+/// so a table doesn't have to fit in memory to be checked.
+pub fn table_checksum(mut region: impl Read) -> std::io::Result<u64> {
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    let mut buf = [0; 64 * 1024];
+
+    loop {
+        match region.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finish()),
+            Ok(len) => hasher.write(&buf[..len]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 pub struct RowCompressor {
     pub(crate) buffer: Vec<u8>,
 }
@@ -41,29 +85,69 @@ impl RowCompressor {
         }
     }
 
-    pub fn compress(&mut self, algorithm: CompressionAlgorithm, bytes: &mut impl Write) -> std::io::Result<usize> {
+    /// Compress the buffer with an algorithm and write it. Returns the number of compressed bytes written.
+    /// 
+    /// The level is used by gzip, where it defaults to the best compression, and by zstd, where it defaults to zstd's default level.
+    pub fn compress(&mut self, algorithm: CompressionAlgorithm, level: Option<u32>, bytes: &mut impl Write) -> std::io::Result<usize> {
+        let mut writer = CountingWriter { inner: bytes, count: 0 };
+
         match algorithm {
             CompressionAlgorithm::None => {
-                bytes.write(&self.buffer)
+                writer.write_all(&self.buffer)?;
             }
             CompressionAlgorithm::Gzip => {
-                let mut encoder = flate2::write::GzEncoder::new(bytes, flate2::Compression::best());
+                let mut encoder = flate2::write::GzEncoder::new(
+                    &mut writer,
+                    level.map_or(flate2::Compression::best(), flate2::Compression::new),
+                );
                 encoder.write_all(&self.buffer)?;
                 encoder.try_finish()?;
-                Ok(self.buffer.len())
             }
             CompressionAlgorithm::LZ4 => {
-                let mut encoder = lz4::EncoderBuilder::new().level(9).build(bytes)?;
+                let mut encoder = lz4::EncoderBuilder::new().level(9).build(&mut writer)?;
                 encoder.write_all(&self.buffer)?;
                 encoder.finish().1?;
-                Ok(self.buffer.len())
+            }
+            CompressionAlgorithm::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(
+                    &mut writer,
+                    level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |level| level as i32),
+                )?;
+                encoder.write_all(&self.buffer)?;
+                encoder.finish()?;
+            }
+            CompressionAlgorithm::Lz4Block => {
+                writer.write_all(vint64::encode(self.buffer.len() as u64).as_ref())?;
+                writer.write_all(&lz4_flex::block::compress(&self.buffer))?;
             }
         }
+
+        Ok(writer.count)
+    }
+}
+
+/// Counts the bytes written to the inner writer
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: usize,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
     }
 }
 
 pub struct RowDecompressor {
     algorithm: CompressionAlgorithm,
+    max_buffer_capacity: Option<usize>,
+    max_decompressed_size: Option<u64>,
 }
 
 #[allow(dead_code)]
@@ -71,26 +155,209 @@ impl RowDecompressor {
     pub fn new(algorithm: CompressionAlgorithm) -> Self {
         Self {
             algorithm,
+            max_buffer_capacity: None,
+            max_decompressed_size: None,
+        }
+    }
+
+    /// Create a decompressor that shrinks the buffer back to `max_buffer_capacity` bytes before decompressing into it, if
+    /// a previous block made it grow larger. Without a cap, the buffer keeps the capacity of the largest block.
+    pub fn with_max_buffer_capacity(algorithm: CompressionAlgorithm, max_buffer_capacity: Option<usize>) -> Self {
+        Self {
+            algorithm,
+            max_buffer_capacity,
+            max_decompressed_size: None,
+        }
+    }
+
+    /// Fail with an `InvalidData` error instead of decompressing a block into more than `max_decompressed_size` bytes,
+    /// so a corrupted or malicious block can't exhaust the memory
+    pub fn set_max_decompressed_size(&mut self, max_decompressed_size: Option<u64>) {
+        self.max_decompressed_size = max_decompressed_size;
+    }
+
+    pub fn max_decompressed_size(&self) -> Option<u64> {
+        self.max_decompressed_size
+    }
+
+    fn check_decompressed_size(&self, size: u64) -> std::io::Result<()> {
+        match self.max_decompressed_size {
+            Some(max_decompressed_size) if size > max_decompressed_size => Err(Error::new(ErrorKind::InvalidData, format!(
+                "The decompressed block exceeds the limit of {} bytes", max_decompressed_size,
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the decoder to the end, stopping one byte past the limit to detect blocks that exceed it
+    fn read_limited(&self, mut decoder: impl Read, buffer: &mut Vec<u8>) -> std::io::Result<()> {
+        match self.max_decompressed_size {
+            Some(max_decompressed_size) => {
+                let len = decoder.take(max_decompressed_size.saturating_add(1)).read_to_end(buffer)?;
+                self.check_decompressed_size(len as u64)
+            }
+            None => {
+                decoder.read_to_end(buffer)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Clear the buffer for the next block, shrinking it if it grew past the cap
+    fn reset_buffer(&self, buffer: &mut Vec<u8>) {
+        buffer.clear();
+
+        if let Some(max_buffer_capacity) = self.max_buffer_capacity {
+            if buffer.capacity() > max_buffer_capacity {
+                buffer.shrink_to(max_buffer_capacity);
+            }
         }
     }
 
     pub fn decompress<'a>(&self, bytes: &'a [u8], buffer: &'a mut Vec<u8>) -> std::io::Result<&'a [u8]> {
         match self.algorithm {
             CompressionAlgorithm::None => {
+                self.check_decompressed_size(bytes.len() as u64)?;
                 Ok(bytes)
             }
             CompressionAlgorithm::Gzip => {
-                let mut decoder = flate2::read::GzDecoder::new(bytes);
-                buffer.clear();
-                decoder.read_to_end(buffer)?;
+                let decoder = flate2::read::GzDecoder::new(bytes);
+                self.reset_buffer(buffer);
+                self.read_limited(decoder, buffer)?;
                 Ok(buffer.as_slice())
             }
             CompressionAlgorithm::LZ4 => {
-                let mut decoder = lz4::Decoder::new(bytes)?;
-                buffer.clear();
-                decoder.read_to_end(buffer)?;
+                let decoder = lz4::Decoder::new(bytes)?;
+                self.reset_buffer(buffer);
+                self.read_limited(decoder, buffer)?;
+                Ok(buffer.as_slice())
+            }
+            CompressionAlgorithm::Zstd => {
+                let decoder = zstd::stream::read::Decoder::new(bytes)?;
+                self.reset_buffer(buffer);
+                self.read_limited(decoder, buffer)?;
+                Ok(buffer.as_slice())
+            }
+            CompressionAlgorithm::Lz4Block => {
+                let mut cursor = Cursor::new(bytes);
+                let len = crate::deserialize::read_vint64(&mut cursor)? as usize;
+                let compressed = &bytes[cursor.position() as usize..];
+                self.check_decompressed_size(len as u64)?;
+                if len > compressed.len().saturating_mul(MAX_LZ4_BLOCK_RATIO) {
+                    return Err(Error::new(ErrorKind::InvalidData, format!(
+                        "The block should have {} bytes, which is more than {} compressed bytes can hold", len, compressed.len(),
+                    )));
+                }
+
+                self.reset_buffer(buffer);
+                buffer.resize(len, 0);
+
+                let decompressed_len = lz4_flex::block::decompress_into(compressed, buffer)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                if decompressed_len != len {
+                    return Err(Error::new(ErrorKind::InvalidData, format!(
+                        "Decompressed {} bytes, but the block should have {} bytes", decompressed_len, len,
+                    )));
+                }
+
                 Ok(buffer.as_slice())
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compress bytes into a block with an algorithm
+    fn compress(algorithm: CompressionAlgorithm, bytes: &[u8]) -> Vec<u8> {
+        let mut compressor = RowCompressor::new();
+        compressor.buffer.extend_from_slice(bytes);
+
+        let mut compressed = Vec::new();
+        compressor.compress(algorithm, None, &mut compressed).unwrap();
+        compressed
+    }
+
+    #[test]
+    fn capped_buffer_shrinks_after_a_large_block() {
+        let large = compress(CompressionAlgorithm::Gzip, &[7; 1 << 20]);
+        let small = compress(CompressionAlgorithm::Gzip, &[7; 100]);
+
+        let decompress_both = |decompressor: RowDecompressor| {
+            let mut buffer = Vec::new();
+            assert_eq!(decompressor.decompress(&large, &mut buffer).unwrap().len(), 1 << 20);
+            assert!(buffer.capacity() >= 1 << 20);
+
+            assert_eq!(decompressor.decompress(&small, &mut buffer).unwrap(), &[7; 100]);
+            buffer.capacity()
+        };
+
+        let capacity = decompress_both(RowDecompressor::with_max_buffer_capacity(CompressionAlgorithm::Gzip, Some(4096)));
+        assert!(capacity <= 4096, "{}", capacity);
+
+        // Without a cap, the buffer keeps the capacity of the large block
+        let capacity = decompress_both(RowDecompressor::new(CompressionAlgorithm::Gzip));
+        assert!(capacity >= 1 << 20, "{}", capacity);
+    }
+
+    #[test]
+    fn lz4_block_round_trips_into_a_buffer_of_the_exact_size() {
+        let bytes: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let compressed = compress(CompressionAlgorithm::Lz4Block, &bytes);
+        assert!(compressed.len() < bytes.len());
+
+        let mut buffer = Vec::new();
+        let decompressor = RowDecompressor::new(CompressionAlgorithm::Lz4Block);
+        assert_eq!(decompressor.decompress(&compressed, &mut buffer).unwrap(), bytes.as_slice());
+
+        let empty = compress(CompressionAlgorithm::Lz4Block, &[]);
+        assert_eq!(decompressor.decompress(&empty, &mut buffer).unwrap(), &[] as &[u8]);
+    }
+
+    #[test]
+    fn lz4_block_with_a_corrupt_length_is_rejected_before_allocating() {
+        let compressed = compress(CompressionAlgorithm::Lz4Block, &[7; 100]);
+        let len_size = vint64::encode(100).as_ref().len();
+
+        let with_length = |len: u64| {
+            let mut corrupt = vint64::encode(len).as_ref().to_vec();
+            corrupt.extend_from_slice(&compressed[len_size..]);
+            corrupt
+        };
+
+        let decompressor = RowDecompressor::new(CompressionAlgorithm::Lz4Block);
+        let mut buffer = Vec::new();
+
+        // A huge length is rejected without resizing the buffer to it
+        let e = decompressor.decompress(&with_length(u64::MAX >> 8), &mut buffer).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("more than"), "{}", e);
+        assert!(buffer.capacity() < 1 << 20, "{}", buffer.capacity());
+
+        // A plausible but wrong length is caught by the decompression itself
+        let e = decompressor.decompress(&with_length(101), &mut buffer).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn block_decompressing_past_the_limit_is_rejected() {
+        for algorithm in CompressionAlgorithm::ALL {
+            // A megabyte of the same byte, which the algorithms compress into a few kilobytes
+            let compressed = compress(algorithm, &[7; 1 << 20]);
+            let mut decompressor = RowDecompressor::new(algorithm);
+            decompressor.set_max_decompressed_size(Some(4096));
+
+            let mut buffer = Vec::new();
+            let e = decompressor.decompress(&compressed, &mut buffer).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "{:?}", algorithm);
+            assert_eq!(e.to_string(), "The decompressed block exceeds the limit of 4096 bytes", "{:?}", algorithm);
+            assert!(buffer.capacity() < 1 << 20, "{:?}: {}", algorithm, buffer.capacity());
+
+            // A block of exactly the limit is decompressed
+            let compressed = compress(algorithm, &[7; 4096]);
+            assert_eq!(decompressor.decompress(&compressed, &mut buffer).unwrap(), &[7; 4096], "{:?}", algorithm);
+        }
+    }
+}
@@ -1,21 +1,29 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::{Path, PathBuf}};
 
-use serde::Deserialize;
-use crate::{compression::CompressionAlgorithm, tsv_reader::{ColumnType, MissingValuePolicy}};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{compression::CompressionAlgorithm, tsv_reader::{CellValue, ColumnType, ConstraintViolationPolicy, MissingValuePolicy, ParseFormat}};
 
-#[derive(Debug, Deserialize)]
+/// The maximum number of decimals of a float position column, so scaled positions keep the precision of an f64.
+pub const MAX_POSITION_DECIMALS: u8 = 9;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     #[serde(skip)]
     pub metadata: Option<ConfigMetadata>,
+    /// The genome assembly the positions refer to, such as "GRCh38".
+    #[serde(default)]
+    pub assembly: Option<String>,
     pub datasets: HashMap<String, Dataset>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct ConfigMetadata {
-    pub config_path: PathBuf,
+    /// The directory that the paths of the datasets are relative to
+    pub config_dir: PathBuf,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Dataset {
     #[serde(skip)]
     pub metadata: Option<DatasetMetadata>,
@@ -24,15 +32,52 @@ pub struct Dataset {
     pub path: String,
     pub columns: Vec<Column>,
     pub rows_per_index: usize,
+    /// Merge the last block of a table into the previous one if it has fewer rows than this. Defaults to 0, which never merges.
+    #[serde(default)]
+    pub min_rows_last_block: usize,
+    /// Pad blocks so they start at a multiple of this many bytes in the file, such as the 4096 byte page size.
+    /// Defaults to 0, which doesn't pad.
+    #[serde(default)]
+    pub block_alignment: usize,
     pub compression_algorithm: CompressionAlgorithm,
+    /// The compression level of the blocks, from 0 (fastest) to 9 (smallest) for gzip, or from 1 to 22 for zstd. Defaults to 9
+    /// for gzip and to zstd's default level for zstd.
+    #[serde(default)]
+    pub compression_level: Option<u32>,
+    /// The compression algorithm used for the table indices. Defaults to no compression.
+    #[serde(default)]
+    pub index_compression_algorithm: CompressionAlgorithm,
+    /// The gzip compression level of the table indices, from 0 (fastest to decode) to 9 (smallest). Defaults to
+    /// `compression_level` when the blocks are compressed with gzip as well, or 9 otherwise.
+    #[serde(default)]
+    pub index_compression_level: Option<u32>,
+    /// The number of decimals of a float position column, such as genetic map positions in centimorgans. The positions are
+    /// stored as integers scaled by `10^position_decimals`, so ranges can be queried on the float values at this precision.
+    #[serde(default)]
+    pub position_decimals: Option<u8>,
+    /// Whether the positions are 0-based, such as in BED files, or 1-based, such as in VCF and GFF files. Queries can
+    /// translate their bounds from the other base if it is set.
+    #[serde(default)]
+    pub coordinate_base: Option<u8>,
+    /// Whether the files are already sorted by position. The rows are then serialized into blocks as they are read,
+    /// instead of loading and sorting the whole table first, and the build fails if a position is out of order.
+    #[serde(default)]
+    pub pre_sorted: bool,
+    /// Build a second index of interval datasets keyed by the end position, so rows can also be queried by their end.
+    #[serde(default)]
+    pub end_index: bool,
+    /// Store the cells of a block column by column instead of row by row. Queries that only read some columns then skip
+    /// the others without decoding them, and similar values next to each other often compress better.
+    #[serde(default)]
+    pub columnar: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct DatasetMetadata {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: String,
     #[serde(rename = "type")]
@@ -41,18 +86,99 @@ pub struct Column {
     pub role: ColumnRole,
     #[serde(default)]
     pub missing_value_policy: MissingValuePolicy,
+    /// Values such as `NA` or `.` that denote a missing value even though the field is present. They are handled by
+    /// the missing value policy like an absent field. An empty value is always missing in a numeric column, as it
+    /// can't be parsed, but is an empty string in a string column unless `""` is listed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_values: Vec<String>,
+    #[serde(default, skip_serializing_if = "ColumnConstraints::is_empty")]
+    pub constraints: ColumnConstraints,
+    #[serde(default)]
+    pub constraint_violation_policy: ConstraintViolationPolicy,
+    /// Parse whole-valued floats such as `3.0` in an integer column as integers.
+    #[serde(default)]
+    pub coerce_whole_floats: bool,
+    /// A built-in parser for values in a non-standard format, such as percentages.
+    #[serde(default)]
+    pub parse_format: Option<ParseFormat>,
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Clone, Copy, Hash)]
+/// Domain rules that every value of a column must satisfy.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Default)]
+pub struct ColumnConstraints {
+    /// The minimum value of a numeric column (inclusive).
+    pub min: Option<f64>,
+    /// The maximum value of a numeric column (inclusive).
+    pub max: Option<f64>,
+    /// A regular expression that values of a string column must match.
+    pub pattern: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Copy, Hash)]
 #[serde(rename_all = "kebab-case")]
 #[repr(u8)]
 pub enum ColumnRole {
     Position,
     PositionStart,
     PositionEnd,
+    /// The chromosome of each row of a dataset in a single file, which selects the table of the row. It isn't stored.
+    Chromosome,
     Data = u8::MAX,
 }
 
+impl Column {
+    /// Whether a value is empty in a numeric column or one of the tokens that denote a missing value in this column.
+    pub fn is_missing_value(&self, value: &str) -> bool {
+        let is_numeric = matches!(self.type_, ColumnType::Integer | ColumnType::Float | ColumnType::Boolean);
+        (value.is_empty() && is_numeric) || self.missing_values.iter().any(|token| token == value)
+    }
+}
+
+impl ColumnConstraints {
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none() && self.pattern.is_none()
+    }
+
+    /// Compile the pattern of the constraints, if any.
+    pub fn compile_pattern(&self) -> Result<Option<Regex>, String> {
+        match &self.pattern {
+            Some(pattern) => Regex::new(pattern)
+                .map(Some)
+                .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Check if a value satisfies the constraints. Returns a description of the violated constraint otherwise.
+    pub fn check(&self, value: &CellValue, pattern: Option<&Regex>) -> Result<(), String> {
+        let number = match value {
+            CellValue::Integer(i) => Some(*i as f64),
+            CellValue::Float(f) => Some(*f),
+            CellValue::String(_) => None,
+        };
+
+        if let (Some(number), Some(min)) = (number, self.min) {
+            if number < min {
+                return Err(format!("value must be at least {}", min));
+            }
+        }
+
+        if let (Some(number), Some(max)) = (number, self.max) {
+            if number > max {
+                return Err(format!("value must be at most {}", max));
+            }
+        }
+
+        if let (CellValue::String(s), Some(pattern)) = (value, pattern) {
+            if !pattern.is_match(s) {
+                return Err(format!("value must match pattern '{}'", pattern.as_str()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl Default for ColumnRole {
     fn default() -> Self {
         ColumnRole::Data
@@ -61,12 +187,24 @@ impl Default for ColumnRole {
 
 impl Config {
     /// Load a config file from a path. Panics if the file cannot be read.
-    pub fn from_file(path: &str) -> Result<Self, toml::de::Error> {
+    pub fn from_file(path: &str) -> Result<Self, String> {
         let config_str = std::fs::read_to_string(path).expect("Could not read config file");
-        let mut res: Config = toml::from_str(&config_str)?;
+        let config_dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+
+        Self::from_str(&config_str, config_dir)
+    }
+
+    /// Parse a config from a TOML string, or from a JSON string if it starts with `{`, without reading a file. The paths
+    /// of the datasets are relative to `config_dir`.
+    pub fn from_str(config_str: &str, config_dir: PathBuf) -> Result<Self, String> {
+        let mut res: Config = if config_str.trim_start().starts_with('{') {
+            serde_json::from_str(config_str).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str(config_str).map_err(|e| e.to_string())?
+        };
 
         res.metadata = Some(ConfigMetadata {
-            config_path: PathBuf::from(path),
+            config_dir,
         });
 
         for (name, dataset) in &mut res.datasets {
@@ -78,8 +216,24 @@ impl Config {
         Ok(res)
     }
 
+    /// Serialize the config to TOML, in the same format as it is loaded by `from_file`. The metadata is not included.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(self)
+    }
+
+    /// Serialize the config to JSON. The metadata is not included.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
     /// Validate the config file. Returns an error message if the config is invalid.
     pub fn validate(&self) -> Result<(), String> {
+        if let Some(assembly) = &self.assembly {
+            if assembly.is_empty() || assembly.len() > 255 {
+                return Err(format!("Assembly '{}' must be between 1 and 255 characters long", assembly));
+            }
+        }
+
         for (name, dataset) in &self.datasets {
             self.validate_dataset(dataset).map_err(|e| format!("Dataset '{}': {}", name, e))?;
         }
@@ -104,28 +258,79 @@ impl Config {
             return Err("'rows_per_index' must be greater than 0".to_string());
         }
 
+        if dataset.block_alignment > 1 && !dataset.block_alignment.is_power_of_two() {
+            return Err(format!("'block_alignment' ({}) must be a power of two", dataset.block_alignment));
+        }
+
+        if let Some(compression_level) = dataset.compression_level {
+            let levels = match dataset.compression_algorithm {
+                CompressionAlgorithm::Gzip => 0..=9,
+                CompressionAlgorithm::Zstd => 1..=22,
+                algorithm => return Err(format!("'compression_level' is only supported for gzip and zstd, not {:?}", algorithm)),
+            };
+            if !levels.contains(&compression_level) {
+                return Err(format!(
+                    "'compression_level' ({}) must be between {} and {} for {:?}",
+                    compression_level, levels.start(), levels.end(), dataset.compression_algorithm,
+                ));
+            }
+        }
+
+        if let Some(index_compression_level) = dataset.index_compression_level {
+            if dataset.index_compression_algorithm != CompressionAlgorithm::Gzip {
+                return Err(format!(
+                    "'index_compression_level' is only supported for gzip, not {:?}",
+                    dataset.index_compression_algorithm,
+                ));
+            } else if index_compression_level > 9 {
+                return Err(format!("'index_compression_level' ({}) must be between 0 and 9 for gzip", index_compression_level));
+            }
+        }
+
+        if let Some(coordinate_base) = dataset.coordinate_base {
+            if coordinate_base > 1 {
+                return Err(format!("'coordinate_base' ({}) must be 0 or 1", coordinate_base));
+            }
+        }
+
+        if let Some(position_decimals) = dataset.position_decimals {
+            if !(1..=MAX_POSITION_DECIMALS).contains(&position_decimals) {
+                return Err(format!("'position_decimals' ({}) must be between 1 and {}", position_decimals, MAX_POSITION_DECIMALS));
+            }
+        }
+
+        if dataset.min_rows_last_block > dataset.rows_per_index {
+            return Err(format!(
+                "'min_rows_last_block' ({}) cannot be greater than 'rows_per_index' ({})",
+                dataset.min_rows_last_block, dataset.rows_per_index,
+            ));
+        }
+
         Ok(())
     }
 
     fn validate_path(&self, dataset: &Dataset) -> Result<(), String> {
-        if dataset.file_per_chromosome {
-            match &dataset.chromosomes {
-                Some(chromosomes) => {
-                    if chromosomes.is_empty() {
-                        return Err("'chromosomes' cannot be empty when 'file_per_chromosome' is true".to_string());
-                    }
-                },
-                None => return Err("'chromosomes' must be specified when 'file_per_chromosome' is true".to_string()),
-            }
-        } else {
-            return Err("Datasets with 'file_per_chromosome' set to false are currently not supported".to_string());
+        // The chromosomes are the tables of the dataset, which are also needed to split the rows of a single file
+        match &dataset.chromosomes {
+            Some(chromosomes) => {
+                if chromosomes.is_empty() {
+                    return Err("'chromosomes' cannot be empty".to_string());
+                }
+            },
+            None => return Err("'chromosomes' must be specified".to_string()),
         }
 
-        if !dataset.path.contains("{chromosome}") {
+        if dataset.file_per_chromosome && !dataset.path.contains("{chromosome}") {
             return Err("'path' must contain '{chromosome}' when 'file_per_chromosome' is true".to_string());
+        } else if !dataset.file_per_chromosome && dataset.path.contains("{chromosome}") {
+            return Err("'path' cannot contain '{chromosome}' when 'file_per_chromosome' is false".to_string());
+        }
+
+        if !dataset.file_per_chromosome && dataset.pre_sorted {
+            return Err("'pre_sorted' is only supported when 'file_per_chromosome' is true".to_string());
         }
 
-        for path in dataset.get_paths(&self.metadata.as_ref().unwrap().config_path).iter().map(|(_, path)| path) {
+        for path in dataset.get_paths(&self.metadata.as_ref().unwrap().config_dir).iter().map(|(_, path)| path) {
             if !path.is_file() {
                 return Err(format!("File '{}' does not exist", path.display()));
             }
@@ -156,8 +361,27 @@ impl Config {
             (None, Some(_), None) => return Err("If a column has the role 'position-start', a column with the role 'position-end' must be present".to_string()),
         };
 
+        match (dataset.file_per_chromosome, column_role_counts.get(&ColumnRole::Chromosome)) {
+            (true, None) | (false, Some(1)) => {},
+            (true, Some(_)) => return Err("A column can only have the role 'chromosome' when 'file_per_chromosome' is false".to_string()),
+            (false, None) => return Err("A column must have the role 'chromosome' when 'file_per_chromosome' is false".to_string()),
+            (false, Some(_)) => return Err("Only one column may have the role 'chromosome'".to_string()),
+        }
+
+        if dataset.end_index && !column_role_counts.contains_key(&ColumnRole::PositionEnd) {
+            return Err("'end_index' is only supported for datasets with a column with the role 'position-end'".to_string());
+        }
+
         for column in &dataset.columns {
-            if column.role == ColumnRole::Position && column.type_ != ColumnType::Integer {
+            if column.role == ColumnRole::Position && dataset.position_decimals.is_some() {
+                if column.type_ != ColumnType::Float {
+                    return Err(format!("Column '{}' with the role 'position' must have the type 'float' when 'position_decimals' is set", column.name));
+                }
+            } else if column.role == ColumnRole::Chromosome && column.type_ != ColumnType::Integer {
+                return Err(format!("Column '{}' with the role 'chromosome' must have the type 'integer'", column.name));
+            } else if column.role != ColumnRole::Data && column.role != ColumnRole::Chromosome && dataset.position_decimals.is_some() {
+                return Err("'position_decimals' is only supported for datasets with a column with the role 'position'".to_string());
+            } else if column.role == ColumnRole::Position && column.type_ != ColumnType::Integer {
                 return Err(format!("Column '{}' with the role 'position' must have the type 'integer'", column.name).to_string());
             } else if column.role == ColumnRole::PositionStart && column.type_ != ColumnType::Integer {
                 return Err(format!("Column '{}' with the role 'position-start' must have the type 'integer'", column.name).to_string());
@@ -166,6 +390,47 @@ impl Config {
             }
         }
 
+        for column in &dataset.columns {
+            let is_numeric = column.type_ == ColumnType::Integer || column.type_ == ColumnType::Float;
+
+            if !is_numeric && (column.constraints.min.is_some() || column.constraints.max.is_some()) {
+                return Err(format!("Column '{}' has a 'min' or 'max' constraint, but is not numeric", column.name));
+            } else if is_numeric && column.constraints.pattern.is_some() {
+                return Err(format!("Column '{}' has a 'pattern' constraint, but is not a string", column.name));
+            }
+
+            if column.coerce_whole_floats && column.type_ != ColumnType::Integer {
+                return Err(format!("Column '{}' has 'coerce_whole_floats' set, but is not an integer column", column.name));
+            }
+
+            if let Some(parse_format) = column.parse_format {
+                if parse_format.column_type() != column.type_ {
+                    return Err(format!(
+                        "Column '{}' has parse format {:?}, which requires type {:?} instead of {:?}",
+                        column.name, parse_format, parse_format.column_type(), column.type_,
+                    ));
+                }
+            }
+
+            if let (Some(min), Some(max)) = (column.constraints.min, column.constraints.max) {
+                if min > max {
+                    return Err(format!("Column '{}' has a 'min' constraint greater than its 'max' constraint", column.name));
+                }
+            }
+
+            column.constraints.compile_pattern().map_err(|e| format!("Column '{}': {}", column.name, e))?;
+
+            // Missing floats are stored as NaN, but integers and booleans have no value to store instead
+            if column.missing_value_policy == MissingValuePolicy::ReplaceWithEmptyString
+                && matches!(column.type_, ColumnType::Integer | ColumnType::Boolean)
+            {
+                return Err(format!(
+                    "Column '{}' has the missing value policy 'ReplaceWithEmptyString', but a column of type {:?} can't store an empty value",
+                    column.name, column.type_,
+                ));
+            }
+        }
+
         for (i, column) in dataset.columns.iter().enumerate() {
             if column.name.len() > 255 {
                 return Err(format!("Column name '{}' is too long (max 255 characters)", column.name));
@@ -177,6 +442,8 @@ impl Config {
                 return Err("The column with role 'position-start' must be the first column".to_string());
             } else if i == 1 && column_role_counts.get(&ColumnRole::PositionEnd).is_some() && column.role != ColumnRole::PositionEnd {
                 return Err("The column with role 'position-end' must be the second column".to_string());
+            } else if column.role == ColumnRole::Chromosome && i != dataset.columns.len() - 1 {
+                return Err("The column with role 'chromosome' must be the last column".to_string());
             }
         }
 
@@ -185,14 +452,27 @@ impl Config {
 }
 
 impl Dataset {
-    /// Get the paths to the dataset files.
-    pub fn get_paths(&self, config_path: &PathBuf) -> Vec<(u8, PathBuf)> {
-        let config_dir = config_path.parent().unwrap();
+    /// The columns that are stored in the blocks, which are all columns except the chromosome column of a dataset in a
+    /// single file. The chromosome column is always the last column.
+    pub fn stored_columns(&self) -> &[Column] {
+        match self.columns.last() {
+            Some(column) if column.role == ColumnRole::Chromosome => &self.columns[..self.columns.len() - 1],
+            _ => &self.columns,
+        }
+    }
 
+    /// The chromosomes of the tables of the dataset, sorted in the order the tables are written.
+    pub fn table_chromosomes(&self) -> Vec<u8> {
+        let mut sorted = self.chromosomes.as_ref().unwrap().to_owned();
+        sorted.sort();
+        sorted
+    }
+
+    /// Get the paths to the dataset files. A dataset in a single file has one path with chromosome 0, as the chromosome of
+    /// each row is read from its chromosome column.
+    pub fn get_paths(&self, config_dir: &Path) -> Vec<(u8, PathBuf)> {
         if self.file_per_chromosome {
-            let mut sorted = self.chromosomes.as_ref().unwrap().to_owned();
-            sorted.sort();
-            sorted.iter().map(|&chromosome| {
+            self.table_chromosomes().iter().map(|&chromosome| {
                 (chromosome, config_dir.join(self.path.replace("{chromosome}", &chromosome.to_string())))
             }).collect()
         } else {
@@ -202,3 +482,270 @@ impl Dataset {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{parse_config, write_files};
+
+    /// Validate a config with a single dataset of which the score column has a type and missing value policy
+    fn validate_score_column(type_: &str, missing_value_policy: &str) -> Result<(), String> {
+        let dir = write_files(&[("data_chr1.tsv", "pos\tscore\n1\t2\n")]);
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "data_chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "{type}", missing_value_policy = "{missing_value_policy}" },
+            ]
+        "#.replace("{type}", type_).replace("{missing_value_policy}", missing_value_policy);
+
+        parse_config(&dir, &config).validate()
+    }
+
+    #[test]
+    fn replace_with_empty_string_requires_a_column_that_can_store_it() {
+        for type_ in ["float", "volatile-string"] {
+            assert_eq!(validate_score_column(type_, "ReplaceWithEmptyString"), Ok(()));
+        }
+
+        for type_ in ["integer", "boolean"] {
+            assert_eq!(validate_score_column(type_, "Throw"), Ok(()));
+
+            let e = validate_score_column(type_, "ReplaceWithEmptyString").unwrap_err();
+            assert!(e.contains("Column 'score' has the missing value policy 'ReplaceWithEmptyString'"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn position_decimals_require_a_float_position_column() {
+        let validate = |position_type: &str, position_decimals: u8| {
+            let dir = write_files(&[("map_chr1.tsv", "cm\trate\n1.5\t2\n")]);
+            let config = r#"
+                [datasets.map]
+                file_per_chromosome = true
+                chromosomes = [1]
+                path = "map_chr{chromosome}.tsv"
+                rows_per_index = 4
+                compression_algorithm = "none"
+                position_decimals = {position_decimals}
+                columns = [
+                    { name = "cm", type = "{position_type}", role = "position" },
+                    { name = "rate", type = "float" },
+                ]
+            "#.replace("{position_type}", position_type).replace("{position_decimals}", &position_decimals.to_string());
+
+            parse_config(&dir, &config).validate()
+        };
+
+        assert_eq!(validate("float", 3), Ok(()));
+
+        let e = validate("integer", 3).unwrap_err();
+        assert!(e.contains("must have the type 'float' when 'position_decimals' is set"), "{}", e);
+
+        let e = validate("float", MAX_POSITION_DECIMALS + 1).unwrap_err();
+        assert!(e.contains("'position_decimals' (10) must be between 1 and 9"), "{}", e);
+    }
+
+    #[test]
+    fn compression_level_is_validated_per_algorithm() {
+        let validate = |compression_algorithm: &str, compression_level: u32| {
+            let dir = write_files(&[("genes_chr1.tsv", "pos\tgene\n1\tA\n")]);
+            let config = r#"
+                [datasets.genes]
+                file_per_chromosome = true
+                chromosomes = [1]
+                path = "genes_chr{chromosome}.tsv"
+                rows_per_index = 4
+                compression_algorithm = "{compression_algorithm}"
+                compression_level = {compression_level}
+                columns = [
+                    { name = "pos", type = "integer", role = "position" },
+                    { name = "gene", type = "volatile-string" },
+                ]
+            "#.replace("{compression_algorithm}", compression_algorithm).replace("{compression_level}", &compression_level.to_string());
+
+            parse_config(&dir, &config).validate()
+        };
+
+        assert_eq!(validate("gzip", 0), Ok(()));
+        assert_eq!(validate("gzip", 9), Ok(()));
+        assert_eq!(validate("zstd", 22), Ok(()));
+
+        let e = validate("gzip", 10).unwrap_err();
+        assert!(e.contains("'compression_level' (10) must be between 0 and 9 for Gzip"), "{}", e);
+
+        let e = validate("zstd", 0).unwrap_err();
+        assert!(e.contains("'compression_level' (0) must be between 1 and 22 for Zstd"), "{}", e);
+
+        let e = validate("lz4", 5).unwrap_err();
+        assert!(e.contains("'compression_level' is only supported for gzip and zstd, not LZ4"), "{}", e);
+    }
+
+    #[test]
+    fn coordinate_base_must_be_zero_or_one() {
+        let validate = |coordinate_base: u8| {
+            let dir = write_files(&[("genes_chr1.tsv", "pos\tgene\n1\tA\n")]);
+            let config = r#"
+                [datasets.genes]
+                file_per_chromosome = true
+                chromosomes = [1]
+                path = "genes_chr{chromosome}.tsv"
+                rows_per_index = 4
+                compression_algorithm = "none"
+                coordinate_base = {coordinate_base}
+                columns = [
+                    { name = "pos", type = "integer", role = "position" },
+                    { name = "gene", type = "volatile-string" },
+                ]
+            "#.replace("{coordinate_base}", &coordinate_base.to_string());
+
+            parse_config(&dir, &config).validate()
+        };
+
+        assert_eq!(validate(0), Ok(()));
+        assert_eq!(validate(1), Ok(()));
+        assert_eq!(validate(2), Err("Dataset 'genes': 'coordinate_base' (2) must be 0 or 1".to_string()));
+    }
+
+    #[test]
+    fn single_file_dataset_needs_a_last_chromosome_column() {
+        let validate = |path: &str, columns: &str| {
+            let dir = write_files(&[("genes.tsv", "pos\tgene\tchrom\n1\tA\t1\n")]);
+            let config = r#"
+                [datasets.genes]
+                file_per_chromosome = false
+                chromosomes = [1]
+                path = "{path}"
+                rows_per_index = 4
+                compression_algorithm = "none"
+                columns = [{columns}]
+            "#.replace("{path}", path).replace("{columns}", columns);
+
+            parse_config(&dir, &config).validate()
+        };
+
+        let pos = r#"{ name = "pos", type = "integer", role = "position" }"#;
+        let gene = r#"{ name = "gene", type = "volatile-string" }"#;
+        let chrom = r#"{ name = "chrom", type = "integer", role = "chromosome" }"#;
+
+        assert_eq!(validate("genes.tsv", &[pos, gene, chrom].join(", ")), Ok(()));
+        assert_eq!(
+            validate("genes.tsv", &[pos, gene].join(", ")),
+            Err("Dataset 'genes': A column must have the role 'chromosome' when 'file_per_chromosome' is false".to_string()),
+        );
+        assert_eq!(
+            validate("genes.tsv", &[pos, chrom, gene].join(", ")),
+            Err("Dataset 'genes': The column with role 'chromosome' must be the last column".to_string()),
+        );
+        assert_eq!(
+            validate("genes_chr{chromosome}.tsv", &[pos, gene, chrom].join(", ")),
+            Err("Dataset 'genes': 'path' cannot contain '{chromosome}' when 'file_per_chromosome' is false".to_string()),
+        );
+    }
+
+    /// A config with every kind of column setting, built in code rather than parsed
+    fn config_built_in_code() -> Config {
+        let column = |name: &str, type_: ColumnType, role: ColumnRole| Column {
+            name: name.to_string(),
+            type_,
+            role,
+            missing_value_policy: MissingValuePolicy::default(),
+            missing_values: Vec::new(),
+            constraints: ColumnConstraints::default(),
+            constraint_violation_policy: ConstraintViolationPolicy::default(),
+            coerce_whole_floats: false,
+            parse_format: None,
+        };
+
+        let columns = vec![
+            Column { coerce_whole_floats: true, ..column("pos", ColumnType::Integer, ColumnRole::Position) },
+            Column {
+                missing_value_policy: MissingValuePolicy::OmitRow,
+                constraints: ColumnConstraints { min: Some(0.0), max: Some(1.0), pattern: None },
+                constraint_violation_policy: ConstraintViolationPolicy::TreatAsMissing,
+                parse_format: Some(ParseFormat::Percent),
+                ..column("af", ColumnType::Float, ColumnRole::Data)
+            },
+            Column {
+                missing_value_policy: MissingValuePolicy::ReplaceWithEmptyString,
+                constraints: ColumnConstraints { min: None, max: None, pattern: Some("^[A-Z0-9]+$".to_string()) },
+                ..column("gene", ColumnType::InternedString, ColumnRole::Data)
+            },
+        ];
+
+        let dataset = Dataset {
+            metadata: None,
+            file_per_chromosome: true,
+            chromosomes: Some(vec![1, 2, 22]),
+            path: "variants_chr{chromosome}.tsv".to_string(),
+            columns,
+            rows_per_index: 64,
+            min_rows_last_block: 8,
+            block_alignment: 4096,
+            compression_algorithm: CompressionAlgorithm::LZ4,
+            compression_level: None,
+            index_compression_algorithm: CompressionAlgorithm::Gzip,
+            index_compression_level: None,
+            position_decimals: None,
+            coordinate_base: None,
+            pre_sorted: false,
+            end_index: false,
+            columnar: false,
+        };
+
+        Config {
+            metadata: None,
+            assembly: Some("GRCh38".to_string()),
+            datasets: HashMap::from([("variants".to_string(), dataset)]),
+        }
+    }
+
+    #[test]
+    fn config_round_trips_through_toml_and_json() {
+        let config = config_built_in_code();
+
+        let toml = config.to_toml().unwrap();
+        assert_eq!(toml::from_str::<Config>(&toml).unwrap(), config, "{}", toml);
+
+        let json = config.to_json().unwrap();
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config, "{}", json);
+    }
+
+    #[test]
+    fn config_from_a_string_is_relative_to_the_given_directory() {
+        let dir = write_files(&[("data_chr1.tsv", "pos\tscore\n1\t2\n")]);
+        let toml = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "data_chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+
+        let config = Config::from_str(toml, dir.path().to_path_buf()).unwrap();
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.metadata, Some(ConfigMetadata { config_dir: dir.path().to_path_buf() }));
+        assert_eq!(config.datasets["scores"].get_paths(dir.path()), [(1, dir.path().join("data_chr1.tsv"))]);
+
+        // A config in JSON is parsed the same
+        let json = config.to_json().unwrap();
+        assert_eq!(Config::from_str(&json, dir.path().to_path_buf()).unwrap(), config);
+
+        // The files are looked up in the given directory, not the working directory
+        let elsewhere = write_files(&[]);
+        let e = Config::from_str(toml, elsewhere.path().to_path_buf()).unwrap().validate().unwrap_err();
+        assert!(e.contains("does not exist"), "{}", e);
+
+        assert!(Config::from_str("{ not json", dir.path().to_path_buf()).is_err());
+    }
+}
@@ -1,21 +1,32 @@
-use std::fs::OpenOptions;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-
-use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
-
-use crate::config::{Column, Config, Dataset};
-use crate::tsv_reader::{CellValue, TabSeparatedFileReader};
-use crate::compression::RowCompressor;
-
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::config::{Column, ColumnRole, Config, Dataset};
+use crate::tsv_reader::{CellValue, ColumnType, TabSeparatedFileReader};
+use crate::compression::{block_checksum, table_checksum, CompressionAlgorithm, RowCompressor};
+use crate::query::{BlockBounds, DatabaseQueryClient, DatasetHeader, FloatTolerance, RowReader};
+
+// The file format doesn't depend on the byte order or pointer width of the machine that built it. Fixed-width integers
+// and floats are big-endian and lengths are written as `u64` rather than `usize`. Integer cells, index entries, string
+// lengths and string pool ids are vint64, which encodes the value byte by byte. Gzip members of the input files are
+// little-endian, as RFC 1952 specifies.
 pub const HEADER_MAGIC: &[u8] = b"ZygosDB";
-pub const HEADER_VERSION: u8 = 1;
+pub const HEADER_VERSION: u8 = 15;
 pub const INDEX_MAGIC: &[u8] = b"INDEX";
+/// The number of index entries between two entries of the skip table of an uncompressed index
+pub const INDEX_SKIP_INTERVAL: usize = 64;
 
 #[derive(Debug)]
 pub struct Database {
     path: std::path::PathBuf,
     config: Config,
+    progress_json: bool,
 }
 
 pub struct Table {
@@ -26,20 +37,34 @@ pub struct Table {
 
 pub type Row = Vec<CellValue>;
 
-pub type IndicesList = Vec<(usize, usize)>;
+/// The position of the first row, offset in the file, compressed size, number of rows and checksum of every block
+pub type IndicesList = Vec<(usize, usize, usize, usize, u32)>;
+
+/// The smallest and largest end position and the offset in the file of every block of an interval table
+pub type EndIndicesList = Vec<(usize, usize, usize)>;
 
 impl Database {
     pub fn new(path: std::path::PathBuf, config: Config) -> Self {
         Self {
             path,
             config,
+            progress_json: false,
         }
     }
 
-    pub fn save(&self) -> std::io::Result<()> {
-        self.clear_if_database(&self.path)?;
+    /// Print a JSON line to stderr for every table that is serialized, instead of printing every block to stdout, so
+    /// the progress of a build can be followed by another program.
+    pub fn set_progress_json(&mut self, progress_json: bool) {
+        self.progress_json = progress_json;
+    }
 
-        let mut file = std::fs::File::create(&self.path)?;
+    /// Build the database and write it to its path.
+    /// 
+    /// The database is written to a temporary file next to the path first, which is then renamed over the path as the
+    /// final step. Readers that already opened the old database keep reading it, and readers that open the path
+    /// concurrently see either the old or the new database, never a partially written one.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.check_overwritable(&self.path)?;
 
         let mut bytes: Vec<u8> = Vec::new();
         let ptr_to_index_locations = self.serialize_database_header(&mut bytes);
@@ -61,13 +86,23 @@ impl Database {
             }
         }
 
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut file = std::fs::File::create(&tmp_path)?;
         file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&tmp_path, &self.path)?;
 
         Ok(())
     }
 
-    pub fn clear_if_database(&self, path: &PathBuf) -> std::io::Result<()> {
-        let mut file = match OpenOptions::new().read(true).write(true).create(false).open(path) {
+    /// Check that the path either does not exist, is empty, or is a database that can be replaced
+    pub fn check_overwritable(&self, path: &PathBuf) -> std::io::Result<()> {
+        let mut file = match OpenOptions::new().read(true).create(false).open(path) {
             Ok(file) => file,
             Err(_) => return Ok(()), // The file does not exist
         };
@@ -76,12 +111,13 @@ impl Database {
         match file.read_exact(&mut magic_bytes) {
             Ok(_) => {
                 if magic_bytes == HEADER_MAGIC {
-                    file.set_len(0)
+                    Ok(())
                 } else {
                     Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Not a ZygosDB database"))
                 }
             },
-            Err(_) => Ok(()), // The file is empty
+            Err(_) if file.metadata()?.len() == 0 => Ok(()), // The file is empty
+            Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Not a ZygosDB database")),
         }
     }
 
@@ -91,6 +127,19 @@ impl Database {
         bytes.extend_from_slice(&HEADER_MAGIC);
         bytes.push(HEADER_VERSION);
 
+        // Build timestamp and the version of ZygosDB, so it can be traced how the database was built
+        let build_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+        bytes.extend_from_slice(&build_timestamp.to_be_bytes());
+
+        let build_version = env!("CARGO_PKG_VERSION");
+        bytes.push(build_version.len() as u8);
+        bytes.extend_from_slice(build_version.as_bytes());
+
+        // Assembly, where an empty string means it is unknown
+        let assembly = self.config.assembly.as_deref().unwrap_or("");
+        bytes.push(assembly.len() as u8);
+        bytes.extend_from_slice(assembly.as_bytes());
+
         bytes.push(self.config.datasets.len() as u8);
 
         let mut ptr_to_index_locations = Vec::new();
@@ -114,35 +163,54 @@ impl Database {
         // Compression algorithm
         bytes.push(dataset.compression_algorithm as u8);
 
+        // Rows per index
+        bytes.extend_from_slice(&(dataset.rows_per_index as u64).to_be_bytes());
+
+        // Decimals of float positions, where 0 means the positions are integers
+        bytes.push(dataset.position_decimals.unwrap_or(0));
+
+        // Coordinate base of the positions, where u8::MAX means it is unknown
+        bytes.push(dataset.coordinate_base.unwrap_or(u8::MAX));
+
+        // Layout of the blocks, where 0 means row by row and 1 means column by column
+        bytes.push(dataset.columnar as u8);
+
         // Columns
-        bytes.push(dataset.columns.len() as u8);
+        bytes.push(dataset.stored_columns().len() as u8);
+
+        for column in dataset.stored_columns() {
+            // Float positions are stored scaled to integers
+            let type_ = match column.role {
+                ColumnRole::Position if dataset.position_decimals.is_some() => ColumnType::Integer,
+                _ => column.type_,
+            };
 
-        for column in dataset.columns.iter() {
-            self.serialize_column_header(bytes, &column);
+            self.serialize_column_header(bytes, &column, type_);
         }
 
         // Tables
-        let paths = dataset.get_paths(&PathBuf::from("."));
-        let file_count = paths.len();
-        assert!(file_count < 256, "Too many files for dataset '{}': max 255, got {}", dataset_name, file_count);
-        bytes.push(file_count as u8);
+        let chromosomes = dataset.table_chromosomes();
+        let table_count = chromosomes.len();
+        assert!(table_count < 256, "Too many tables for dataset '{}': max 255, got {}", dataset_name, table_count);
+        bytes.push(table_count as u8);
 
         let mut ptr_to_index_locations = Vec::new();
 
-        for (chromosome, _) in paths {
+        for chromosome in chromosomes {
             bytes.push(chromosome);
             ptr_to_index_locations.push((chromosome, bytes.len()));
             bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]); // Placeholder for the offset
+            bytes.push(dataset.index_compression_algorithm as u8);
         }
 
         ptr_to_index_locations
     }
 
-    fn serialize_column_header(&self, bytes: &mut Vec<u8>, column: &Column) -> () {
+    fn serialize_column_header(&self, bytes: &mut Vec<u8>, column: &Column, type_: ColumnType) -> () {
         let column_name = &column.name;
         assert!(column_name.len() < 256);
 
-        bytes.push(column.type_ as u8);
+        bytes.push(type_ as u8);
         bytes.push(column_name.len() as u8);
         bytes.extend_from_slice(column_name.as_bytes());
     }
@@ -159,24 +227,128 @@ impl Database {
     }
 
     fn load_dataset(&self, dataset: &Dataset) -> Result<Vec<Table>, String> {
-        let config_path = &self.config.metadata.as_ref().expect("metadata must be present").config_path;
+        // Pre-sorted files are read while the dataset is serialized
+        if dataset.pre_sorted {
+            return Ok(Vec::new());
+        }
+
+        let config_dir = &self.config.metadata.as_ref().expect("metadata must be present").config_dir;
+
+        if !dataset.file_per_chromosome {
+            let (_, path) = dataset.get_paths(config_dir).into_iter().next().expect("a dataset in a single file has one path");
+            return self.load_dataset_single_file(dataset, &path)
+                .map_err(|e| format!("Failed to load file '{}':\n\t{}", path.display(), e));
+        }
         
-        let par_iter = dataset.get_paths(config_path).into_par_iter().map(|(chromosome, path)| {
+        #[cfg(feature = "parallel")]
+        let paths = dataset.get_paths(config_dir).into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let paths = dataset.get_paths(config_dir).into_iter();
+
+        let result: Vec<_> = paths.map(|(chromosome, path)| {
             match self.load_dataset_file(&dataset, &path) {
                 Ok(rows) => Ok(Table { chromosome, rows }),
                 Err(e) => Err(format!("Failed to load file of chromosome {} '{}':\n\t{}", chromosome, path.display(), e)),
             }
-        });
-
-        let mut result = Vec::new();
-        par_iter.collect_into_vec(&mut result);
+        }).collect();
 
         result.into_iter().collect()
     }
 
     fn load_dataset_file(&self, dataset: &Dataset, path: &PathBuf) -> Result<Vec<Row>, String> {
         let mut reader = TabSeparatedFileReader::new(std::fs::File::open(path).unwrap());
+        let wide_index_to_config_column = self.find_config_columns(&mut reader, dataset)?;
+
+        let all_data: Vec<Row> = reader.read_all(&wide_index_to_config_column)?;
+        let all_data: Vec<Row> = reader.convert_read_data(&dataset.columns, dataset.position_decimals, all_data)?;
+
+        Ok(all_data)
+    }
+
+    /// Load the file of a dataset with the rows of all chromosomes, and split the rows into a table per chromosome by their
+    /// chromosome column
+    fn load_dataset_single_file(&self, dataset: &Dataset, path: &PathBuf) -> Result<Vec<Table>, String> {
+        let mut reader = TabSeparatedFileReader::new(std::fs::File::open(path).unwrap());
+        let wide_index_to_config_column = self.find_config_columns(&mut reader, dataset)?;
+
+        let chromosomes = dataset.table_chromosomes();
+        let mut rows_by_chromosome: HashMap<u8, Vec<Row>> = chromosomes.iter().map(|&chromosome| (chromosome, Vec::new())).collect();
+
+        reader.read_rows(&wide_index_to_config_column, |mut row| {
+            let chromosome = take_chromosome(dataset, &mut row)?.expect("a dataset in a single file has a chromosome column");
+
+            match rows_by_chromosome.get_mut(&chromosome) {
+                Some(rows) => rows.push(row),
+                None => return Err(format!("Chromosome {} is not one of the 'chromosomes' of the dataset", chromosome)),
+            }
+
+            Ok(())
+        })?;
+
+        chromosomes.into_iter().map(|chromosome| {
+            let rows = rows_by_chromosome.remove(&chromosome).unwrap_or_default();
+            let rows = reader.convert_read_data(dataset.stored_columns(), dataset.position_decimals, rows)?;
+            Ok(Table { chromosome, rows })
+        }).collect()
+    }
+
+    /// Estimate how well each compression algorithm compresses a dataset, without building the database. The first
+    /// `sample_rows` rows of the file of the first chromosome are split into blocks of `rows_per_index` rows like in a
+    /// build, and every block is compressed with each algorithm.
+    pub fn sample_compression(&self, dataset_name: &str, sample_rows: usize) -> Result<Vec<CompressionSample>, String> {
+        let dataset = self.config.datasets.get(dataset_name)
+            .ok_or_else(|| format!("Dataset '{}' not found in config", dataset_name))?;
+        let config_dir = &self.config.metadata.as_ref().expect("metadata must be present").config_dir;
+
+        let (chromosome, path) = dataset.get_paths(config_dir).into_iter().next()
+            .ok_or_else(|| format!("Dataset '{}' has no chromosomes", dataset_name))?;
+
+        let mut reader = TabSeparatedFileReader::new(File::open(&path)
+            .map_err(|e| format!("Failed to open file of chromosome {} '{}': {}", chromosome, path.display(), e))?);
+        let wide_index_to_config_column = self.find_config_columns(&mut reader, dataset)?;
+
+        let mut rows: Vec<Row> = reader.read_head(&wide_index_to_config_column, sample_rows)?;
+        for row in rows.iter_mut() {
+            take_chromosome(dataset, row)?;
+            TabSeparatedFileReader::scale_position(&dataset.columns, dataset.position_decimals, row)?;
+        }
+
+        if rows.is_empty() {
+            return Err(format!("File of chromosome {} '{}' has no rows to sample", chromosome, path.display()));
+        }
+
+        let mut samples: Vec<CompressionSample> = CompressionAlgorithm::ALL.iter().map(|&algorithm| CompressionSample {
+            algorithm,
+            rows: rows.len(),
+            uncompressed_size: 0,
+            compressed_size: 0,
+            duration: Duration::ZERO,
+        }).collect();
+
+        let mut compressed: Vec<u8> = Vec::new();
+        for (i_block, chunk) in self.split_into_blocks(&rows, dataset).into_iter().enumerate() {
+            let mut row_compressor = RowCompressor::new();
+            self.serialize_dataset_block(&mut row_compressor.buffer, dataset, chunk, i_block)?;
+
+            for sample in samples.iter_mut() {
+                compressed.clear();
+
+                // The configured level only applies to the configured algorithm, as the levels of algorithms differ
+                let level = dataset.compression_level.filter(|_| sample.algorithm == dataset.compression_algorithm);
+
+                let start = Instant::now();
+                sample.compressed_size += row_compressor.compress(sample.algorithm, level, &mut compressed)
+                    .map_err(|e| e.to_string())?;
+                sample.duration += start.elapsed();
+                sample.uncompressed_size += row_compressor.buffer.len();
+            }
+        }
 
+        Ok(samples)
+    }
+
+    /// Read the header of a file of a dataset and map the columns of the config to their index in the file
+    fn find_config_columns<'a>(&self, reader: &mut TabSeparatedFileReader, dataset: &'a Dataset) -> Result<Vec<(usize, &'a Column)>, String> {
         let column_names = dataset.columns.iter().map(|column| column.name.to_owned()).collect();
         let column_indices: Vec<(String, usize)> = reader.find_column_indices(&column_names)?;
 
@@ -188,10 +360,7 @@ impl Database {
             };
         }
 
-        let all_data: Vec<Row> = reader.read_all(&wide_index_to_config_column)?;
-        let all_data: Vec<Row> = reader.convert_read_data(&dataset.columns, all_data)?;
-
-        Ok(all_data)
+        Ok(wide_index_to_config_column)
     }
 
     pub fn serialize_datasets(
@@ -210,109 +379,1385 @@ impl Database {
     }
 
     pub fn serialize_dataset(&self, bytes: &mut Vec<u8>, dataset: &Dataset, tables: Vec<Table>, ptr_to_index_locations: Vec<(u8, usize)>) -> Result<(), String> {
+        if dataset.pre_sorted {
+            return self.serialize_pre_sorted_dataset(bytes, dataset, ptr_to_index_locations);
+        }
+
         for (table, (chromosome, ptr_to_index_location)) in tables.into_iter().zip(ptr_to_index_locations) {
             assert_eq!(table.chromosome, chromosome);
 
-            let max_position = match table.rows.last() {
-                Some(row) => match row.first() {
-                    Some(CellValue::Integer(i)) => *i,
-                    _ => return Err("First cell of the first row must be an integer".to_string()),
-                },
-                None => return Err("Table must have at least one row".to_string()),
-            };
+            let mut table_writer = TableWriter::new(chromosome, ptr_to_index_location, bytes.len());
+            let chunks = self.split_into_blocks(&table.rows, dataset);
+
+            // The blocks are compressed independently, so only appending them has to happen in order, as their offsets
+            // depend on the sizes of the blocks before them
+            #[cfg(feature = "parallel")]
+            let i_blocks = (0..chunks.len()).into_par_iter();
+            #[cfg(not(feature = "parallel"))]
+            let i_blocks = 0..chunks.len();
+
+            let compressed_blocks = i_blocks
+                .map(|i_block| TableWriter::compress_block(self, dataset, chunks[i_block], i_block))
+                .collect::<Result<Vec<_>, String>>()?;
 
-            // Map of position (first column) to offset in the file
-            let mut position_indices: Vec<(usize, usize)> = Vec::new();
+            for (chunk, compressed_block) in chunks.into_iter().zip(compressed_blocks) {
+                table_writer.append_block(self, bytes, dataset, chunk, compressed_block)?;
+            }
+            table_writer.finish(self, bytes, dataset)?;
+        }
+
+        Ok(())
+    }
+
+    /// Serialize a dataset of which the files are already sorted by position, reading the rows of each file while its
+    /// blocks are written.
+    fn serialize_pre_sorted_dataset(&self, bytes: &mut Vec<u8>, dataset: &Dataset, ptr_to_index_locations: Vec<(u8, usize)>) -> Result<(), String> {
+        let config_dir = &self.config.metadata.as_ref().expect("metadata must be present").config_dir;
+
+        for ((chromosome, path), (_chromosome, ptr_to_index_location)) in dataset.get_paths(config_dir).into_iter().zip(ptr_to_index_locations) {
+            assert_eq!(chromosome, _chromosome);
+
+            self.serialize_pre_sorted_table(bytes, dataset, chromosome, &path, ptr_to_index_location)
+                .map_err(|e| format!(
+                    "Failed to load dataset '{}':\n\tFailed to load file of chromosome {} '{}':\n\t{}",
+                    dataset.metadata.as_ref().unwrap().name, chromosome, path.display(), e,
+                ))?;
+        }
+
+        Ok(())
+    }
 
-            for (i_block, chunk) in table.rows.chunks(dataset.rows_per_index).enumerate() {
-                let offset_block_start = bytes.len();
-                let first_position = match chunk.first() {
-                    Some(row) => match row.first() {
-                        Some(CellValue::Integer(i)) => *i as usize,
-                        _ => return Err("First cell of the first row must be an integer".to_string()),
-                    },
-                    None => return Err("Table must have at least one row".to_string()),
-                };
+    /// Serialize the table of a pre-sorted file. Only the rows of the blocks that haven't been written yet are held in
+    /// memory, instead of the whole table.
+    fn serialize_pre_sorted_table(&self, bytes: &mut Vec<u8>, dataset: &Dataset, chromosome: u8, path: &PathBuf, ptr_to_index_location: usize) -> Result<(), String> {
+        let mut reader = TabSeparatedFileReader::new(std::fs::File::open(path).unwrap());
+        let wide_index_to_config_column = self.find_config_columns(&mut reader, dataset)?;
+        let mut table_writer = TableWriter::new(chromosome, ptr_to_index_location, bytes.len());
 
-                let mut row_compressor = RowCompressor::new();
-                self.serialize_dataset_block(&mut row_compressor.buffer, dataset, chunk, i_block)?;
-                let compressed_size = row_compressor.compress(dataset.compression_algorithm, bytes).map_err(|e| e.to_string())?;
+        // A block is only written once enough rows follow it that an undersized last block can't be merged into it
+        let mut pending_rows: Vec<Row> = Vec::new();
+        let mut previous_position: Option<i64> = None;
 
-                println!("Block {} ({} rows) compressed from {} to {}", i_block, chunk.len(), row_compressor.buffer.len(), compressed_size);
+        reader.read_rows(&wide_index_to_config_column, |mut row| {
+            TabSeparatedFileReader::scale_position(&dataset.columns, dataset.position_decimals, &mut row)?;
 
+            let position = match row[0] {
+                CellValue::Integer(i) => i,
+                _ => return Err(format!("Values in first column must be integers. Found '{:?}'.", row[0])),
+            };
+            if let Some(previous_position) = previous_position {
+                if position < previous_position {
+                    return Err(format!(
+                        "Position {} comes after position {}, but the dataset is declared to be pre-sorted",
+                        position, previous_position,
+                    ));
+                }
+            }
+            previous_position = Some(position);
 
-                position_indices.push((first_position, offset_block_start));
+            pending_rows.push(row);
+            if pending_rows.len() >= dataset.rows_per_index + dataset.min_rows_last_block {
+                table_writer.write_block(self, bytes, dataset, &pending_rows[..dataset.rows_per_index])?;
+                pending_rows.drain(..dataset.rows_per_index);
             }
 
-            // Update the location of the index in the header
-            let index_offset = bytes.len();
-            let index_size = 8;
-            bytes.splice(ptr_to_index_location..ptr_to_index_location + index_size, index_offset.to_be_bytes().into_iter());
+            Ok(())
+        })?;
 
-            self.serialize_table_index(bytes, position_indices, max_position as usize);
+        for chunk in self.split_into_blocks(&pending_rows, dataset) {
+            table_writer.write_block(self, bytes, dataset, chunk)?;
         }
+        table_writer.finish(self, bytes, dataset)
+    }
 
-        Ok(())
+    /// Split the rows of a table into blocks of `rows_per_index` rows. An undersized last block is merged into the previous
+    /// one, so a merged block never has more than `rows_per_index + min_rows_last_block - 1` rows.
+    fn split_into_blocks<'a>(&self, rows: &'a [Row], dataset: &Dataset) -> Vec<&'a [Row]> {
+        let mut blocks: Vec<&[Row]> = rows.chunks(dataset.rows_per_index).collect();
+
+        if blocks.len() >= 2 && blocks[blocks.len() - 1].len() < dataset.min_rows_last_block {
+            let last = blocks.pop().unwrap();
+            let previous = blocks.pop().unwrap();
+            blocks.push(&rows[rows.len() - last.len() - previous.len()..]);
+        }
+
+        blocks
     }
 
     fn serialize_dataset_block(&self, bytes: &mut Vec<u8>, dataset: &Dataset, rows: &[Row], i_block: usize) -> Result<(), String> {
+        // Strings of interned columns that were already written in this block, mapped to their index in the block's pool
+        let mut string_pools: Vec<HashMap<&str, u64>> = dataset.stored_columns().iter().map(|_| HashMap::new()).collect();
+
+        if dataset.columnar {
+            // Every column is written as the cells of all rows, prefixed with its length so it can be skipped
+            let mut column_bytes: Vec<u8> = Vec::new();
+
+            for (i_col, pool) in string_pools.iter_mut().enumerate() {
+                column_bytes.clear();
+
+                if dataset.columns[i_col].type_ == ColumnType::Boolean {
+                    self.serialize_packed_booleans(&mut column_bytes, rows, i_col);
+                } else {
+                    for (i_row, row) in rows.iter().enumerate() {
+                        self.serialize_cell(&mut column_bytes, dataset, pool, i_col, &row[i_col], i_block * dataset.rows_per_index + i_row)?;
+                    }
+                }
+
+                bytes.extend_from_slice(vint64::encode(column_bytes.len() as u64).as_ref());
+                bytes.extend_from_slice(&column_bytes);
+            }
+
+            return Ok(());
+        }
+
         for (i_row, row) in rows.iter().enumerate() {
             for (i_col, cell) in row.iter().enumerate() {
-                match cell {
-                    CellValue::Integer(i) => {
-                        if i_col == 0 {
-                            if *i < 0 {
-                                return Err(format!(
-                                    "Position must be a positive integer (column {:?}, row {})",
-                                    dataset.columns[i_col].name, i_block * dataset.rows_per_index + i_row
-                                ));
-                            }
-                        }
-
-                        let encoded = vint64::signed::encode(*i);
-                        bytes.extend_from_slice(encoded.as_ref());
-                    },
-                    CellValue::Float(f) => {
-                        bytes.extend_from_slice(&f.to_be_bytes());
-                    },
-                    CellValue::String(s) => {
-                        let s_bytes = s.as_bytes();
-                        let s_len = s_bytes.len();
-
-                        if s_len > 255 {
-                            return Err(
-                                format!("Strings longer than 255 bytes are currently not supported (column {:?}, row {})",
-                                dataset.columns[i_col].name, i_block * dataset.rows_per_index + i_row
-                            ));
-                        }
-
-                        bytes.push(s_len as u8);
-                        bytes.extend_from_slice(s_bytes);
-                    },
-                }
+                self.serialize_cell(bytes, dataset, &mut string_pools[i_col], i_col, cell, i_block * dataset.rows_per_index + i_row)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pack the values of a boolean column of a columnar block into bits, so the column takes one byte per 8 rows. The
+    /// value of row `i` is bit `i % 8` of byte `i / 8`.
+    fn serialize_packed_booleans(&self, bytes: &mut Vec<u8>, rows: &[Row], i_col: usize) {
+        let packed = bytes.len();
+        bytes.resize(packed + rows.len().div_ceil(8), 0);
+
+        for (i_row, row) in rows.iter().enumerate() {
+            if let CellValue::Integer(1) = row[i_col] {
+                bytes[packed + i_row / 8] |= 1 << (i_row % 8);
             }
         }
+    }
+
+    fn serialize_cell<'a>(
+        &self,
+        bytes: &mut Vec<u8>,
+        dataset: &Dataset,
+        pool: &mut HashMap<&'a str, u64>,
+        i_col: usize,
+        cell: &'a CellValue,
+        i_row: usize,
+    ) -> Result<(), String> {
+        if let (ColumnType::Char, CellValue::String(s)) = (dataset.columns[i_col].type_, cell) {
+            // Empty values are written as 0, as the characters were checked to be ASCII when they were read
+            bytes.push(s.as_bytes().first().copied().unwrap_or(0));
+            return Ok(());
+        }
+
+        if let (ColumnType::Boolean, CellValue::Integer(i)) = (dataset.columns[i_col].type_, cell) {
+            // The values were checked to be 0 or 1 when they were read
+            bytes.push(*i as u8);
+            return Ok(());
+        }
+
+        if let (ColumnType::InternedString, CellValue::String(s)) = (dataset.columns[i_col].type_, cell) {
+            // 0 means a new string follows, anything else references the pool with an offset of 1
+            if let Some(&pool_index) = pool.get(&**s) {
+                bytes.extend_from_slice(vint64::encode(pool_index + 1).as_ref());
+                return Ok(());
+            }
+
+            pool.insert(&**s, pool.len() as u64);
+            bytes.extend_from_slice(vint64::encode(0).as_ref());
+        }
+
+        match cell {
+            CellValue::Integer(i) => {
+                if i_col == 0 {
+                    if *i < 0 {
+                        return Err(format!(
+                            "Position must be a positive integer (column {:?}, row {})",
+                            dataset.columns[i_col].name, i_row
+                        ));
+                    }
+                }
+
+                let encoded = vint64::signed::encode(*i);
+                bytes.extend_from_slice(encoded.as_ref());
+            },
+            CellValue::Float(f) => {
+                bytes.extend_from_slice(&f.to_be_bytes());
+            },
+            CellValue::String(s) => {
+                bytes.extend_from_slice(vint64::encode(s.len() as u64).as_ref());
+                bytes.extend_from_slice(s.as_bytes());
+            },
+        }
 
         Ok(())
     }
 
-    fn serialize_table_index(&self, bytes: &mut Vec<u8>, indices: IndicesList, max_position: usize) {
+    fn serialize_table_index(&self, bytes: &mut Vec<u8>, dataset: &Dataset, indices: IndicesList, end_indices: EndIndicesList, max_position: usize) -> Result<(), String> {
         bytes.extend_from_slice(INDEX_MAGIC);
 
-        bytes.extend_from_slice(&max_position.to_be_bytes());
+        bytes.extend_from_slice(&(max_position as u64).to_be_bytes());
         
         let ptr_to_end_offset = bytes.len();
         bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]); // Placeholder for the offset of the end of the index
-        
-        bytes.extend_from_slice(&indices.len().to_be_bytes());
 
-        for (position, offset) in indices {
-            bytes.extend_from_slice(vint64::encode(position as u64).as_ref());
-            bytes.extend_from_slice(vint64::encode(offset as u64).as_ref());
+        let ptr_to_end_index_offset = bytes.len();
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]); // Placeholder for the offset of the end index, 0 if there is none
+
+        // The entries are variable length, so a skip table with the position and offset in the entries of every
+        // `INDEX_SKIP_INTERVAL`th entry allows reading only part of the index. A compressed index has to be
+        // decompressed entirely, so it has no skip table.
+        let mut skip_table = Vec::new();
+
+        // Everything after the skip table is compressed with the index compression algorithm
+        let mut index_compressor = RowCompressor::new();
+        index_compressor.buffer.extend_from_slice(&(indices.len() as u64).to_be_bytes());
+
+        for (i, (position, offset, compressed_size, num_rows, checksum)) in indices.into_iter().enumerate() {
+            if i % INDEX_SKIP_INTERVAL == 0 && dataset.index_compression_algorithm == CompressionAlgorithm::None {
+                skip_table.push((position, index_compressor.buffer.len()));
+            }
+
+            index_compressor.buffer.extend_from_slice(vint64::encode(position as u64).as_ref());
+            index_compressor.buffer.extend_from_slice(vint64::encode(offset as u64).as_ref());
+            index_compressor.buffer.extend_from_slice(vint64::encode(compressed_size as u64).as_ref());
+            index_compressor.buffer.extend_from_slice(vint64::encode(num_rows as u64).as_ref());
+            // Fixed width, so it can be overwritten when a cell of the block is updated
+            index_compressor.buffer.extend_from_slice(&checksum.to_be_bytes());
+        }
+
+        bytes.extend_from_slice(&(skip_table.len() as u64).to_be_bytes());
+        for (position, offset_in_entries) in skip_table {
+            bytes.extend_from_slice(&(position as u64).to_be_bytes());
+            bytes.extend_from_slice(&(offset_in_entries as u64).to_be_bytes());
+        }
+
+        index_compressor.compress(dataset.index_compression_algorithm, index_compression_level(dataset), bytes).map_err(|e| e.to_string())?;
+
+        if dataset.end_index {
+            let end_index_offset = bytes.len();
+            bytes.splice(ptr_to_end_index_offset..ptr_to_end_index_offset + 8, (end_index_offset as u64).to_be_bytes());
+            self.serialize_end_index(bytes, dataset, end_indices)?;
         }
 
         let end_offset = bytes.len();
         let end_size = 8;
-        bytes.splice(ptr_to_end_offset..ptr_to_end_offset + end_size, end_offset.to_be_bytes().into_iter());
+        bytes.splice(ptr_to_end_offset..ptr_to_end_offset + end_size, (end_offset as u64).to_be_bytes());
+
+        Ok(())
+    }
+
+    /// Serialize the end index of an interval table after its index. The blocks are sorted by their smallest end
+    /// position, so the blocks that could contain an end before a position are a prefix of the end index.
+    fn serialize_end_index(&self, bytes: &mut Vec<u8>, dataset: &Dataset, mut end_indices: EndIndicesList) -> Result<(), String> {
+        end_indices.sort_unstable();
+
+        let mut index_compressor = RowCompressor::new();
+        index_compressor.buffer.extend_from_slice(&(end_indices.len() as u64).to_be_bytes());
+
+        for (min_end, max_end, offset) in end_indices {
+            index_compressor.buffer.extend_from_slice(vint64::encode(min_end as u64).as_ref());
+            index_compressor.buffer.extend_from_slice(vint64::encode(max_end as u64).as_ref());
+            index_compressor.buffer.extend_from_slice(vint64::encode(offset as u64).as_ref());
+        }
+
+        index_compressor.compress(dataset.index_compression_algorithm, index_compression_level(dataset), bytes).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+/// Remove the chromosome cell from a row of a dataset in a single file and return the chromosome, which is the last cell.
+/// Rows of other datasets have no chromosome cell and are left unchanged.
+fn take_chromosome(dataset: &Dataset, row: &mut Row) -> Result<Option<u8>, String> {
+    if dataset.stored_columns().len() == dataset.columns.len() {
+        return Ok(None);
+    }
+
+    match row.pop() {
+        Some(CellValue::Integer(chromosome)) => u8::try_from(chromosome)
+            .map(Some)
+            .map_err(|_| format!("Chromosome {} must be between 0 and 255", chromosome)),
+        cell => Err(format!("Chromosome must be an integer. Found '{:?}'.", cell)),
+    }
+}
+
+/// The gzip level of the index, which is the level of gzip compressed blocks unless it is set separately
+fn index_compression_level(dataset: &Dataset) -> Option<u32> {
+    // The levels of other block algorithms, such as zstd's 1 to 22, don't apply to gzip
+    let block_level = dataset.compression_level.filter(|_| dataset.compression_algorithm == CompressionAlgorithm::Gzip);
+    dataset.index_compression_level.or(block_level)
+}
+
+/// The compressed bytes of a block that is yet to be appended to its table
+struct CompressedBlock {
+    bytes: Vec<u8>,
+    uncompressed_size: usize,
+}
+
+/// Writes the blocks of a table one at a time, keeping track of their index entries until the index is written
+struct TableWriter {
+    chromosome: u8,
+    ptr_to_index_location: usize,
+    offset_table_start: usize,
+    table_start: Instant,
+    /// Map of position (first column) to offset in the file
+    position_indices: IndicesList,
+    end_indices: EndIndicesList,
+    num_rows: usize,
+    max_position: Option<usize>,
+}
+
+impl TableWriter {
+    fn new(chromosome: u8, ptr_to_index_location: usize, offset_table_start: usize) -> Self {
+        Self {
+            chromosome,
+            ptr_to_index_location,
+            offset_table_start,
+            table_start: Instant::now(),
+            position_indices: Vec::new(),
+            end_indices: Vec::new(),
+            num_rows: 0,
+            max_position: None,
+        }
+    }
+
+    fn write_block(&mut self, database: &Database, bytes: &mut Vec<u8>, dataset: &Dataset, chunk: &[Row]) -> Result<(), String> {
+        let compressed_block = Self::compress_block(database, dataset, chunk, self.position_indices.len())?;
+        self.append_block(database, bytes, dataset, chunk, compressed_block)
+    }
+
+    /// Serialize and compress the rows of a block into its own buffer, without appending it, so blocks can be compressed
+    /// in parallel
+    fn compress_block(database: &Database, dataset: &Dataset, chunk: &[Row], i_block: usize) -> Result<CompressedBlock, String> {
+        let mut row_compressor = RowCompressor::new();
+        database.serialize_dataset_block(&mut row_compressor.buffer, dataset, chunk, i_block)?;
+
+        let mut bytes = Vec::new();
+        row_compressor.compress(dataset.compression_algorithm, dataset.compression_level, &mut bytes).map_err(|e| e.to_string())?;
+
+        Ok(CompressedBlock {
+            bytes,
+            uncompressed_size: row_compressor.buffer.len(),
+        })
+    }
+
+    /// Append a compressed block after the previous block and add its index entries
+    fn append_block(&mut self, database: &Database, bytes: &mut Vec<u8>, dataset: &Dataset, chunk: &[Row], compressed_block: CompressedBlock) -> Result<(), String> {
+        let i_block = self.position_indices.len();
+
+        // Pad the previous block, so this block starts at a multiple of the alignment
+        if dataset.block_alignment > 1 {
+            let padding = (dataset.block_alignment - bytes.len() % dataset.block_alignment) % dataset.block_alignment;
+            bytes.resize(bytes.len() + padding, 0);
+        }
+
+        let offset_block_start = bytes.len();
+        let (first_position, last_position) = match (chunk.first(), chunk.last()) {
+            (Some(first), Some(last)) => match (first.first(), last.first()) {
+                (Some(CellValue::Integer(first)), Some(CellValue::Integer(last))) => (*first as usize, *last),
+                _ => return Err("First cell of the first and last row must be an integer".to_string()),
+            },
+            _ => return Err("Table must have at least one row".to_string()),
+        };
+
+        let compressed_size = compressed_block.bytes.len();
+        bytes.extend_from_slice(&compressed_block.bytes);
+
+        if !database.progress_json {
+            println!("Block {} ({} rows) compressed from {} to {}", i_block, chunk.len(), compressed_block.uncompressed_size, compressed_size);
+        }
+
+        let checksum = block_checksum(&compressed_block.bytes);
+        self.position_indices.push((first_position, offset_block_start, compressed_size, chunk.len(), checksum));
+
+        // The end position is the second column of interval datasets
+        if dataset.end_index {
+            let ends = chunk.iter().map(|row| match row.get(1) {
+                Some(CellValue::Integer(end)) => usize::try_from(*end)
+                    .map_err(|_| format!("End position must be a positive integer (got {})", end)),
+                _ => Err("Second cell of a row of an interval dataset must be an integer".to_string()),
+            }).collect::<Result<Vec<usize>, String>>()?;
+
+            let min_end = ends.iter().copied().min().unwrap_or(0);
+            let max_end = ends.iter().copied().max().unwrap_or(0);
+            self.end_indices.push((min_end, max_end, offset_block_start));
+        }
+        self.num_rows += chunk.len();
+        self.max_position = Some(usize::try_from(last_position)
+            .map_err(|_| format!("Position must be a positive integer (got {} as the maximum position)", last_position))?);
+
+        Ok(())
+    }
+
+    /// Write the index of the table after its blocks and point the header to it
+    fn finish(self, database: &Database, bytes: &mut Vec<u8>, dataset: &Dataset) -> Result<(), String> {
+        let max_position = self.max_position
+            .ok_or_else(|| format!("Table of chromosome {} must have at least one row", self.chromosome))?;
+
+        // Update the location of the index in the header
+        let index_offset = bytes.len();
+        let index_size = 8;
+        bytes.splice(self.ptr_to_index_location..self.ptr_to_index_location + index_size, (index_offset as u64).to_be_bytes());
+
+        let num_blocks = self.position_indices.len();
+        let offset_first_block = self.position_indices[0].1;
+        database.serialize_table_index(bytes, dataset, self.position_indices, self.end_indices, max_position)?;
+
+        // Checksum of everything from the first block to the end of the index, so a table can be verified at once
+        let checksum = table_checksum(&bytes[offset_first_block..]).map_err(|e| e.to_string())?;
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+
+        if database.progress_json {
+            eprintln!("{}", serde_json::json!({
+                "dataset": dataset.metadata.as_ref().unwrap().name,
+                "chromosome": self.chromosome,
+                "rows": self.num_rows,
+                "blocks": num_blocks,
+                "bytes": bytes.len() - self.offset_table_start,
+                "elapsed_ms": self.table_start.elapsed().as_millis() as u64,
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+/// The size of the sampled blocks of a dataset after compressing them with an algorithm, and the time it took
+#[derive(Debug)]
+pub struct CompressionSample {
+    pub algorithm: CompressionAlgorithm,
+    pub rows: usize,
+    pub uncompressed_size: usize,
+    pub compressed_size: usize,
+    pub duration: Duration,
+}
+
+impl CompressionSample {
+    /// The uncompressed size divided by the compressed size, which is higher for better compression
+    pub fn ratio(&self) -> f64 {
+        self.uncompressed_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+/// The differences between two databases with the same schema
+#[derive(Debug, Default)]
+pub struct DatabaseDiff {
+    /// Tables that contain at least one difference
+    pub tables: Vec<TableDiff>,
+}
+
+impl DatabaseDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct TableDiff {
+    pub dataset: String,
+    pub chromosome: u8,
+    /// Rows that are only present in the first database
+    pub only_in_a: Vec<Row>,
+    /// Rows that are only present in the second database
+    pub only_in_b: Vec<Row>,
+    /// Cells that differ between rows with the same position
+    pub changed_cells: Vec<CellDiff>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.changed_cells.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct CellDiff {
+    pub position: u64,
+    pub column: String,
+    pub a: CellValue,
+    pub b: CellValue,
+}
+
+/// Reads the rows of a table block by block, grouped by position
+struct TableRowStream {
+    reader: RowReader<BufReader<File>>,
+    blocks: std::vec::IntoIter<BlockBounds>,
+    rows: VecDeque<Row>,
+    compressed: Vec<u8>,
+    decompressed: Vec<u8>,
+}
+
+impl TableRowStream {
+    fn open(path: &Path, dataset: &DatasetHeader, chromosome: u8) -> std::io::Result<Option<Self>> {
+        let table = match dataset.tables.iter().find(|table| table.chromosome == chromosome) {
+            Some(table) => table,
+            None => return Ok(None),
+        };
+
+        let index = DatabaseQueryClient::new(File::open(path)?).read_table_index(table)?;
+        let mut reader = RowReader::new(BufReader::new(File::open(path)?), dataset, index);
+
+        let blocks = reader.get_blocks(0, u64::MAX);
+        if let Some(block) = blocks.first() {
+            reader.seek_to_block(*block)?;
+        }
+
+        Ok(Some(Self {
+            reader,
+            blocks: blocks.into_iter(),
+            rows: VecDeque::new(),
+            compressed: Vec::new(),
+            decompressed: Vec::new(),
+        }))
+    }
+
+    fn peek(&mut self) -> std::io::Result<Option<&Row>> {
+        while self.rows.is_empty() {
+            let block = match self.blocks.next() {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+
+            let mut rows = Vec::new();
+            self.reader.read_block(block, 0, u64::MAX, &mut self.compressed, &mut self.decompressed, &mut rows)?;
+            self.rows.extend(rows);
+        }
+
+        Ok(self.rows.front())
+    }
+
+    /// Read all rows with the next position
+    fn next_group(&mut self) -> std::io::Result<Option<(u64, Vec<Row>)>> {
+        let position = match self.peek()? {
+            Some(row) => row_position(row)?,
+            None => return Ok(None),
+        };
+
+        let mut group = Vec::new();
+        while let Some(row) = self.peek()? {
+            if row_position(row)? != position {
+                break;
+            }
+            group.push(self.rows.pop_front().unwrap());
+        }
+
+        Ok(Some((position, group)))
+    }
+}
+
+fn row_position(row: &Row) -> std::io::Result<u64> {
+    match row.first() {
+        Some(CellValue::Integer(i)) => u64::try_from(*i).map_err(|_| std::io::Error::new(
+            std::io::ErrorKind::InvalidData, format!("Invalid negative position {}", i),
+        )),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "First column must be an integer")),
+    }
+}
+
+impl Database {
+    /// Compare two databases with the same schema row by row.
+    /// 
+    /// The tables of both databases are read block by block and merged by position. Rows with the same position are
+    /// paired up after removing identical rows, and the cells of each pair that differ are reported. Rows without a
+    /// counterpart in the other database are reported as only present in one of them. Float cells are compared with
+    /// `tolerance`.
+    pub fn diff(a: &Path, b: &Path, tolerance: FloatTolerance) -> std::io::Result<DatabaseDiff> {
+        let header_a = DatabaseQueryClient::new(File::open(a)?).read_database_header()?;
+        let header_b = DatabaseQueryClient::new(File::open(b)?).read_database_header()?;
+
+        let schema_mismatch = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+        if header_a.datasets.len() != header_b.datasets.len() {
+            return Err(schema_mismatch(format!(
+                "Databases have a different number of datasets ({} and {})",
+                header_a.datasets.len(), header_b.datasets.len(),
+            )));
+        }
+
+        let mut diff = DatabaseDiff::default();
+
+        for dataset_a in &header_a.datasets {
+            let dataset_b = header_b.datasets.iter().find(|dataset| dataset.name == dataset_a.name)
+                .ok_or_else(|| schema_mismatch(format!("Dataset '{}' is missing from the second database", dataset_a.name)))?;
+
+            let same_columns = dataset_a.columns.len() == dataset_b.columns.len()
+                && dataset_a.columns.iter().zip(&dataset_b.columns).all(|(a, b)| a.name == b.name && a.type_ == b.type_);
+            if !same_columns {
+                return Err(schema_mismatch(format!("Dataset '{}' has different columns in both databases", dataset_a.name)));
+            }
+
+            let mut chromosomes: Vec<u8> = dataset_a.tables.iter().chain(&dataset_b.tables).map(|table| table.chromosome).collect();
+            chromosomes.sort();
+            chromosomes.dedup();
+
+            for chromosome in chromosomes {
+                let stream_a = TableRowStream::open(a, dataset_a, chromosome)?;
+                let stream_b = TableRowStream::open(b, dataset_b, chromosome)?;
+
+                let table_diff = Self::diff_table(dataset_a, chromosome, stream_a, stream_b, tolerance)?;
+                if !table_diff.is_empty() {
+                    diff.tables.push(table_diff);
+                }
+            }
+        }
+
+        Ok(diff)
+    }
+
+    fn diff_table(
+        dataset: &DatasetHeader,
+        chromosome: u8,
+        mut stream_a: Option<TableRowStream>,
+        mut stream_b: Option<TableRowStream>,
+        tolerance: FloatTolerance,
+    ) -> std::io::Result<TableDiff> {
+        let mut diff = TableDiff {
+            dataset: dataset.name.clone(),
+            chromosome,
+            only_in_a: Vec::new(),
+            only_in_b: Vec::new(),
+            changed_cells: Vec::new(),
+        };
+
+        let next_group = |stream: &mut Option<TableRowStream>| match stream {
+            Some(stream) => stream.next_group(),
+            None => Ok(None),
+        };
+
+        let mut group_a = next_group(&mut stream_a)?;
+        let mut group_b = next_group(&mut stream_b)?;
+
+        loop {
+            match (group_a.take(), group_b.take()) {
+                (None, None) => break,
+                (Some((_, rows_a)), None) => {
+                    diff.only_in_a.extend(rows_a);
+                    group_a = next_group(&mut stream_a)?;
+                },
+                (None, Some((_, rows_b))) => {
+                    diff.only_in_b.extend(rows_b);
+                    group_b = next_group(&mut stream_b)?;
+                },
+                (Some((position_a, rows_a)), Some((position_b, rows_b))) => {
+                    if position_a < position_b {
+                        diff.only_in_a.extend(rows_a);
+                        group_a = next_group(&mut stream_a)?;
+                        group_b = Some((position_b, rows_b));
+                    } else if position_b < position_a {
+                        diff.only_in_b.extend(rows_b);
+                        group_a = Some((position_a, rows_a));
+                        group_b = next_group(&mut stream_b)?;
+                    } else {
+                        Self::diff_rows(dataset, position_a, rows_a, rows_b, tolerance, &mut diff);
+                        group_a = next_group(&mut stream_a)?;
+                        group_b = next_group(&mut stream_b)?;
+                    }
+                },
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Compare rows with the same position
+    fn diff_rows(
+        dataset: &DatasetHeader,
+        position: u64,
+        rows_a: Vec<Row>,
+        mut rows_b: Vec<Row>,
+        tolerance: FloatTolerance,
+        diff: &mut TableDiff,
+    ) {
+        // Rows that are present in both databases are not a difference, regardless of their order
+        let mut unmatched_a = Vec::new();
+        for row_a in rows_a {
+            match rows_b.iter().position(|row_b| tolerance.rows_equal(row_b, &row_a)) {
+                Some(i) => { rows_b.remove(i); },
+                None => unmatched_a.push(row_a),
+            }
+        }
+
+        let num_pairs = unmatched_a.len().min(rows_b.len());
+        let extra_b = rows_b.split_off(num_pairs);
+        let extra_a = unmatched_a.split_off(num_pairs);
+
+        for (row_a, row_b) in unmatched_a.into_iter().zip(rows_b) {
+            for ((column, cell_a), cell_b) in dataset.columns.iter().zip(row_a).zip(row_b) {
+                if !tolerance.cells_equal(&cell_a, &cell_b) {
+                    diff.changed_cells.push(CellDiff {
+                        position,
+                        column: column.name.clone(),
+                        a: cell_a,
+                        b: cell_b,
+                    });
+                }
+            }
+        }
+
+        diff.only_in_a.extend(extra_a);
+        diff.only_in_b.extend(extra_b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_config, peak_allocated_bytes, row_reader, write_files, TestDatabase};
+
+    const CONFIG: &str = r#"
+        [datasets.scores]
+        file_per_chromosome = true
+        chromosomes = [1]
+        path = "chr{chromosome}.tsv"
+        rows_per_index = 4
+        compression_algorithm = "gzip"
+        columns = [
+            { name = "pos", type = "integer", role = "position" },
+            { name = "score", type = "float" },
+        ]
+    "#;
+
+    fn scores_tsv(rows: i64) -> String {
+        let mut tsv = String::from("pos\tscore\n");
+        for i in 0..rows {
+            tsv.push_str(&format!("{}\t{}\n", i * 7, i as f64 / 4.0));
+        }
+        tsv
+    }
+
+    fn positions(rows: &[Vec<CellValue>]) -> Vec<i64> {
+        rows.iter().map(|row| match row[0] {
+            CellValue::Integer(position) => position,
+            ref cell => panic!("{:?}", cell),
+        }).collect()
+    }
+
+    #[test]
+    fn saving_over_an_open_database_keeps_it_readable_and_the_new_rows_are_queryable_immediately() {
+        let database = TestDatabase::build(CONFIG, &[("chr1.tsv", &scores_tsv(10))]);
+        let mut old_reader = row_reader(&database, "scores", 1);
+
+        std::fs::write(database.dir.path().join("chr1.tsv"), scores_tsv(20)).unwrap();
+        Database::new(database.path.clone(), load_config(&database.dir, CONFIG)).save().unwrap();
+
+        let new_rows = row_reader(&database, "scores", 1).query_range(70, 140).unwrap();
+        assert_eq!(positions(&new_rows), (10..20).map(|i| i * 7).collect::<Vec<i64>>());
+
+        // The old reader still reads the database it opened, not a mix of the old and the new one
+        let old_rows = old_reader.query_range(0, 140).unwrap();
+        assert_eq!(positions(&old_rows), (0..10).map(|i| i * 7).collect::<Vec<i64>>());
+
+        assert!(!database.dir.path().join("test.zygosdb.tmp").exists());
+    }
+
+    #[test]
+    fn saving_does_not_overwrite_a_file_that_is_not_a_database() {
+        let database = TestDatabase::build(CONFIG, &[("chr1.tsv", &scores_tsv(10))]);
+        let path = database.dir.path().join("chr1.tsv");
+
+        let e = Database::new(path.clone(), load_config(&database.dir, CONFIG)).save().unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), scores_tsv(10));
+    }
+
+    #[test]
+    fn missing_value_error_names_the_file_chromosome_line_and_column() {
+        let dir = write_files(&[("chr1.tsv", "pos\tscore\n0\t0.5\n7\t\n")]);
+        let database = Database::new(dir.path().join("test.zygosdb"), load_config(&dir, CONFIG));
+
+        let e = database.load_datasets().err().unwrap();
+        assert!(e.contains(&format!("chromosome 1 '{}'", dir.path().join("chr1.tsv").display())), "{}", e);
+        assert!(e.contains("Line 3"), "{}", e);
+        assert!(e.contains("Missing value in column 'score'."), "{}", e);
+    }
+
+    #[test]
+    fn diff_pinpoints_the_row_that_differs_between_two_builds() {
+        let a = TestDatabase::build(CONFIG, &[("chr1.tsv", &scores_tsv(10))]);
+
+        // The same rows, with a different score at position 35
+        let changed = scores_tsv(10).replace("35\t1.25\n", "35\t9.5\n");
+        let b = TestDatabase::build(CONFIG, &[("chr1.tsv", &changed)]);
+
+        let diff = Database::diff(&a.path, &b.path, FloatTolerance::EXACT).unwrap();
+        assert_eq!(diff.tables.len(), 1);
+        let table = &diff.tables[0];
+        assert_eq!((table.dataset.as_str(), table.chromosome), ("scores", 1));
+        assert!(table.only_in_a.is_empty() && table.only_in_b.is_empty());
+        assert_eq!(table.changed_cells.len(), 1);
+        let cell = &table.changed_cells[0];
+        assert_eq!((cell.position, cell.column.as_str()), (35, "score"));
+        assert_eq!((&cell.a, &cell.b), (&CellValue::Float(1.25), &CellValue::Float(9.5)));
+
+        // One more row at the end of an otherwise identical table
+        let c = TestDatabase::build(CONFIG, &[("chr1.tsv", &scores_tsv(11))]);
+
+        let diff = Database::diff(&a.path, &c.path, FloatTolerance::EXACT).unwrap();
+        assert_eq!(diff.tables.len(), 1);
+        assert!(diff.tables[0].only_in_a.is_empty() && diff.tables[0].changed_cells.is_empty());
+        assert_eq!(positions(&diff.tables[0].only_in_b), [70]);
+
+        assert!(Database::diff(&a.path, &a.path, FloatTolerance::EXACT).unwrap().is_empty());
+    }
+
+    #[test]
+    fn diff_ignores_float_differences_within_the_tolerance() {
+        let a = TestDatabase::build(CONFIG, &[("chr1.tsv", "pos\tscore\n10\t0.01\n20\t0.5\n")]);
+        let b = TestDatabase::build(CONFIG, &[("chr1.tsv", "pos\tscore\n10\t0.0100001\n20\t0.5\n")]);
+
+        let diff = Database::diff(&a.path, &b.path, FloatTolerance::EXACT).unwrap();
+        assert_eq!(diff.tables[0].changed_cells.len(), 1);
+        assert_eq!(diff.tables[0].changed_cells[0].position, 10);
+
+        let absolute = FloatTolerance { absolute: 1e-6, relative: 0.0 };
+        assert!(Database::diff(&a.path, &b.path, absolute).unwrap().is_empty());
+
+        let relative = FloatTolerance { absolute: 0.0, relative: 1e-4 };
+        assert!(Database::diff(&a.path, &b.path, relative).unwrap().is_empty());
+    }
+
+    #[test]
+    fn blocks_compressed_in_parallel_are_indexed_at_their_offsets() {
+        for block_alignment in [1, 64] {
+            let config = CONFIG.replace("rows_per_index = 4", &format!("rows_per_index = 7\nblock_alignment = {}", block_alignment));
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &scores_tsv(2000))]);
+            let mut reader = row_reader(&database, "scores", 1);
+
+            // Every block is found at its offset and matches its checksum, so no block was appended out of order
+            assert_eq!(reader.verify_checksums().unwrap(), 2000usize.div_ceil(7));
+
+            let blocks = reader.get_blocks(0, u64::MAX);
+            assert!(blocks.iter().all(|((position, offset), (next_position, next_offset))| {
+                position < next_position && offset < next_offset && offset % block_alignment == 0
+            }));
+
+            let rows = reader.query_range(0, u64::MAX).unwrap();
+            assert_eq!(positions(&rows), (0..2000).map(|i| i * 7).collect::<Vec<i64>>());
+        }
+    }
+
+    #[test]
+    fn build_is_identical_with_and_without_the_parallel_feature() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1, 2, 3]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let database = TestDatabase::build(config, &[
+            ("chr1.tsv", &scores_tsv(10)),
+            ("chr2.tsv", &scores_tsv(20)),
+            ("chr3.tsv", &scores_tsv(30)),
+        ]);
+
+        let mut bytes = std::fs::read(&database.path).unwrap();
+        let header = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap();
+
+        // The build timestamp is the only part of the file that differs between builds
+        let timestamp = header.build_timestamp.to_be_bytes();
+        let at = bytes.windows(timestamp.len()).position(|window| window == timestamp).unwrap();
+        bytes[at..at + timestamp.len()].fill(0);
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&bytes);
+
+        // Compared with the output of a build with the `parallel` feature, as this test also runs without it
+        assert_eq!((bytes.len(), crc.sum()), (1009, 463492302));
+    }
+
+    fn genes_config(compression_algorithm: &str) -> String {
+        r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "{compression_algorithm}"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "volatile-string" },
+            ]
+        "#.replace("{compression_algorithm}", compression_algorithm)
+    }
+
+    #[test]
+    fn uncompressed_dataset_stores_blocks_as_is() {
+        let tsv: String = std::iter::once("pos\tgene\n".to_string())
+            .chain((0..20).map(|i| format!("{}\tgene-{:02}\n", i * 10, i)))
+            .collect();
+
+        let database = TestDatabase::build(&genes_config("none"), &[("chr1.tsv", &tsv)]);
+        let bytes = std::fs::read(&database.path).unwrap();
+
+        // Every block contains the strings of its rows as they are
+        let mut reader = row_reader(&database, "genes", 1);
+        assert_eq!(reader.compression_algorithm(), CompressionAlgorithm::None);
+
+        let blocks = reader.get_blocks(0, u64::MAX);
+        assert_eq!(blocks.len(), 5);
+
+        for (i, ((_, offset), (_, offset_end))) in blocks.into_iter().enumerate() {
+            let block = &bytes[offset as usize..offset_end as usize];
+            for gene in (i * 4..i * 4 + 4).map(|i| format!("gene-{:02}", i)) {
+                assert!(block.windows(gene.len()).any(|window| window == gene.as_bytes()), "{} not in block {}", gene, i);
+            }
+        }
+
+        let rows = reader.query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows.len(), 20);
+        assert_eq!(rows[13], vec![CellValue::Integer(130), CellValue::String("gene-13".into())]);
+
+        // The same strings are not found in a database with compressed blocks
+        let compressed = TestDatabase::build(&genes_config("gzip"), &[("chr1.tsv", &tsv)]);
+        let bytes = std::fs::read(&compressed.path).unwrap();
+        assert!(!bytes.windows(7).any(|window| window == b"gene-13"));
+        assert_eq!(row_reader(&compressed, "genes", 1).query_range(0, u64::MAX).unwrap(), rows);
+    }
+
+    #[test]
+    fn compression_algorithm_is_read_from_the_file() {
+        let config = r#"
+            [datasets.gzipped]
+            file_per_chromosome = true
+            chromosomes = [1, 2]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+
+            [datasets.lz4]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "lz4"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv = "pos\tscore\n1\t0.5\n5\t1.5\n9\t2.5\n13\t3.5\n17\t4.5\n";
+        let database = TestDatabase::build(config, &[("chr1.tsv", tsv), ("chr2.tsv", tsv)]);
+
+        // Only the database is kept, without the config and the files it was built from
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("copy.zygosdb");
+        std::fs::copy(&database.path, &path).unwrap();
+        drop(database);
+
+        let mut client = DatabaseQueryClient::new(File::open(&path).unwrap());
+        let header = client.read_database_header().unwrap();
+
+        for (name, compression_algorithm, num_tables) in [("gzipped", CompressionAlgorithm::Gzip, 2), ("lz4", CompressionAlgorithm::LZ4, 1)] {
+            let dataset = header.datasets.iter().find(|dataset| dataset.name == name).unwrap();
+            assert_eq!(dataset.compression_algorithm, compression_algorithm);
+            assert_eq!(dataset.tables.len(), num_tables);
+
+            for table in &dataset.tables {
+                let index = client.read_table_index(table).unwrap();
+                let mut reader = RowReader::new(File::open(&path).unwrap(), dataset, index);
+                assert_eq!(reader.compression_algorithm(), compression_algorithm);
+
+                let rows = reader.query_range(0, u64::MAX).unwrap();
+                assert_eq!(rows.len(), 5);
+                assert_eq!(rows[4], vec![CellValue::Integer(17), CellValue::Float(4.5)]);
+            }
+        }
+    }
+
+    #[test]
+    fn zstd_dataset_round_trips_through_save_and_the_header() {
+        let config = CONFIG.replace(r#"compression_algorithm = "gzip""#, r#"compression_algorithm = "zstd""#);
+        let database = TestDatabase::build(&config, &[("chr1.tsv", &scores_tsv(100))]);
+
+        let header = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap();
+        assert_eq!(header.datasets[0].compression_algorithm, CompressionAlgorithm::Zstd);
+
+        let mut reader = row_reader(&database, "scores", 1);
+        assert_eq!(reader.compression_algorithm(), CompressionAlgorithm::Zstd);
+
+        let rows = reader.query_range(0, u64::MAX).unwrap();
+        assert_eq!(positions(&rows), (0..100).map(|i| i * 7).collect::<Vec<_>>());
+        assert_eq!(rows[42][1], CellValue::Float(10.5));
+    }
+
+    #[test]
+    fn compression_level_trades_size_for_speed_and_round_trips() {
+        let tsv: String = std::iter::once("pos\tgene\n".to_string())
+            .chain((0..2000).map(|i| format!("{}\tgene-{}\n", i * 10, i % 37)))
+            .collect();
+
+        let build = |compression_algorithm: &str, compression_level: Option<u32>| {
+            let mut config = genes_config(compression_algorithm).replace("rows_per_index = 4", "rows_per_index = 500");
+            if let Some(compression_level) = compression_level {
+                config = config.replace(
+                    "rows_per_index = 500",
+                    &format!("rows_per_index = 500\ncompression_level = {}", compression_level),
+                );
+            }
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+            let size = std::fs::metadata(&database.path).unwrap().len();
+            let rows = row_reader(&database, "genes", 1).query_range(0, u64::MAX).unwrap();
+            (size, rows)
+        };
+
+        for (compression_algorithm, fast_level) in [("gzip", 1), ("zstd", 1)] {
+            let (default_size, default_rows) = build(compression_algorithm, None);
+            let (fast_size, fast_rows) = build(compression_algorithm, Some(fast_level));
+
+            assert!(fast_size > default_size, "{}: {} <= {}", compression_algorithm, fast_size, default_size);
+            assert_eq!(fast_rows, default_rows);
+            assert_eq!(fast_rows.len(), 2000);
+            assert_eq!(fast_rows[1234], vec![CellValue::Integer(12340), CellValue::String("gene-13".into())]);
+        }
+    }
+
+    #[test]
+    fn fixed_width_fields_are_big_endian_u64() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [3]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 2
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv = "pos\tscore\n10\t0.25\n300\t-2.5\n70000\t1e300\n";
+        let database = TestDatabase::build(config, &[("chr3.tsv", tsv)]);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let table = client.read_database_header().unwrap().datasets.remove(0).tables.remove(0);
+        let index = client.read_table_index(&table).unwrap();
+        let bytes = std::fs::read(&database.path).unwrap();
+
+        // The fields are compared with their big-endian bytes, which are the same on machines of either byte order
+        let u64_at = |offset: u64| -> [u8; 8] { bytes[offset as usize..offset as usize + 8].try_into().unwrap() };
+
+        // The table entry in the header: chromosome and offset of the index
+        let mut table_entry = vec![3];
+        table_entry.extend_from_slice(&table.offset.to_be_bytes());
+        assert!(bytes[..table.offset as usize].windows(table_entry.len()).any(|window| window == table_entry));
+
+        // The index starts with its magic, the maximum position and the end offset
+        let index_start = table.offset as usize;
+        assert_eq!(&bytes[index_start..index_start + INDEX_MAGIC.len()], INDEX_MAGIC);
+
+        let fields_start = table.offset + INDEX_MAGIC.len() as u64;
+        assert_eq!(u64_at(fields_start), 70000u64.to_be_bytes());
+        assert_eq!(u64_at(fields_start + 8), index.index_end_offset.to_be_bytes());
+
+        // Floats are stored as their big-endian IEEE 754 bytes
+        for score in [0.25f64, -2.5, 1e300] {
+            assert!(bytes.windows(8).any(|window| window == score.to_be_bytes()), "{}", score);
+        }
+
+        let rows = row_reader(&database, "scores", 3).query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows[2], vec![CellValue::Integer(70000), CellValue::Float(1e300)]);
+    }
+
+    #[test]
+    fn strings_longer_than_255_bytes_round_trip() {
+        let long_gene = "ACGT".repeat(100);
+        let tsv = format!("pos\tgene\n1\tshort\n2\t{}\n3\tx\n", long_gene);
+        let database = TestDatabase::build(&genes_config("lz4"), &[("chr1.tsv", &tsv)]);
+
+        let rows = row_reader(&database, "genes", 1).query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows, vec![
+            vec![CellValue::Integer(1), CellValue::String("short".into())],
+            vec![CellValue::Integer(2), CellValue::String(long_gene.into())],
+            vec![CellValue::Integer(3), CellValue::String("x".into())],
+        ]);
+    }
+
+    fn sorted_genes_tsv(num_rows: usize) -> String {
+        std::iter::once("pos\tgene\n".to_string())
+            .chain((0..num_rows).map(|i| format!("{}\tgene-{}\n", i * 3, i % 1000)))
+            .collect()
+    }
+
+    #[test]
+    fn pre_sorted_build_streams_rows_with_bounded_memory() {
+        let num_rows = 100_000;
+        let tsv = sorted_genes_tsv(num_rows);
+        let config = genes_config("gzip")
+            .replace("rows_per_index = 4", "rows_per_index = 256\npre_sorted = true");
+
+        let dir = write_files(&[("chr1.tsv", &tsv)]);
+        let config = load_config(&dir, &config);
+        let path = dir.path().join("test.zygosdb");
+
+        let (res, peak) = peak_allocated_bytes(|| Database::new(path.clone(), config).save());
+        res.unwrap();
+
+        // The cells of the rows alone would take more memory than the whole build did
+        let table_size = num_rows * 2 * std::mem::size_of::<CellValue>();
+        assert!(peak < table_size, "{} >= {}", peak, table_size);
+
+        let database = TestDatabase { dir, path };
+        let mut reader = row_reader(&database, "genes", 1);
+        let rows = reader.query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows.len(), num_rows);
+        assert_eq!(rows[54321], vec![CellValue::Integer(54321 * 3), CellValue::String("gene-321".into())]);
+        assert_eq!(positions(&reader.query_range(300, 312).unwrap()), vec![300, 303, 306, 309]);
+
+        // The blocks are the same as those of a build that loads and sorts the whole table
+        let sorted = TestDatabase::build(&genes_config("gzip").replace("rows_per_index = 4", "rows_per_index = 256"), &[("chr1.tsv", &tsv)]);
+        assert_eq!(row_reader(&sorted, "genes", 1).get_blocks(0, u64::MAX), reader.get_blocks(0, u64::MAX));
+    }
+
+    #[test]
+    fn pre_sorted_build_fails_on_a_position_out_of_order() {
+        let config = genes_config("none").replace("rows_per_index = 4", "rows_per_index = 4\npre_sorted = true");
+        let dir = write_files(&[("chr1.tsv", "pos\tgene\n1\ta\n5\tb\n5\tc\n3\td\n")]);
+        let config = load_config(&dir, &config);
+        let database = Database::new(dir.path().join("test.zygosdb"), config);
+        let dataset = &database.config.datasets["genes"];
+
+        // The header is left out, with a placeholder for the location of the index
+        let mut bytes = vec![0; 8];
+        let e = database.serialize_pre_sorted_table(&mut bytes, dataset, 1, &dir.path().join("chr1.tsv"), 0).unwrap_err();
+        assert!(e.contains("Position 3 comes after position 5, but the dataset is declared to be pre-sorted"), "{}", e);
+    }
+
+    #[test]
+    fn sampled_compression_reports_every_algorithm() {
+        let tsv: String = std::iter::once("pos\tgene\n".to_string())
+            .chain((0..1000).map(|i| format!("{}\tgene-{}\n", i * 10, i % 37)))
+            .collect();
+        let dir = write_files(&[("chr1.tsv", &tsv)]);
+        // Blocks need enough rows for the compression to outweigh the headers of the algorithms
+        let config = load_config(&dir, &genes_config("gzip").replace("rows_per_index = 4", "rows_per_index = 100"));
+        let database = Database::new(dir.path().join("test.zygosdb"), config);
+
+        let samples = database.sample_compression("genes", 200).unwrap();
+
+        let algorithms: Vec<_> = samples.iter().map(|sample| sample.algorithm).collect();
+        assert_eq!(algorithms, CompressionAlgorithm::ALL);
+
+        for sample in &samples {
+            assert_eq!(sample.rows, 200, "{:?}", sample);
+            assert!(sample.uncompressed_size > 0, "{:?}", sample);
+            if sample.algorithm == CompressionAlgorithm::None {
+                assert_eq!(sample.compressed_size, sample.uncompressed_size);
+            } else {
+                // The repetitive genes compress well with every algorithm
+                assert!(sample.ratio() > 1.0 && sample.ratio() < 100.0, "{:?} has ratio {}", sample, sample.ratio());
+            }
+        }
+
+        assert!(database.sample_compression("variants", 200).unwrap_err().contains("Dataset 'variants' not found"));
+    }
+
+    #[test]
+    fn single_file_is_split_into_a_table_per_chromosome() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = false
+            chromosomes = [1, 2]
+            path = "genes.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "volatile-string" },
+                { name = "chrom", type = "integer", role = "chromosome" },
+            ]
+        "#;
+        let tsv = "pos\tchrom\tgene\n30\t2\tCFTR\n10\t1\tBRCA2\n20\t2\tTP53\n5\t1\tBRCA1\n";
+        let database = TestDatabase::build(config, &[("genes.tsv", tsv)]);
+
+        let header = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap();
+        let dataset = &header.datasets[0];
+        let column_names: Vec<_> = dataset.columns.iter().map(|column| column.name.as_str()).collect();
+        assert_eq!(column_names, ["pos", "gene"]);
+        let chromosomes: Vec<_> = dataset.tables.iter().map(|table| table.chromosome).collect();
+        assert_eq!(chromosomes, [1, 2]);
+
+        let rows = |chromosome| row_reader(&database, "genes", chromosome).query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows(1), [
+            vec![CellValue::Integer(5), CellValue::String("BRCA1".into())],
+            vec![CellValue::Integer(10), CellValue::String("BRCA2".into())],
+        ]);
+        assert_eq!(rows(2), [
+            vec![CellValue::Integer(20), CellValue::String("TP53".into())],
+            vec![CellValue::Integer(30), CellValue::String("CFTR".into())],
+        ]);
+    }
+
+    #[test]
+    fn char_column_is_stored_in_one_byte_per_row_and_read_back() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "strand", type = "char", missing_value_policy = "ReplaceWithEmptyString" },
+            ]
+        "#;
+        let strands = ["+", "-", "-", "+", "", "+"];
+        let tsv: String = std::iter::once("pos\tstrand\n".to_string())
+            .chain(strands.iter().enumerate().map(|(i, strand)| format!("{}\t{}\n", i, strand)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+
+        let rows = row_reader(&database, "genes", 1).query_range(0, u64::MAX).unwrap();
+        let expected: Vec<_> = strands.iter().enumerate()
+            .map(|(i, strand)| vec![CellValue::Integer(i as i64), CellValue::String((*strand).into())])
+            .collect();
+        assert_eq!(rows, expected);
+
+        // A character takes one byte less than the same non-empty string with its length prefix
+        let block_sizes = |database: &TestDatabase| -> Vec<u64> {
+            row_reader(database, "genes", 1).get_blocks(0, u64::MAX).into_iter()
+                .map(|((_, offset), (_, offset_end))| offset_end - offset)
+                .collect()
+        };
+        let strings = TestDatabase::build(&config.replace(r#"type = "char""#, r#"type = "volatile-string""#), &[("chr1.tsv", &tsv)]);
+        let savings: Vec<_> = block_sizes(&strings).iter().zip(block_sizes(&database)).map(|(string, char)| string - char).collect();
+        assert_eq!(savings, [4, 1]);
+    }
+
+    #[test]
+    fn boolean_column_of_a_columnar_block_is_packed_into_bits() {
+        let config = |columnar: bool, with_flag: bool| format!(r#"
+            [datasets.flags]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{{chromosome}}.tsv"
+            rows_per_index = 1000
+            compression_algorithm = "none"
+            columnar = {}
+            columns = [
+                {{ name = "pos", type = "integer", role = "position" }},
+                {}
+            ]
+        "#, columnar, if with_flag { r#"{ name = "flag", type = "boolean" },"# } else { "" });
+
+        let flags: Vec<bool> = (0..1000).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+        let tsv: String = std::iter::once("pos\tflag\n".to_string())
+            .chain(flags.iter().enumerate().map(|(i, flag)| format!("{}\t{}\n", i, if *flag { "true" } else { "0" })))
+            .collect();
+
+        let block_size = |config: &str| -> (u64, Vec<Row>) {
+            let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+            let mut reader = row_reader(&database, "flags", 1);
+            let blocks = reader.get_blocks(0, u64::MAX);
+            assert_eq!(blocks.len(), 1);
+
+            let ((_, offset), (_, offset_end)) = blocks[0];
+            (offset_end - offset, reader.query_range(0, u64::MAX).unwrap())
+        };
+
+        let expected: Vec<Row> = flags.iter().enumerate()
+            .map(|(i, flag)| vec![CellValue::Integer(i as i64), CellValue::Integer(*flag as i64)])
+            .collect();
+
+        // 1000 rows take 125 bytes, and a byte for the length of the column
+        let (packed_size, rows) = block_size(&config(true, true));
+        assert_eq!(rows, expected);
+        let (positions_size, _) = block_size(&config(true, false));
+        assert_eq!(packed_size - positions_size, 125 + 1);
+
+        // Blocks with rows store a byte per row
+        let (unpacked_size, rows) = block_size(&config(false, true));
+        assert_eq!(rows, expected);
+        let (positions_size, _) = block_size(&config(false, false));
+        assert_eq!(unpacked_size - positions_size, 1000);
+    }
+
+    /// Two datasets that differ in every field of the header, one of which is in a single file
+    const HEADER_CONFIG: &str = r#"
+        assembly = "GRCh38"
+
+        [datasets.markers]
+        file_per_chromosome = false
+        chromosomes = [2, 1]
+        path = "markers.tsv"
+        rows_per_index = 3
+        compression_algorithm = "zstd"
+        index_compression_algorithm = "gzip"
+        position_decimals = 2
+        coordinate_base = 1
+        columnar = true
+        columns = [
+            { name = "cm", type = "float", role = "position" },
+            { name = "marker", type = "volatile-string" },
+            { name = "chr", type = "integer", role = "chromosome" },
+        ]
+
+        [datasets.genes]
+        file_per_chromosome = true
+        chromosomes = [3]
+        path = "chr{chromosome}.tsv"
+        rows_per_index = 64
+        compression_algorithm = "none"
+        coordinate_base = 0
+        columns = [
+            { name = "start", type = "integer", role = "position-start" },
+            { name = "end", type = "integer", role = "position-end" },
+            { name = "score", type = "float" },
+        ]
+    "#;
+
+    #[test]
+    fn header_round_trips_every_field_of_the_config() {
+        let markers = "cm\tmarker\tchr\n0.5\trs1\t1\n1.25\trs2\t2\n3.75\trs3\t1\n";
+        let genes = "start\tend\tscore\n100\t200\t0.5\n150\t400\t1.5\n";
+        let database = TestDatabase::build(HEADER_CONFIG, &[("markers.tsv", markers), ("chr3.tsv", genes)]);
+
+        let config = load_config(&database.dir, HEADER_CONFIG);
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let header = client.read_database_header().unwrap();
+
+        assert_eq!(header.version, HEADER_VERSION);
+        assert_eq!(header.build_version, env!("CARGO_PKG_VERSION"));
+        assert!(header.build_timestamp > 0);
+        assert_eq!(header.assembly, config.assembly);
+        assert_eq!(header.datasets.len(), config.datasets.len());
+
+        for (name, dataset) in &config.datasets {
+            let parsed = header.datasets.iter().find(|parsed| &parsed.name == name).unwrap();
+
+            assert_eq!(parsed.compression_algorithm, dataset.compression_algorithm);
+            assert_eq!(parsed.rows_per_index, dataset.rows_per_index as u64);
+            assert_eq!(parsed.position_decimals, dataset.position_decimals);
+            assert_eq!(parsed.coordinate_base, dataset.coordinate_base);
+            assert_eq!(parsed.columnar, dataset.columnar);
+
+            // The chromosome column isn't stored, and float positions are stored scaled to integers
+            let columns: Vec<(&str, ColumnType)> = parsed.columns.iter()
+                .map(|column| (column.name.as_str(), column.type_))
+                .collect();
+            let expected_columns: Vec<(&str, ColumnType)> = dataset.stored_columns().iter()
+                .map(|column| match column.role {
+                    ColumnRole::Position if dataset.position_decimals.is_some() => (column.name.as_str(), ColumnType::Integer),
+                    _ => (column.name.as_str(), column.type_),
+                })
+                .collect();
+            assert_eq!(columns, expected_columns);
+
+            let chromosomes: Vec<u8> = parsed.tables.iter().map(|table| table.chromosome).collect();
+            assert_eq!(chromosomes, dataset.table_chromosomes());
+
+            for table in &parsed.tables {
+                assert_eq!(table.index_compression_algorithm, dataset.index_compression_algorithm);
+                assert!(client.read_table_index(table).unwrap().num_rows() > 0);
+            }
+        }
     }
 }
@@ -1,4 +1,4 @@
-use std::io::{Cursor, Error, ErrorKind, Read};
+use std::{io::{Cursor, Error, ErrorKind, Read}, sync::Arc};
 
 #[inline]
 pub fn read_u64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u64> {
@@ -7,6 +7,13 @@ pub fn read_u64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u64> {
     Ok(u64::from_be_bytes(tmp))
 }
 
+#[inline]
+pub fn read_u32(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u32> {
+    let mut tmp = [0; size_of::<u32>()];
+    cursor.read_exact(&mut tmp)?;
+    Ok(u32::from_be_bytes(tmp))
+}
+
 #[inline]
 pub fn read_i64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<i64> {
     let mut tmp = [0; size_of::<i64>()];
@@ -14,6 +21,22 @@ pub fn read_i64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<i64> {
     Ok(i64::from_be_bytes(tmp))
 }
 
+#[inline]
+pub fn read_vint64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u64> {
+    let mut tmp = [0u8; 9];
+    cursor.read_exact(&mut tmp[0..1])?;
+    let len = vint64::decoded_len(tmp[0]);
+
+    cursor.read_exact(&mut tmp[1..len])?;
+    let mut slice = &tmp[..len];
+
+    vint64::decode(&mut slice)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!(
+            "Failed to decode vint64 (len={:?}, buf={:?}): {:?}",
+            len, tmp, e,
+        )))
+}
+
 #[inline]
 pub fn read_zigzag_i64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<(i64, usize)> {
     let mut tmp = [0u8; 9];
@@ -46,15 +69,122 @@ pub fn read_u8(cursor: &mut Cursor<&[u8]>) -> std::io::Result<u8> {
     Ok(tmp[0])
 }
 
+/// Read a string without allocating, borrowing it from the bytes of the cursor.
 #[inline]
-pub fn read_string_u8(cursor: &mut Cursor<&[u8]>) -> std::io::Result<String> {
-    let len = read_u8(cursor)? as usize;
-    let mut tmp = vec![0; len];
-    cursor.read_exact(&mut tmp)?;
-    Ok(String::from_utf8(tmp).map_err(|e| Error::new(ErrorKind::InvalidData, e))?)
+pub fn read_str_varlen<'a>(cursor: &mut Cursor<&'a [u8]>) -> std::io::Result<&'a str> {
+    let string = read_bytes_varlen(cursor)?;
+    std::str::from_utf8(string).map_err(|e| Error::new(ErrorKind::InvalidData, e))
 }
 
+/// Read the bytes of a string without checking that they are valid UTF-8. The length is checked against the remaining
+/// bytes before anything is read, so a corrupt length can't cause a huge allocation.
+#[inline]
+fn read_bytes_varlen<'a>(cursor: &mut Cursor<&'a [u8]>) -> std::io::Result<&'a [u8]> {
+    let len = read_vint64(cursor)?;
+    let start = cursor.position() as usize;
+    let bytes: &'a [u8] = cursor.get_ref();
 
+    let string = usize::try_from(len).ok()
+        .and_then(|len| bytes.get(start..start.checked_add(len)?))
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "String extends past the end of the block"))?;
+    cursor.set_position((start + string.len()) as u64);
+
+    Ok(string)
+}
+
+/// Read a string of which the length is written as a vint64 before it into a shared string, without copying it to an
+/// intermediate buffer.
+#[inline]
+pub fn read_shared_string_varlen(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Arc<str>> {
+    read_str_varlen(cursor).map(Arc::from)
+}
+
+/// Read a string of an interned column. New strings are added to the pool of the block, so later rows can reference them
+/// without allocating the string again.
+#[inline]
+pub fn read_interned_string(cursor: &mut Cursor<&[u8]>, pool: &mut Vec<Arc<str>>) -> std::io::Result<(Arc<str>, usize)> {
+    let tag_start = cursor.position();
+    let tag = read_vint64(cursor)?;
+    let tag_len = (cursor.position() - tag_start) as usize;
+
+    if tag == 0 {
+        let string = read_shared_string_varlen(cursor)?;
+        let len = (cursor.position() - tag_start) as usize;
+        pool.push(string.clone());
+        return Ok((string, len));
+    }
+
+    match pool.get(tag as usize - 1) {
+        Some(string) => Ok((string.clone(), tag_len)),
+        None => Err(Error::new(ErrorKind::InvalidData, format!(
+            "Interned string reference {} is out of bounds of the pool of size {}",
+            tag - 1, pool.len(),
+        ))),
+    }
+}
+
+/// Read a string of an interned column without allocating, borrowing it from the bytes of the cursor.
+#[inline]
+pub fn read_interned_str<'a>(cursor: &mut Cursor<&'a [u8]>, pool: &mut Vec<&'a str>) -> std::io::Result<&'a str> {
+    let tag = read_vint64(cursor)?;
+
+    if tag == 0 {
+        let string = read_str_varlen(cursor)?;
+        pool.push(string);
+        return Ok(string);
+    }
+
+    match pool.get(tag as usize - 1) {
+        Some(string) => Ok(string),
+        None => Err(Error::new(ErrorKind::InvalidData, format!(
+            "Interned string reference {} is out of bounds of the pool of size {}",
+            tag - 1, pool.len(),
+        ))),
+    }
+}
+
+/// Read the character of a char column, which is a single ASCII byte, or 0 if the value is empty.
+#[inline]
+pub fn read_char(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Arc<str>> {
+    read_char_str(cursor).map(Arc::from)
+}
+
+/// Read the character of a char column without allocating, borrowing it from the bytes of the cursor.
+#[inline]
+pub fn read_char_str<'a>(cursor: &mut Cursor<&'a [u8]>) -> std::io::Result<&'a str> {
+    let start = cursor.position() as usize;
+    let bytes: &'a [u8] = cursor.get_ref();
+
+    let char = bytes.get(start..start + 1)
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "Character extends past the end of the block"))?;
+    cursor.set_position(start as u64 + 1);
+
+    match char {
+        [0] => Ok(""),
+        [byte] if byte.is_ascii() => Ok(std::str::from_utf8(char).expect("ASCII is valid UTF-8")),
+        [byte] => Err(Error::new(ErrorKind::InvalidData, format!("Character {:#04x} is not ASCII", byte))),
+        _ => unreachable!(),
+    }
+}
+
+/// Read the value of a boolean column of a block with rows, which is one byte that is 0 or 1.
+#[inline]
+pub fn read_bool(cursor: &mut Cursor<&[u8]>) -> std::io::Result<bool> {
+    match read_u8(cursor)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        byte => Err(Error::new(ErrorKind::InvalidData, format!("Boolean {:#04x} is not 0 or 1", byte))),
+    }
+}
+
+/// Read the value of a row of a boolean column of a columnar block, where the values are packed into bits. The value
+/// of row `i` is bit `i % 8` of byte `i / 8`.
+#[inline]
+pub fn read_packed_bool(bytes: &[u8], i_row: usize) -> std::io::Result<bool> {
+    let byte = bytes.get(i_row / 8)
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, format!("Boolean of row {} extends past the end of the column", i_row)))?;
+    Ok(byte >> (i_row % 8) & 1 == 1)
+}
 
 #[inline]
 pub fn skip_zigzag_i64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<usize> {
@@ -74,9 +204,116 @@ pub fn skip_f64(cursor: &mut Cursor<&[u8]>) -> std::io::Result<usize> {
 }
 
 #[inline]
-pub fn skip_string_u8(cursor: &mut Cursor<&[u8]>) -> std::io::Result<usize> {
-    let len = read_u8(cursor)? as usize;
-    let mut tmp = [0; u8::MAX as usize];
-    cursor.read_exact(&mut tmp[0..len])?;
-    Ok(1 + len)
+pub fn skip_string_varlen(cursor: &mut Cursor<&[u8]>) -> std::io::Result<usize> {
+    let start = cursor.position();
+    read_bytes_varlen(cursor)?;
+    Ok((cursor.position() - start) as usize)
+}
+
+#[inline]
+pub fn skip_char(cursor: &mut Cursor<&[u8]>) -> std::io::Result<usize> {
+    read_u8(cursor)?;
+    Ok(size_of::<u8>())
+}
+
+#[inline]
+pub fn skip_bool(cursor: &mut Cursor<&[u8]>) -> std::io::Result<usize> {
+    read_bool(cursor)?;
+    Ok(size_of::<u8>())
+}
+
+#[inline]
+pub fn skip_interned_string(cursor: &mut Cursor<&[u8]>, pool: &mut Vec<Arc<str>>) -> std::io::Result<usize> {
+    // New strings can't be skipped, as later rows may reference them
+    let (_, len) = read_interned_string(cursor, pool)?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::count_allocations;
+
+    /// Serialize a new string of an interned column
+    fn new_interned_string(bytes: &mut Vec<u8>, string: &str) {
+        bytes.extend_from_slice(vint64::encode(0).as_ref());
+        bytes.extend_from_slice(vint64::encode(string.len() as u64).as_ref());
+        bytes.extend_from_slice(string.as_bytes());
+    }
+
+    /// Serialize a reference to a string in the pool of an interned column
+    fn interned_string_reference(bytes: &mut Vec<u8>, pool_index: u64) {
+        bytes.extend_from_slice(vint64::encode(pool_index + 1).as_ref());
+    }
+
+    #[test]
+    fn interned_strings_are_allocated_once_per_block() {
+        let mut bytes = Vec::new();
+        new_interned_string(&mut bytes, "BRCA2");
+        interned_string_reference(&mut bytes, 0);
+        new_interned_string(&mut bytes, "CFTR");
+        interned_string_reference(&mut bytes, 0);
+        interned_string_reference(&mut bytes, 1);
+        interned_string_reference(&mut bytes, 0);
+
+        let mut cursor = Cursor::new(&bytes[..]);
+        let mut pool: Vec<Arc<str>> = Vec::with_capacity(2);
+        let mut strings: Vec<Arc<str>> = Vec::with_capacity(6);
+
+        let (res, allocations) = count_allocations(|| {
+            for _ in 0..6 {
+                let (string, _) = read_interned_string(&mut cursor, &mut pool)?;
+                strings.push(string);
+            }
+            std::io::Result::Ok(())
+        });
+        res.unwrap();
+
+        assert_eq!(allocations, 2);
+        assert_eq!(strings.iter().map(|s| &**s).collect::<Vec<_>>(), ["BRCA2", "BRCA2", "CFTR", "BRCA2", "CFTR", "BRCA2"]);
+        assert!(Arc::ptr_eq(&strings[0], &strings[5]));
+        assert_eq!(cursor.position() as usize, bytes.len());
+    }
+
+    #[test]
+    fn interned_string_reference_out_of_the_pool_is_invalid_data() {
+        let mut bytes = Vec::new();
+        new_interned_string(&mut bytes, "BRCA2");
+        interned_string_reference(&mut bytes, 1);
+
+        let mut cursor = Cursor::new(&bytes[..]);
+        let mut pool = Vec::new();
+        read_interned_string(&mut cursor, &mut pool).unwrap();
+
+        let e = read_interned_string(&mut cursor, &mut pool).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn strings_longer_than_a_byte_of_length_are_read_whole() {
+        let long = "ACGT".repeat(1000);
+        let mut bytes = vint64::encode(long.len() as u64).as_ref().to_vec();
+        bytes.extend_from_slice(long.as_bytes());
+        bytes.extend_from_slice(vint64::encode(2).as_ref());
+        bytes.extend_from_slice(b"ok");
+
+        let mut cursor = Cursor::new(&bytes[..]);
+        assert_eq!(read_str_varlen(&mut cursor).unwrap(), long);
+        assert_eq!(&*read_shared_string_varlen(&mut cursor).unwrap(), "ok");
+        assert_eq!(cursor.position() as usize, bytes.len());
+
+        let mut cursor = Cursor::new(&bytes[..]);
+        assert_eq!(skip_string_varlen(&mut cursor).unwrap(), 2 + long.len());
+    }
+
+    #[test]
+    fn string_length_past_the_end_of_the_block_is_unexpected_eof() {
+        for len in [5, u64::MAX >> 8] {
+            let mut bytes = vint64::encode(len).as_ref().to_vec();
+            bytes.extend_from_slice(b"abcd");
+
+            let e = read_str_varlen(&mut Cursor::new(&bytes[..])).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        }
+    }
 }
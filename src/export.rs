@@ -0,0 +1,207 @@
+use std::{fs::File, io::{Read, Seek}, path::Path, sync::Arc};
+
+use arrow::{array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder}, datatypes::{DataType, Field, Schema, SchemaRef}, error::ArrowError, record_batch::RecordBatch};
+use parquet::{arrow::ArrowWriter, basic::{Compression, ZstdLevel}, file::properties::WriterProperties};
+
+use crate::{query::{ColumnHeader, Row, RowReader}, tsv_reader::{CellValue, ColumnType}};
+
+/// Write all rows of the table of a row reader to a Parquet file, one block at a time, so the table doesn't have to fit in
+/// memory. Returns the number of rows that were written.
+pub fn export_table_parquet<R: Read + Seek>(row_reader: &mut RowReader<R>, path: &Path) -> Result<u64, String> {
+    let schema = arrow_schema(row_reader.columns(), row_reader.position_decimals());
+
+    let file = File::create(path).map_err(|e| format!("Failed to create '{}': {}", path.display(), e))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .build();
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(properties)).map_err(|e| e.to_string())?;
+
+    let blocks = row_reader.get_blocks(0, u64::MAX);
+    if let Some(block) = blocks.first() {
+        row_reader.seek_to_block(*block).map_err(|e| e.to_string())?;
+    }
+
+    let mut compressed: Vec<u8> = Vec::new();
+    let mut decompressed: Vec<u8> = Vec::new();
+    let mut rows: Vec<Row> = Vec::new();
+    let mut num_rows = 0;
+
+    for block in blocks {
+        rows.clear();
+        row_reader.read_block(block, 0, u64::MAX, &mut compressed, &mut decompressed, &mut rows)
+            .map_err(|e| format!("Failed to read the block at offset {}: {}", block.0.1, e))?;
+
+        let batch = rows_to_record_batch(schema.clone(), &rows, row_reader.position_decimals()).map_err(|e| e.to_string())?;
+        writer.write(&batch).map_err(|e| e.to_string())?;
+        num_rows += rows.len() as u64;
+    }
+
+    writer.close().map_err(|e| e.to_string())?;
+
+    Ok(num_rows)
+}
+
+/// The Arrow schema of the columns of a dataset. The position column is a float if the dataset has float positions.
+fn arrow_schema(columns: &[ColumnHeader], position_decimals: Option<u8>) -> SchemaRef {
+    let fields: Vec<Field> = columns.iter().enumerate().map(|(i, column)| {
+        let data_type = match column.type_ {
+            ColumnType::Integer if i == 0 && position_decimals.is_some() => DataType::Float64,
+            ColumnType::Integer => DataType::Int64,
+            ColumnType::Float => DataType::Float64,
+            ColumnType::Boolean => DataType::Boolean,
+            ColumnType::VolatileString | ColumnType::HashtableString | ColumnType::InternedString | ColumnType::Char => DataType::Utf8,
+        };
+
+        Field::new(&column.name, data_type, false)
+    }).collect();
+
+    Arc::new(Schema::new(fields))
+}
+
+fn rows_to_record_batch(schema: SchemaRef, rows: &[Row], position_decimals: Option<u8>) -> Result<RecordBatch, ArrowError> {
+    let type_mismatch = |field: &Field, cell: &CellValue| ArrowError::InvalidArgumentError(format!(
+        "Value {:?} does not match the type {} of column '{}'",
+        cell, field.data_type(), field.name(),
+    ));
+
+    // Float positions are stored as integers scaled by 10^decimals
+    let scale = 10f64.powi(position_decimals.unwrap_or(0) as i32);
+
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+
+    for (i, field) in schema.fields().iter().enumerate() {
+        let array: ArrayRef = match field.data_type() {
+            DataType::Int64 => {
+                let mut builder = Int64Builder::with_capacity(rows.len());
+                for row in rows {
+                    match &row[i] {
+                        CellValue::Integer(value) => builder.append_value(*value),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+            DataType::Float64 => {
+                let mut builder = Float64Builder::with_capacity(rows.len());
+                for row in rows {
+                    match &row[i] {
+                        CellValue::Float(value) => builder.append_value(*value),
+                        CellValue::Integer(position) if i == 0 => builder.append_value(*position as f64 / scale),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+            DataType::Boolean => {
+                let mut builder = BooleanBuilder::with_capacity(rows.len());
+                for row in rows {
+                    match &row[i] {
+                        CellValue::Integer(value) => builder.append_value(*value != 0),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+            _ => {
+                let mut builder = StringBuilder::new();
+                for row in rows {
+                    match &row[i] {
+                        CellValue::String(value) => builder.append_value(value),
+                        cell => return Err(type_mismatch(field, cell)),
+                    }
+                }
+                Arc::new(builder.finish())
+            },
+        };
+
+        arrays.push(array);
+    }
+
+    RecordBatch::try_new(schema, arrays)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, BooleanArray, Float64Array, Int64Array, StringArray};
+
+    use super::*;
+    use crate::test_utils::{row_reader, TestDatabase};
+
+    #[test]
+    fn exported_columns_keep_their_names_and_types() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1, 2]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+                { name = "gene", type = "interned-string" },
+                { name = "strand", type = "char" },
+                { name = "coding", type = "boolean" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tscore\tgene\tstrand\tcoding\n".to_string())
+            .chain((0..10).map(|i| format!("{}\t{}\tGENE{}\t{}\t{}\n", i * 10, i as f64 / 2.0, i % 3, ["+", "-"][i % 2], i % 4 == 0)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv), ("chr2.tsv", "pos\tscore\tgene\tstrand\tcoding\n5\t1\tA\t+\t0\n")]);
+
+        let mut reader = row_reader(&database, "genes", 1);
+        let schema = arrow_schema(reader.columns(), reader.position_decimals());
+        let fields: Vec<(&str, &DataType)> = schema.fields().iter().map(|field| (field.name().as_str(), field.data_type())).collect();
+        assert_eq!(fields, [
+            ("pos", &DataType::Int64),
+            ("score", &DataType::Float64),
+            ("gene", &DataType::Utf8),
+            ("strand", &DataType::Utf8),
+            ("coding", &DataType::Boolean),
+        ]);
+
+        let rows = reader.query_range(0, u64::MAX).unwrap();
+        let batch = rows_to_record_batch(schema, &rows, None).unwrap();
+        assert_eq!(batch.num_rows(), 10);
+
+        let column = |i: usize| batch.column(i).as_any();
+        assert_eq!(column(0).downcast_ref::<Int64Array>().unwrap().value(9), 90);
+        assert_eq!(column(1).downcast_ref::<Float64Array>().unwrap().value(3), 1.5);
+        assert_eq!(column(2).downcast_ref::<StringArray>().unwrap().value(4), "GENE1");
+        assert_eq!(column(3).downcast_ref::<StringArray>().unwrap().value(1), "-");
+        let coding = column(4).downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!((0..10).filter(|&i| coding.value(i)).collect::<Vec<_>>(), [0, 4, 8]);
+
+        // Every row of a table is written, one block at a time
+        let path = database.dir.path().join("genes.chr1.parquet");
+        assert_eq!(export_table_parquet(&mut reader, &path).unwrap(), 10);
+        assert!(path.is_file());
+        assert_eq!(export_table_parquet(&mut row_reader(&database, "genes", 2), &path).unwrap(), 1);
+    }
+
+    #[test]
+    fn float_positions_are_exported_as_floats() {
+        let config = r#"
+            [datasets.map]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            position_decimals = 2
+            columns = [
+                { name = "cm", type = "float", role = "position" },
+                { name = "rate", type = "float" },
+            ]
+        "#;
+        let database = TestDatabase::build(config, &[("chr1.tsv", "cm\trate\n0.25\t1\n1.5\t2\n")]);
+        let mut reader = row_reader(&database, "map", 1);
+
+        let schema = arrow_schema(reader.columns(), reader.position_decimals());
+        assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+
+        let batch = rows_to_record_batch(schema, &reader.query_range(0, u64::MAX).unwrap(), reader.position_decimals()).unwrap();
+        let positions = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(positions.values().to_vec(), [0.25, 1.5]);
+    }
+}
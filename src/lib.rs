@@ -6,6 +6,8 @@ mod config;
 mod database;
 
 pub mod query;
-pub use tsv_reader::ColumnType;
+pub use tsv_reader::{CellValue, ColumnType};
 pub mod compression;
 pub mod deserialize;
+#[cfg(test)]
+mod test_utils;
@@ -1,9 +1,17 @@
+#![allow(dead_code)]
+#![feature(btree_cursors)]
+
 mod tsv_reader;
 mod config;
 mod database;
 mod compression;
+mod query;
+mod deserialize;
+mod export;
+#[cfg(test)]
+mod test_utils;
 
-use std::path::PathBuf;
+use std::{fs::File, io::{BufReader, Cursor, Read, Seek}, path::PathBuf, sync::Arc, time::{Duration, Instant}};
 
 use clap::{Args, Parser, Subcommand};
 use ascii_table::AsciiTable;
@@ -26,6 +34,22 @@ enum Commands {
     Sample(SampleArgs),
     /// Build the database from a config file.
     Build(BuildArgs),
+    /// Benchmark the throughput of random range queries on a built database.
+    Bench(BenchArgs),
+    /// Compare two databases with the same schema and report the rows that differ.
+    Diff(DiffArgs),
+    /// Print a hex and ASCII view of a region of a database, for debugging the file format.
+    Dump(DumpArgs),
+    /// Print the datasets of a database and the size and block layout of each table.
+    Info(InfoArgs),
+    /// Query a range of rows of a table and print them as a table.
+    Query(QueryArgs),
+    /// Check the checksum of every block and table of a database, to detect corruption of the file.
+    Verify(VerifyArgs),
+    /// Compress a sample of the rows of a dataset with every compression algorithm and print how well each compresses.
+    SampleCompression(SampleCompressionArgs),
+    /// Export the tables of a dataset to Parquet files, one file per chromosome.
+    Export(ExportArgs),
 }
 
 #[derive(Args)]
@@ -41,9 +65,24 @@ struct GuessColumnTypesArgs {
     /// The minimum number of lines to read to guess the column types.
     #[arg(short, long, default_value_t = 1000)]
     min_sample_size: usize,
+    /// The maximum number of lines to read to guess the column types. If not specified, the entire file is read.
+    #[arg(short = 's', long)]
+    max_sample_size: Option<usize>,
+    /// The fraction between 0 and 1 of the file to read to guess the column types, after reading the minimum number of
+    /// lines. Compressed files are measured by their compressed size.
+    #[arg(long, conflicts_with = "max_sample_size")]
+    max_sample_fraction: Option<f64>,
     /// The policy to use for missing values.
     #[arg(value_enum, short = 'p', long, default_value_t = tsv_reader::MissingValuePolicy::ReplaceWithEmptyString)]
     missing_value_policy: tsv_reader::MissingValuePolicy,
+    /// Values such as `NA` or `.` that denote a missing value, in addition to an empty value. Columns with missing values
+    /// can't be integer columns.
+    #[arg(long, value_delimiter = ',')]
+    missing_values: Vec<String>,
+    /// The minimum fraction between 0 and 1 of values written as integers for a column with whole-valued floats such as `3.0` to be considered an integer column.
+    /// If not specified, columns with whole-valued floats are considered float columns.
+    #[arg(short, long)]
+    whole_float_threshold_fraction: Option<f32>,
 }
 
 #[derive(Args)]
@@ -70,6 +109,123 @@ struct BuildArgs {
     /// If the database already exists, it is overwritten.
     #[arg(short, long)]
     output: Option<String>,
+    /// Print a JSON line to stderr for every table that is built, with its dataset, chromosome, number of rows and
+    /// blocks, size in bytes and the time it took in milliseconds. The blocks are not printed.
+    #[arg(long)]
+    progress_json: bool,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// The path to the database.
+    database: String,
+    /// The name of the dataset to query.
+    #[arg(short, long)]
+    dataset: String,
+    /// The chromosome to query.
+    #[arg(short, long)]
+    chromosome: u8,
+    /// The number of random range queries to run.
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    queries: usize,
+    /// The width of each range query.
+    #[arg(short, long, default_value_t = 10000)]
+    width: u64,
+    /// The seed used to pick the random ranges, so runs with the same seed query the same ranges.
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+    /// Map the database into memory and decompress the blocks straight from the mapping, instead of reading them.
+    #[arg(long)]
+    mmap: bool,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// The path to the first database.
+    a: String,
+    /// The path to the second database.
+    b: String,
+    /// The maximum number of differences to print for each table.
+    #[arg(short = 'n', long, default_value_t = 10)]
+    max_differences: usize,
+    /// The absolute difference below which float cells are considered equal.
+    #[arg(short, long, default_value_t = 0.0)]
+    absolute_tolerance: f64,
+    /// The difference relative to the largest value below which float cells are considered equal.
+    #[arg(short, long, default_value_t = 0.0)]
+    relative_tolerance: f64,
+}
+
+#[derive(Args)]
+struct DumpArgs {
+    /// The path to the database.
+    database: String,
+    /// The offset in bytes of the start of the region.
+    #[arg(short, long, default_value_t = 0)]
+    offset: u64,
+    /// The length in bytes of the region.
+    #[arg(short, long, default_value_t = 256)]
+    length: u64,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// The path to the database.
+    database: String,
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// The path to the database.
+    database: String,
+    /// The name of the dataset to query.
+    #[arg(short, long)]
+    dataset: String,
+    /// The chromosome to query.
+    #[arg(short, long)]
+    chromosome: u8,
+    /// The start of the range (inclusive). If not specified, the range starts at the first row of the table.
+    #[arg(short, long)]
+    start: Option<u64>,
+    /// The end of the range (exclusive). If not specified, the range ends after the last row of the table.
+    #[arg(short, long)]
+    end: Option<u64>,
+    /// The names of the columns to print. If not specified, all columns are printed.
+    #[arg(long)]
+    columns: Vec<String>,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// The path to the database.
+    database: String,
+}
+
+#[derive(Args)]
+struct SampleCompressionArgs {
+    /// The path to the configuration file.
+    config: String,
+    /// The name of the dataset to sample.
+    #[arg(short, long)]
+    dataset: String,
+    /// The number of rows to sample from the file of the first chromosome.
+    #[arg(short = 'n', long, default_value_t = 10000)]
+    rows: usize,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// The path to the database.
+    database: String,
+    /// The name of the dataset to export.
+    #[arg(short, long)]
+    dataset: String,
+    /// The chromosome to export. All chromosomes of the dataset are exported if it is not given.
+    #[arg(short, long)]
+    chromosome: Option<u8>,
+    /// The directory to write the Parquet files to, named `{dataset}.chr{chromosome}.parquet`.
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
 }
 
 fn main() {
@@ -79,15 +235,39 @@ fn main() {
         Commands::GuessColumnTypes(args) => guess_column_types(args),
         Commands::Sample(args) => sample(args),
         Commands::Build(args) => build(args),
+        Commands::Bench(args) => bench(args),
+        Commands::Diff(args) => diff(args),
+        Commands::Dump(args) => dump(args),
+        Commands::Info(args) => info(args),
+        Commands::Query(args) => query(args),
+        Commands::Verify(args) => verify(args),
+        Commands::SampleCompression(args) => sample_compression(args),
+        Commands::Export(args) => export(args),
     }
 }
 
 fn guess_column_types(args: GuessColumnTypesArgs) {
-    let file = std::fs::File::open(args.file).unwrap();
+    if args.max_sample_size.is_some_and(|max_sample_size| max_sample_size < args.min_sample_size) {
+        eprintln!("The maximum sample size must be at least the minimum sample size ({}).", args.min_sample_size);
+        std::process::exit(1);
+    }
+
+    if args.max_sample_fraction.is_some_and(|max_sample_fraction| !(max_sample_fraction > 0.0 && max_sample_fraction <= 1.0)) {
+        eprintln!("The maximum sample fraction must be greater than 0 and at most 1.");
+        std::process::exit(1);
+    }
+
+    let sample_limit = match (args.max_sample_size, args.max_sample_fraction) {
+        (Some(max_sample_size), _) => Some(tsv_reader::SampleLimit::Lines(max_sample_size)),
+        (None, Some(max_sample_fraction)) => Some(tsv_reader::SampleLimit::Fraction(max_sample_fraction)),
+        (None, None) => None,
+    };
+
+    let file = std::fs::File::open(&args.file).unwrap();
     let mut reader: tsv_reader::TabSeparatedFileReader = tsv_reader::TabSeparatedFileReader::new(file);
 
     let mut line_buf = String::new();
-    let found_column_names: Vec<String> = reader.read_line_and_split(&mut line_buf).unwrap().map(|s| s.to_owned()).collect();
+    let found_column_names: Vec<String> = reader.read_line_and_split(&mut line_buf).unwrap().map(|s| s.into_owned()).collect();
 
     // Verify all column names are present
     for column_name in args.column_names.iter() {
@@ -110,9 +290,27 @@ fn guess_column_types(args: GuessColumnTypesArgs) {
     
     let column_types = reader.guess_column_types_but_better(
         interesting_column_indices,
+        &args.missing_values,
         args.volatile_threshold_fraction,
-        args.min_sample_size
-    ).unwrap();
+        args.min_sample_size,
+        sample_limit,
+        args.whole_float_threshold_fraction,
+    );
+
+    let column_types = match column_types {
+        Ok(column_types) => column_types,
+        Err(tsv_reader::GuessColumnTypesError::MissingValue { column, line }) => {
+            eprintln!("Failed to guess the column types of '{}':\n\tLine {}: Missing value in column '{}'.", args.file, line, found_column_names[column]);
+            std::process::exit(1);
+        },
+        Err(e) => {
+            eprintln!("Failed to guess the column types of '{}':\n\t{}", args.file, e);
+            std::process::exit(1);
+        },
+    };
+
+    // The header is not a sampled line
+    println!("Sampled {} lines.", reader.lines_read() - 1);
 
     let named_column_types: std::collections::HashMap<String, &tsv_reader::ColumnType> = column_types.iter().map(|(&i, t)| {
         (found_column_names[i].to_owned(), t)
@@ -196,7 +394,8 @@ fn build(args: BuildArgs) {
         }
     };
 
-    let database = database::Database::new(output, config);
+    let mut database = database::Database::new(output, config);
+    database.set_progress_json(args.progress_json);
     match database.save() {
         Ok(_) => {},
         Err(e) => {
@@ -207,3 +406,625 @@ fn build(args: BuildArgs) {
 
     println!("Database: {:?}", database);
 }
+
+/// Open a reader for the table of a chromosome in a dataset, exiting if the database, dataset or table can't be read
+fn open_row_reader(database: &str, dataset_name: &str, chromosome: u8) -> query::RowReader<BufReader<File>> {
+    let open_database = || match File::open(database) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to open database '{}': {}", database, e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut client = query::DatabaseQueryClient::new(open_database());
+    let (dataset, index) = read_table_index(&mut client, dataset_name, chromosome);
+
+    query::RowReader::new(BufReader::new(open_database()), &dataset, index)
+}
+
+/// Open a reader for the table of a chromosome in a dataset of a database that is mapped into memory, exiting if the
+/// database, dataset or table can't be read
+fn open_mapped_row_reader(database: &str, dataset_name: &str, chromosome: u8) -> query::RowReader<Cursor<query::MappedFile>> {
+    let mut client = match query::DatabaseQueryClient::open_mapped(database) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to map database '{}': {}", database, e);
+            std::process::exit(1);
+        }
+    };
+    let (dataset, index) = read_table_index(&mut client, dataset_name, chromosome);
+
+    query::RowReader::new_mapped(client.mapped_file().clone(), &dataset, index)
+}
+
+/// Read the header of a dataset and the index of the table of a chromosome, exiting if they can't be read
+fn read_table_index<R: Read + Seek>(
+    client: &mut query::DatabaseQueryClient<R>,
+    dataset_name: &str,
+    chromosome: u8,
+) -> (query::DatasetHeader, Arc<query::TableIndex>) {
+    let header = match client.read_database_header() {
+        Ok(header) => header,
+        Err(e) => {
+            eprintln!("Failed to read database header: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let dataset = match header.datasets.iter().find(|dataset| dataset.name == dataset_name) {
+        Some(dataset) => dataset,
+        None => {
+            eprintln!("Dataset '{}' not found in database.", dataset_name);
+            std::process::exit(1);
+        }
+    };
+
+    let table = match dataset.tables.iter().find(|table| table.chromosome == chromosome) {
+        Some(table) => table,
+        None => {
+            eprintln!("Chromosome {} not found in dataset '{}'.", chromosome, dataset_name);
+            std::process::exit(1);
+        }
+    };
+
+    let index = match client.read_table_index(table) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Failed to read table index: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    (dataset.clone(), index)
+}
+
+fn bench(args: BenchArgs) {
+    let result = if args.mmap {
+        run_bench(open_mapped_row_reader(&args.database, &args.dataset, args.chromosome), &args)
+    } else {
+        run_bench(open_row_reader(&args.database, &args.dataset, args.chromosome), &args)
+    };
+
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Queries: {} of width {} in {:?}", result.latencies.len(), args.width, result.elapsed);
+    println!("Rows: {} ({:.1} per query)", result.rows, result.rows as f64 / result.latencies.len().max(1) as f64);
+    println!("Throughput: {:.1} queries/s, {:.1} rows/s", result.queries_per_second(), result.rows_per_second());
+    println!("Latency: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}", result.percentile(0.5), result.percentile(0.9), result.percentile(0.99), result.percentile(1.0));
+}
+
+/// The measurements of a benchmark of random range queries
+struct BenchResult {
+    /// The latency of every query, from fastest to slowest
+    latencies: Vec<Duration>,
+    /// The total number of rows returned by the queries
+    rows: usize,
+    /// The time it took to run all queries
+    elapsed: Duration,
+}
+
+impl BenchResult {
+    fn queries_per_second(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn rows_per_second(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let i = ((self.latencies.len() - 1) as f64 * p).round() as usize;
+        self.latencies[i]
+    }
+}
+
+/// Run random range queries of the width of the arguments on a table.
+fn run_bench<R: Read + Seek>(mut row_reader: query::RowReader<R>, args: &BenchArgs) -> Result<BenchResult, String> {
+    let index = row_reader.index();
+    let min_position = index.min_position();
+    let max_start = index.max_position.saturating_sub(args.width).max(min_position);
+
+    // xorshift64* needs a non-zero state
+    let mut random_state = args.seed ^ 0x9E37_79B9_7F4A_7C15;
+    let mut next_random = || {
+        random_state ^= random_state >> 12;
+        random_state ^= random_state << 25;
+        random_state ^= random_state >> 27;
+        random_state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+
+    let mut latencies: Vec<Duration> = Vec::with_capacity(args.queries);
+    let mut rows: usize = 0;
+
+    let bench_start = Instant::now();
+
+    for _ in 0..args.queries {
+        let start = min_position + next_random() % (max_start - min_position + 1);
+        let end = start.saturating_add(args.width);
+
+        let query_start = Instant::now();
+        let query_rows = row_reader.query_range(start, end)
+            .map_err(|e| format!("Query {}-{} failed: {}", start, end, e))?;
+        latencies.push(query_start.elapsed());
+
+        rows += query_rows.len();
+    }
+
+    let elapsed = bench_start.elapsed();
+    latencies.sort();
+
+    Ok(BenchResult { latencies, rows, elapsed })
+}
+
+fn diff(args: DiffArgs) {
+    let tolerance = query::FloatTolerance {
+        absolute: args.absolute_tolerance,
+        relative: args.relative_tolerance,
+    };
+
+    let diff = match database::Database::diff(args.a.as_ref(), args.b.as_ref(), tolerance) {
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("Failed to diff databases: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if diff.is_empty() {
+        println!("Databases are identical");
+        return;
+    }
+
+    for table in &diff.tables {
+        println!(
+            "Dataset '{}', chromosome {}: {} rows only in A, {} rows only in B, {} cells differ",
+            table.dataset, table.chromosome, table.only_in_a.len(), table.only_in_b.len(), table.changed_cells.len(),
+        );
+
+        for row in table.only_in_a.iter().take(args.max_differences) {
+            println!("\t- {:?}", row);
+        }
+        for row in table.only_in_b.iter().take(args.max_differences) {
+            println!("\t+ {:?}", row);
+        }
+        for cell in table.changed_cells.iter().take(args.max_differences) {
+            println!("\t~ position {}, column '{}': {:?} -> {:?}", cell.position, cell.column, cell.a, cell.b);
+        }
+    }
+
+    // Differences are reported with a non-zero exit code, like `diff`
+    std::process::exit(1);
+}
+
+fn dump(args: DumpArgs) {
+    let file = match File::open(&args.database) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to open database '{}': {}", args.database, e);
+            std::process::exit(1);
+        }
+    };
+
+    match query::DatabaseQueryClient::new(file).hexdump_region(args.offset, args.length) {
+        Ok(dump) => print!("{}", dump),
+        Err(e) => {
+            eprintln!("Failed to read region: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn info(args: InfoArgs) {
+    let file = match File::open(&args.database) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to open database '{}': {}", args.database, e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut client = query::DatabaseQueryClient::new(file);
+
+    let header = match client.read_database_header() {
+        Ok(header) => header,
+        Err(e) => {
+            eprintln!("Failed to read database header: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Version: {}", header.version);
+    println!("Built at: {} (Unix time) with ZygosDB {}", header.build_timestamp, header.build_version);
+    println!("Assembly: {}", header.assembly.as_deref().unwrap_or("unknown"));
+
+    for dataset in &header.datasets {
+        println!();
+        println!("Dataset '{}'", dataset.name);
+        println!("Compression: {:?}", dataset.compression_algorithm);
+        println!("Rows per index: {}", dataset.rows_per_index);
+        if let Some(position_decimals) = dataset.position_decimals {
+            println!("Position decimals: {}", position_decimals);
+        }
+        if let Some(coordinate_base) = dataset.coordinate_base {
+            println!("Coordinate base: {}", coordinate_base);
+        }
+        println!("Block layout: {}", if dataset.columnar { "columnar" } else { "row" });
+
+        let mut columns_table = AsciiTable::default();
+        columns_table.set_max_width(usize::MAX);
+        for (i, name) in ["Column", "Type"].iter().enumerate() {
+            columns_table.column(i).set_header(*name);
+        }
+        columns_table.print(dataset.columns.iter()
+            .map(|column| vec![column.name.clone(), format!("{:?}", column.type_)])
+            .collect::<Vec<_>>());
+
+        let mut ascii_table = AsciiTable::default();
+        ascii_table.set_max_width(usize::MAX);
+        for (i, name) in ["Chromosome", "Index offset", "Rows", "Index entries", "Average rows per block", "Index size"].iter().enumerate() {
+            ascii_table.column(i).set_header(*name);
+        }
+
+        let mut data: Vec<Vec<String>> = vec![];
+
+        for table in &dataset.tables {
+            let index = match client.read_table_index(table) {
+                Ok(index) => index,
+                Err(e) => {
+                    eprintln!("Failed to read index of chromosome {}: {}", table.chromosome, e);
+                    std::process::exit(1);
+                }
+            };
+
+            data.push(table_info(table, &index));
+        }
+
+        ascii_table.print(data);
+    }
+}
+
+fn query(args: QueryArgs) {
+    let row_reader = open_row_reader(&args.database, &args.dataset, args.chromosome);
+
+    let (headers, rows) = match query_table(row_reader, &args) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut ascii_table = AsciiTable::default();
+    ascii_table.set_max_width(usize::MAX);
+    for (i, header) in headers.into_iter().enumerate() {
+        ascii_table.column(i).set_header(header);
+    }
+
+    ascii_table.print(rows);
+}
+
+/// Query the range and columns of the arguments on a table, as the names of the columns and the formatted cells of the rows
+fn query_table<R: Read + Seek>(mut row_reader: query::RowReader<R>, args: &QueryArgs) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    let column_indices = if args.columns.is_empty() {
+        (0..row_reader.columns().len()).collect()
+    } else {
+        row_reader.column_indices(&args.columns)
+            .map_err(|e| format!("{} in dataset '{}'.", e, args.dataset))?
+    };
+
+    let headers = column_indices.iter().map(|&column| row_reader.columns()[column].name.clone()).collect();
+
+    let (start, end) = row_reader.resolve_bounds(args.start, args.end);
+    let rows = row_reader.query_range_projected(start, end, column_indices)
+        .map_err(|e| format!("Query {}-{} failed: {}", start, end, e))?;
+
+    let rows = rows.into_iter().map(|row| row.into_iter().map(|cell| match cell {
+        tsv_reader::CellValue::Integer(i) => i.to_string(),
+        tsv_reader::CellValue::Float(f) => f.to_string(),
+        tsv_reader::CellValue::String(s) => s.to_string(),
+    }).collect()).collect();
+
+    Ok((headers, rows))
+}
+
+fn verify(args: VerifyArgs) {
+    let open_database = || match File::open(&args.database) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to open database '{}': {}", args.database, e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut client = query::DatabaseQueryClient::new(open_database());
+
+    let header = match client.read_database_header() {
+        Ok(header) => header,
+        Err(e) => {
+            eprintln!("Failed to read database header: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut num_blocks = 0;
+    let mut num_tables = 0;
+
+    for dataset in &header.datasets {
+        for table in &dataset.tables {
+            let index = match client.read_table_index(table) {
+                Ok(index) => index,
+                Err(e) => {
+                    eprintln!("Failed to read index of chromosome {} of dataset '{}': {}", table.chromosome, dataset.name, e);
+                    std::process::exit(1);
+                }
+            };
+
+            let mut row_reader = query::RowReader::new(BufReader::new(open_database()), dataset, index);
+            match row_reader.verify_checksums() {
+                Ok(verified) => num_blocks += verified,
+                Err(e) => {
+                    eprintln!("Dataset '{}' is corrupted: {}", dataset.name, e);
+                    std::process::exit(1);
+                }
+            }
+
+            if let Err(e) = client.verify_table_checksum(table) {
+                eprintln!("Dataset '{}' is corrupted: {}", dataset.name, e);
+                std::process::exit(1);
+            }
+
+            num_tables += 1;
+        }
+    }
+
+    println!("All {} blocks of {} tables match their checksums", num_blocks, num_tables);
+}
+
+fn sample_compression(args: SampleCompressionArgs) {
+    let config = match config::Config::from_file(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to parse config file: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = config.validate() {
+        eprintln!("Config validation failed:\n\t{}", e);
+        std::process::exit(1);
+    }
+
+    let database = database::Database::new(PathBuf::from(&args.config), config);
+    let samples = match database.sample_compression(&args.dataset, args.rows) {
+        Ok(samples) => samples,
+        Err(e) => {
+            eprintln!("Failed to sample dataset '{}':\n\t{}", args.dataset, e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(sample) = samples.first() {
+        println!("Sampled {} rows of dataset '{}'", sample.rows, args.dataset);
+    }
+
+    let mut ascii_table = AsciiTable::default();
+    ascii_table.set_max_width(usize::MAX);
+    for (i, name) in ["Algorithm", "Uncompressed size", "Compressed size", "Ratio", "Time"].iter().enumerate() {
+        ascii_table.column(i).set_header(*name);
+    }
+
+    ascii_table.print(samples.iter().map(|sample| vec![
+        format!("{:?}", sample.algorithm),
+        sample.uncompressed_size.to_string(),
+        sample.compressed_size.to_string(),
+        format!("{:.2}", sample.ratio()),
+        format!("{:.2?}", sample.duration),
+    ]).collect::<Vec<_>>());
+}
+
+fn export(args: ExportArgs) {
+    let open_database = || match File::open(&args.database) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to open database '{}': {}", args.database, e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut client = query::DatabaseQueryClient::new(open_database());
+
+    let header = match client.read_database_header() {
+        Ok(header) => header,
+        Err(e) => {
+            eprintln!("Failed to read database header: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let dataset = match header.datasets.iter().find(|dataset| dataset.name == args.dataset) {
+        Some(dataset) => dataset,
+        None => {
+            eprintln!("Dataset '{}' not found in database.", args.dataset);
+            std::process::exit(1);
+        }
+    };
+
+    let tables: Vec<&query::TableHeader> = dataset.tables.iter()
+        .filter(|table| args.chromosome.is_none_or(|chromosome| table.chromosome == chromosome))
+        .collect();
+    if let Some(chromosome) = args.chromosome {
+        if tables.is_empty() {
+            eprintln!("Chromosome {} not found in dataset '{}'.", chromosome, dataset.name);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(e) = std::fs::create_dir_all(&args.output_dir) {
+        eprintln!("Failed to create output directory '{}': {}", args.output_dir.display(), e);
+        std::process::exit(1);
+    }
+
+    for table in tables {
+        let index = match client.read_table_index(table) {
+            Ok(index) => index,
+            Err(e) => {
+                eprintln!("Failed to read index of chromosome {}: {}", table.chromosome, e);
+                std::process::exit(1);
+            }
+        };
+
+        let path = args.output_dir.join(format!("{}.chr{}.parquet", dataset.name, table.chromosome));
+        let mut row_reader = query::RowReader::new(BufReader::new(open_database()), dataset, index);
+
+        match export::export_table_parquet(&mut row_reader, &path) {
+            Ok(num_rows) => println!("Exported {} rows of chromosome {} to '{}'", num_rows, table.chromosome, path.display()),
+            Err(e) => {
+                eprintln!("Failed to export chromosome {}: {}", table.chromosome, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Describe the offset and block layout of a table as a row of the table printed by the `info` command
+fn table_info(table: &query::TableHeader, index: &query::TableIndex) -> Vec<String> {
+    let num_rows = index.num_rows();
+    let num_blocks = index.num_blocks();
+
+    vec![
+        table.chromosome.to_string(),
+        table.offset.to_string(),
+        num_rows.to_string(),
+        num_blocks.to_string(),
+        format!("{:.1}", num_rows as f64 / num_blocks.max(1) as f64),
+        format!("{} bytes", index.index_size()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{row_reader, TestDatabase};
+
+    #[test]
+    fn bench_reports_nonzero_throughput() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tscore\n".to_string())
+            .chain((0..100).map(|i| format!("{}\t{}\n", i * 10, i)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+
+        let args = BenchArgs {
+            database: database.path.to_str().unwrap().to_string(),
+            dataset: "scores".to_string(),
+            chromosome: 1,
+            queries: 5,
+            width: 100,
+            seed: 0,
+            mmap: false,
+        };
+        let result = run_bench(row_reader(&database, "scores", 1), &args).unwrap();
+
+        assert_eq!(result.latencies.len(), 5);
+        assert!(result.rows > 0);
+        assert!(result.queries_per_second() > 0.0);
+        assert!(result.rows_per_second() > 0.0);
+        assert!(result.percentile(0.5) <= result.percentile(1.0));
+
+        // The same queries return the same rows from a mapped file
+        let mapped = run_bench(open_mapped_row_reader(&args.database, &args.dataset, args.chromosome), &args).unwrap();
+        assert_eq!(mapped.rows, result.rows);
+    }
+
+    #[test]
+    fn info_reports_an_index_entry_per_block() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tscore\n".to_string())
+            .chain((0..100).map(|i| format!("{}\t{}\n", i * 10, i)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+
+        let mut client = query::DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let table = client.read_database_header().unwrap().datasets.remove(0).tables.remove(0);
+        let index = client.read_table_index(&table).unwrap();
+
+        let row = table_info(&table, &index);
+        assert_eq!(row[1], table.offset.to_string());
+        assert_eq!(row[2], "100");
+        assert_eq!(row[3], index.inner.len().to_string());
+        assert_eq!(row[4], "4.0");
+    }
+
+    #[test]
+    fn query_prints_the_projected_columns_of_the_range() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+                { name = "gene", type = "volatile-string" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tscore\tgene\n".to_string())
+            .chain((0..100).map(|i| format!("{}\t{}\tgene-{}\n", i * 10, i as f64 / 2.0, i)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+
+        let args = |start: Option<u64>, end: Option<u64>, columns: &[&str]| QueryArgs {
+            database: database.path.to_str().unwrap().to_string(),
+            dataset: "scores".to_string(),
+            chromosome: 1,
+            start,
+            end,
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+        };
+
+        let (headers, rows) = query_table(row_reader(&database, "scores", 1), &args(Some(20), Some(40), &[])).unwrap();
+        assert_eq!(headers, ["pos", "score", "gene"]);
+        assert_eq!(rows, [["20", "1", "gene-2"], ["30", "1.5", "gene-3"]]);
+
+        let (headers, rows) = query_table(row_reader(&database, "scores", 1), &args(Some(975), None, &["gene", "pos"])).unwrap();
+        assert_eq!(headers, ["gene", "pos"]);
+        assert_eq!(rows, [["gene-98", "980"], ["gene-99", "990"]]);
+
+        let e = query_table(row_reader(&database, "scores", 1), &args(None, None, &["variant"])).unwrap_err();
+        assert_eq!(e, "Column 'variant' not found in dataset 'scores'.");
+    }
+}
@@ -1,11 +1,23 @@
-use std::{collections::BTreeMap, io::{Error, ErrorKind, Read, Seek, SeekFrom}, mem::size_of};
+use std::{cmp::max, collections::{BTreeMap, HashMap, HashSet}, fs::File, io::{Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write}, mem::size_of, ops::Bound, path::Path, sync::{mpsc, Arc, Mutex}, thread};
+
+use memmap2::Mmap;
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::Deserialize;
 
-use crate::{compression::CompressionAlgorithm, database::{HEADER_MAGIC, INDEX_MAGIC}, tsv_reader::ColumnType};
+use rhexdump::prelude::*;
+
+use crate::{compression::{block_checksum, table_checksum, CompressionAlgorithm, RowDecompressor}, database::{HEADER_MAGIC, HEADER_VERSION, INDEX_MAGIC}, deserialize, tsv_reader::{CellValue, ColumnType}};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct DatabaseHeader {
     pub version: u8,
+    /// When the database was built, in seconds since the Unix epoch
+    pub build_timestamp: u64,
+    /// The version of ZygosDB the database was built with
+    pub build_version: String,
+    /// The genome assembly the positions refer to, if it is known
+    pub assembly: Option<String>,
     pub datasets: Vec<DatasetHeader>,
 }
 
@@ -13,6 +25,15 @@ pub struct DatabaseHeader {
 pub struct DatasetHeader {
     pub name: String,
     pub compression_algorithm: CompressionAlgorithm,
+    /// The number of rows per block the dataset was built with
+    pub rows_per_index: u64,
+    /// The number of decimals of float positions, which are stored as integers scaled by `10^position_decimals`. `None`
+    /// if the positions are integers.
+    pub position_decimals: Option<u8>,
+    /// Whether the positions are 0-based or 1-based, if it is known
+    pub coordinate_base: Option<u8>,
+    /// Whether the cells of a block are stored column by column instead of row by row
+    pub columnar: bool,
     pub columns: Vec<ColumnHeader>,
     pub tables: Vec<TableHeader>,
 }
@@ -28,16 +49,111 @@ pub struct ColumnHeader {
 pub struct TableHeader {
     pub chromosome: u8,
     pub offset: u64,
+    pub index_compression_algorithm: CompressionAlgorithm,
+}
+
+/// The number of datasets, chromosomes and tables of a database. The number of rows isn't stored in the header, but in
+/// the index of every table, see `TableIndex::num_rows`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DatabaseSummary {
+    pub num_datasets: usize,
+    /// The number of distinct chromosomes across all datasets
+    pub num_chromosomes: usize,
+    pub num_tables: usize,
+}
+
+impl DatasetHeader {
+    /// Translate a position from the coordinate base of the caller to the coordinate base of the dataset, like
+    /// `RowReader::translate_position`. Fails if the coordinate base of the dataset is unknown.
+    pub fn translate_position(&self, position: u64, coordinate_base: u8) -> std::io::Result<u64> {
+        translate_position(self.coordinate_base, position, coordinate_base)
+    }
+}
+
+/// Translate a position from a coordinate base to the coordinate base of a dataset, if it is known
+fn translate_position(dataset_coordinate_base: Option<u8>, position: u64, coordinate_base: u8) -> std::io::Result<u64> {
+    let Some(dataset_coordinate_base) = dataset_coordinate_base else {
+        return Err(Error::new(ErrorKind::InvalidInput, format!(
+            "Can't translate {}-based positions, as the coordinate base of the dataset is unknown", coordinate_base,
+        )));
+    };
+
+    Ok(match dataset_coordinate_base.cmp(&coordinate_base) {
+        std::cmp::Ordering::Greater => position.saturating_add((dataset_coordinate_base - coordinate_base) as u64),
+        std::cmp::Ordering::Less => position.saturating_sub((coordinate_base - dataset_coordinate_base) as u64),
+        std::cmp::Ordering::Equal => position,
+    })
+}
+
+impl DatabaseHeader {
+    pub fn summary(&self) -> DatabaseSummary {
+        let chromosomes: HashSet<u8> = self.datasets.iter()
+            .flat_map(|dataset| dataset.tables.iter().map(|table| table.chromosome))
+            .collect();
+
+        DatabaseSummary {
+            num_datasets: self.datasets.len(),
+            num_chromosomes: chromosomes.len(),
+            num_tables: self.datasets.iter().map(|dataset| dataset.tables.len()).sum(),
+        }
+    }
+}
+
+/// The schema and bounds of a table, assembled from the header of its dataset and its index
+#[derive(Clone, Debug)]
+pub struct TableDescription {
+    pub dataset_name: String,
+    pub chromosome: u8,
+    pub columns: Vec<ColumnHeader>,
+    /// The position of the first row of the table
+    pub min_position: u64,
+    /// The position of the last row of the table
+    pub max_position: u64,
+    pub num_rows: u64,
+    pub num_blocks: usize,
+    pub compression_algorithm: CompressionAlgorithm,
+    pub index_compression_algorithm: CompressionAlgorithm,
+    /// The size of the index in the file in bytes
+    pub index_size: u64,
+}
+
+/// The size in bytes of a table entry in the dataset header: chromosome, index offset and index compression algorithm
+const TABLE_HEADER_SIZE: usize = 1 + 8 + 1;
+
+/// A database file that is mapped into memory. Clones share the mapping, so the readers of a file can read its blocks
+/// as slices of memory instead of with a `seek` and `read` for every block.
+#[derive(Clone)]
+pub struct MappedFile(Arc<Mmap>);
+
+impl MappedFile {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+
+        // SAFETY: The file must not be truncated while it is mapped. Databases are only ever modified in place by
+        // `update_cell`, which doesn't change the size of the file.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Ok(Self(Arc::new(mmap)))
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
 }
 
 pub struct DatabaseQueryClient<R: Read + Seek> {
     reader: R,
+    /// Table indices that were read or prefetched, by the offset of the index
+    index_cache: HashMap<u64, Arc<TableIndex>>,
 }
 
 impl<R: Read + Seek> DatabaseQueryClient<R> {
     pub fn new(reader: R) -> Self {
         Self {
             reader,
+            index_cache: HashMap::new(),
         }
     }
 
@@ -53,17 +169,6 @@ impl<R: Read + Seek> DatabaseQueryClient<R> {
         Ok(buf[0])
     }
 
-    fn read_vint64(&mut self) -> std::io::Result<u64> {
-        let mut buf = [0u8; 9];
-        self.reader.read_exact(&mut buf[0..1])?;
-        let len = vint64::decoded_len(buf[0]);
-
-        self.reader.read_exact(&mut buf[1..len])?;
-        let mut slice = &buf[..len];
-
-        Ok(vint64::decode(&mut slice).unwrap())
-    }
-
     pub fn read_string_u8(&mut self) -> std::io::Result<String> {
         let len = self.read_u8()? as usize;
         let mut buf = vec![0; len];
@@ -71,7 +176,9 @@ impl<R: Read + Seek> DatabaseQueryClient<R> {
         Ok(String::from_utf8(buf).map_err(|e| Error::new(ErrorKind::InvalidData, e))?)
     }
 
-    pub fn read_database_header(&mut self) -> std::io::Result<DatabaseHeader> {
+    /// Read and validate the magic and version at the start of the database and read the rest of the header before the
+    /// datasets, leaving the reader right after it. The datasets of the returned header are empty.
+    fn read_database_preamble(&mut self) -> std::io::Result<DatabaseHeader> {
         self.reader.seek(SeekFrom::Start(0))?;
 
         {
@@ -87,6 +194,41 @@ impl<R: Read + Seek> DatabaseQueryClient<R> {
         }
 
         let version = self.read_u8()?;
+        if version != HEADER_VERSION {
+            let err_msg = format!(
+                "Unsupported database version: expected {}, got {}",
+                HEADER_VERSION, version
+            );
+            return Err(Error::new(ErrorKind::InvalidData, err_msg));
+        }
+
+        let build_timestamp = self.read_u64()?;
+        let build_version = self.read_string_u8()?;
+
+        let assembly = Some(self.read_string_u8()?).filter(|assembly| !assembly.is_empty());
+
+        Ok(DatabaseHeader{ version, build_timestamp, build_version, assembly, datasets: Vec::new() })
+    }
+
+    /// Check that the database was built for the expected genome assembly, so positions of another assembly aren't
+    /// queried by accident. Databases without an assembly never match.
+    pub fn check_assembly(&mut self, expected_assembly: &str) -> std::io::Result<()> {
+        let DatabaseHeader{ assembly, .. } = self.read_database_preamble()?;
+
+        match assembly {
+            Some(assembly) if assembly == expected_assembly => Ok(()),
+            Some(assembly) => Err(Error::new(ErrorKind::InvalidData, format!(
+                "Database was built for assembly '{}', but '{}' was expected", assembly, expected_assembly,
+            ))),
+            None => Err(Error::new(ErrorKind::InvalidData, format!(
+                "Database has no assembly, but '{}' was expected", expected_assembly,
+            ))),
+        }
+    }
+
+    pub fn read_database_header(&mut self) -> std::io::Result<DatabaseHeader> {
+        let mut header = self.read_database_preamble()?;
+
         let num_datasets = self.read_u8()? as usize;
 
         let mut datasets = Vec::with_capacity(num_datasets);
@@ -98,6 +240,15 @@ impl<R: Read + Seek> DatabaseQueryClient<R> {
             let compression_algorithm = CompressionAlgorithm::try_from(compression_algorithm_id)
                 .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Unknown compression algorithm with id {}", compression_algorithm_id)))?;
 
+            let rows_per_index = self.read_u64()?;
+            let position_decimals = Some(self.read_u8()?).filter(|&decimals| decimals != 0);
+            let coordinate_base = Some(self.read_u8()?).filter(|&base| base != u8::MAX);
+            let columnar = match self.read_u8()? {
+                0 => false,
+                1 => true,
+                layout_id => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown block layout with id {}", layout_id))),
+            };
+
             let num_columns = self.read_u8()? as usize;
 
             let mut columns = Vec::with_capacity(num_columns);
@@ -119,16 +270,287 @@ impl<R: Read + Seek> DatabaseQueryClient<R> {
                 let chromosome = self.read_u8()?;
                 let offset = self.read_u64()?;
 
-                tables.push(TableHeader{ chromosome, offset });
+                let index_compression_algorithm_id = self.read_u8()?;
+                let index_compression_algorithm = CompressionAlgorithm::try_from(index_compression_algorithm_id)
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Unknown index compression algorithm with id {}", index_compression_algorithm_id)))?;
+
+                tables.push(TableHeader{ chromosome, offset, index_compression_algorithm });
+            }
+
+            datasets.push(DatasetHeader{ name, compression_algorithm, rows_per_index, position_decimals, coordinate_base, columnar, columns, tables });
+        }
+
+        header.datasets = datasets;
+
+        Ok(header)
+    }
+
+    /// Count the datasets, chromosomes and tables of the database with a single parse of the header
+    pub fn summary(&mut self) -> std::io::Result<DatabaseSummary> {
+        Ok(self.read_database_header()?.summary())
+    }
+
+    /// The compression algorithm of the blocks of a dataset, as stored in the header
+    pub fn compression_algorithm(&mut self, dataset_name: &str) -> std::io::Result<CompressionAlgorithm> {
+        self.read_database_header()?.datasets.into_iter()
+            .find(|dataset| dataset.name == dataset_name)
+            .map(|dataset| dataset.compression_algorithm)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Dataset '{}' not found", dataset_name)))
+    }
+
+    /// Describe the schema and bounds of the table of a chromosome in a dataset, reading its index if it isn't cached
+    pub fn describe_table(&mut self, dataset_name: &str, chromosome: u8) -> std::io::Result<TableDescription> {
+        let header = self.read_database_header()?;
+
+        let dataset = header.datasets.into_iter()
+            .find(|dataset| dataset.name == dataset_name)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Dataset '{}' not found", dataset_name)))?;
+
+        let table = dataset.tables.iter()
+            .find(|table| table.chromosome == chromosome)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!(
+                "Chromosome {} not found in dataset '{}'", chromosome, dataset_name,
+            )))?;
+
+        let index = self.read_table_index(table)?;
+
+        Ok(TableDescription {
+            dataset_name: dataset.name,
+            chromosome,
+            min_position: index.min_position(),
+            max_position: index.max_position,
+            num_rows: index.num_rows(),
+            num_blocks: index.num_blocks(),
+            compression_algorithm: dataset.compression_algorithm,
+            index_compression_algorithm: table.index_compression_algorithm,
+            index_size: index.index_size(),
+            columns: dataset.columns,
+        })
+    }
+
+    /// Compute the checksum of the blocks and index of a table, which span from its first block to the end of its index
+    pub fn table_checksum(&mut self, table: &TableHeader) -> std::io::Result<u64> {
+        let index = self.read_table_index(table)?;
+        let region_start = index.inner.values().min().copied().unwrap_or(index.index_start_offset);
+
+        self.reader.seek(SeekFrom::Start(region_start))?;
+        table_checksum(self.reader.by_ref().take(index.index_end_offset - region_start))
+    }
+
+    /// Read the checksum of a table that is stored after its index
+    pub fn read_table_checksum(&mut self, table: &TableHeader) -> std::io::Result<u64> {
+        let index = self.read_table_index(table)?;
+
+        self.reader.seek(SeekFrom::Start(index.index_end_offset))?;
+        let mut buf = [0; size_of::<u64>()];
+        self.reader.read_exact(&mut buf)?;
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    /// Check the checksum of the blocks and index of a table against the checksum stored after its index. Unlike
+    /// `RowReader::verify_checksums`, a mismatch only tells that the table is corrupted, not which block.
+    pub fn verify_table_checksum(&mut self, table: &TableHeader) -> std::io::Result<()> {
+        let expected = self.read_table_checksum(table)?;
+        let checksum = self.table_checksum(table)?;
+
+        if checksum != expected {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "Table of chromosome {} has checksum {:#018x}, but {:#018x} is stored after its index",
+                table.chromosome, checksum, expected,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Check the checksum of every table of the database. Returns the number of tables that were checked, or an error
+    /// for the first table of which the checksum doesn't match.
+    pub fn verify_table_checksums(&mut self) -> std::io::Result<usize> {
+        let header = self.read_database_header()?;
+        let mut num_tables = 0;
+
+        for dataset in &header.datasets {
+            for table in &dataset.tables {
+                self.verify_table_checksum(table).map_err(|e| Error::new(e.kind(), format!("Dataset '{}': {}", dataset.name, e)))?;
+                num_tables += 1;
+            }
+        }
+
+        Ok(num_tables)
+    }
+
+    /// Read a region of the database file, for example to inspect the layout of a table.
+    /// The region is truncated if it extends past the end of the file.
+    pub fn read_region(&mut self, offset: u64, length: u64) -> std::io::Result<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+
+        let mut bytes = Vec::new();
+        self.reader.by_ref().take(length).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Format a region of the database file as a hex and ASCII view, labelled with the offsets in the file.
+    pub fn hexdump_region(&mut self, offset: u64, length: u64) -> std::io::Result<String> {
+        let bytes = self.read_region(offset, length)?;
+        Ok(rhexdumps!(&bytes[..], offset))
+    }
+
+    /// Check if a dataset has a table for a chromosome without parsing the entire header.
+    /// The columns and tables of the datasets before the requested one are skipped over.
+    pub fn has_chromosome(&mut self, dataset_name: &str, chromosome: u8) -> std::io::Result<bool> {
+        self.read_database_preamble()?;
+
+        let num_datasets = self.read_u8()? as usize;
+
+        for _ in 0..num_datasets {
+            let name = self.read_string_u8()?;
+
+            // Compression algorithm, rows per index, position decimals, coordinate base and block layout
+            self.reader.seek(SeekFrom::Current(1 + 8 + 1 + 1 + 1))?;
+
+            let num_columns = self.read_u8()? as usize;
+            for _ in 0..num_columns {
+                // Column type
+                self.reader.seek(SeekFrom::Current(1))?;
+                let name_len = self.read_u8()? as i64;
+                self.reader.seek(SeekFrom::Current(name_len))?;
             }
 
-            datasets.push(DatasetHeader{ name, compression_algorithm, columns, tables });
+            let num_tables = self.read_u8()? as usize;
+
+            if name != dataset_name {
+                self.reader.seek(SeekFrom::Current((num_tables * TABLE_HEADER_SIZE) as i64))?;
+                continue;
+            }
+
+            for _ in 0..num_tables {
+                if self.read_u8()? == chromosome {
+                    return Ok(true);
+                }
+                self.reader.seek(SeekFrom::Current(TABLE_HEADER_SIZE as i64 - 1))?;
+            }
+
+            return Ok(false);
+        }
+
+        Ok(false)
+    }
+
+    /// Read the index of a table, or get it from the cache if it was read or prefetched before. The index is cached, so
+    /// reading the same table again doesn't read the file, until the cache is cleared with `clear_index_cache`. The
+    /// cached index is shared with the returned one instead of copied.
+    pub fn read_table_index(&mut self, table: &TableHeader) -> std::io::Result<Arc<TableIndex>> {
+        if let Some(index) = self.index_cache.get(&table.offset) {
+            return Ok(Arc::clone(index));
+        }
+
+        let index = Arc::new(self.read_table_index_uncached(table)?);
+        self.index_cache.insert(table.offset, Arc::clone(&index));
+
+        Ok(index)
+    }
+
+    /// Read and cache the indices of all tables of a dataset, so later calls to `read_table_index` don't read the file.
+    /// The indices are read in parallel if the `parallel` feature is enabled, each with its own reader created by
+    /// `open_reader`.
+    pub fn prefetch_indices<F, S>(&mut self, dataset: &DatasetHeader, open_reader: F) -> std::io::Result<()>
+    where
+        F: Fn() -> std::io::Result<S> + Sync,
+        S: Read + Seek,
+    {
+        let uncached_tables: Vec<&TableHeader> = dataset.tables.iter()
+            .filter(|table| !self.index_cache.contains_key(&table.offset))
+            .collect();
+
+        #[cfg(feature = "parallel")]
+        let uncached_tables = uncached_tables.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let uncached_tables = uncached_tables.into_iter();
+
+        let indices = uncached_tables
+            .map(|table| {
+                let mut client = DatabaseQueryClient::new(open_reader()?);
+                Ok((table.offset, Arc::new(client.read_table_index_uncached(table)?)))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        self.index_cache.extend(indices);
+
+        Ok(())
+    }
+
+    /// Drop the cached indices of all tables, to free their memory
+    pub fn clear_index_cache(&mut self) {
+        self.index_cache.clear();
+    }
+
+    /// Read only the entries of the index of a table that are needed to query `[start, end)`, using the skip table of
+    /// the index. The returned index contains a contiguous part of the entries, so it can only be used for queries
+    /// within the range. Compressed indices have no skip table and are read entirely. If the full index was already read
+    /// or prefetched, the cached index is returned instead.
+    pub fn read_table_index_range(
+        &mut self,
+        table: &TableHeader,
+        start: u64,
+        end: u64,
+    ) -> std::io::Result<Arc<TableIndex>> {
+        if let Some(index) = self.index_cache.get(&table.offset) {
+            return Ok(Arc::clone(index));
         }
 
-        Ok(DatabaseHeader{ version, datasets })
+        let header = self.read_index_header(table)?;
+        if header.skip_table.is_empty() {
+            return self.read_index_entries(table, &header).map(Arc::new);
+        }
+
+        // Start at the part with the block containing the start, and end after the part with the first block at or
+        // after the end, as its offset is the end of the last block in the range
+        let skip_table = &header.skip_table;
+        let first_part = skip_table.partition_point(|(position, _)| *position <= start).saturating_sub(1);
+        let last_part = skip_table.partition_point(|(position, _)| *position < end).min(skip_table.len() - 1);
+
+        let part_start = header.entries_offset + skip_table[first_part].1;
+        let entries_end = TableIndex::entries_end_offset(&header);
+        let part_end = skip_table.get(last_part + 1).map_or(entries_end, |(_, offset)| header.entries_offset + offset);
+
+        let part_len = part_end.checked_sub(part_start).filter(|_| part_end <= entries_end)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid skip table of table index at offset {}", table.offset)))?;
+
+        self.reader.seek(SeekFrom::Start(part_start))?;
+
+        let mut entries = Vec::new();
+        self.reader.by_ref().take(part_len).read_to_end(&mut entries)?;
+
+        let mut index = TableIndex::empty(table, &header);
+        let mut cursor = Cursor::new(&entries[..]);
+        while cursor.position() < entries.len() as u64 {
+            index.read_entry(&mut cursor)?;
+        }
+
+        Ok(Arc::new(index))
+    }
+
+    fn read_table_index_uncached(&mut self, table: &TableHeader) -> std::io::Result<TableIndex> {
+        let header = self.read_index_header(table)?;
+        self.read_index_entries(table, &header)
     }
 
-    pub fn read_table_index(&mut self, offset: u64) -> std::io::Result<TableIndex> {
+    /// Read the part of the index of a table before its entries, leaving the reader at the start of the entries
+    fn read_index_header(&mut self, table: &TableHeader) -> std::io::Result<IndexHeader> {
+        let offset = table.offset;
+
+        // A database that is still being downloaded can have a complete header and tables, but a missing tail
+        let file_len = self.reader.seek(SeekFrom::End(0))?;
+        let table_unavailable = |end: u64| Error::new(ErrorKind::UnexpectedEof, format!(
+            "Table of chromosome {} is not available yet: its index ends at offset {}, but the file is only {} bytes long",
+            table.chromosome, end, file_len,
+        ));
+
+        let index_header_end = offset.saturating_add((INDEX_MAGIC.len() + 4 * size_of::<u64>()) as u64);
+        if index_header_end > file_len {
+            return Err(table_unavailable(index_header_end));
+        }
+
         self.reader.seek(SeekFrom::Start(offset))?;
 
         {
@@ -146,38 +568,387 @@ impl<R: Read + Seek> DatabaseQueryClient<R> {
         let max_position = self.read_u64()?;
 
         let end_offset = self.read_u64()?;
-        let num_indices = self.read_u64()?;
-
-        let mut res = BTreeMap::new();
 
-        for _ in 0..num_indices {
-            let position = self.read_vint64()?;
-            let offset = self.read_vint64()?;
+        if end_offset > file_len {
+            return Err(table_unavailable(end_offset));
+        }
 
-            res.insert(position, offset);
+        let end_index_offset = Some(self.read_u64()?).filter(|&end_index_offset| end_index_offset != 0);
+        if end_index_offset.is_some_and(|end_index_offset| end_index_offset > end_offset) {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "Invalid end index offset {:?} of table index at offset {}", end_index_offset, offset,
+            )));
         }
 
-        Ok(TableIndex{
-            inner: res,
+        let skip_table_len = self.read_u64()?;
+        let skip_table_size = skip_table_len.checked_mul(2 * size_of::<u64>() as u64)
+            .filter(|size| self.reader.stream_position().is_ok_and(|position| position + size <= end_index_offset.unwrap_or(end_offset)))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid skip table length {} of table index at offset {}", skip_table_len, offset)))?;
+
+        let mut skip_table_bytes = Vec::with_capacity(skip_table_size as usize);
+        self.reader.by_ref().take(skip_table_size).read_to_end(&mut skip_table_bytes)?;
+
+        let mut cursor = Cursor::new(&skip_table_bytes[..]);
+        let skip_table = (0..skip_table_len)
+            .map(|_| Ok((deserialize::read_u64(&mut cursor)?, deserialize::read_u64(&mut cursor)?)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(IndexHeader {
             max_position,
-            index_start_offset: offset,
-            index_end_offset: end_offset,
+            end_offset,
+            end_index_offset,
+            skip_table,
+            entries_offset: self.reader.stream_position()?,
         })
     }
+
+    /// Read and decompress all entries of the index of a table, starting at the current position of the reader
+    fn read_index_entries(&mut self, table: &TableHeader, header: &IndexHeader) -> std::io::Result<TableIndex> {
+        // The rest of the index is compressed and bounded by the start of the end index or the end offset
+        let entries_end = TableIndex::entries_end_offset(header);
+        let compressed_len = entries_end.checked_sub(header.entries_offset)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid table index end offset {} at offset {}", entries_end, table.offset)))?;
+
+        let mut compressed = Vec::new();
+        self.reader.by_ref().take(compressed_len).read_to_end(&mut compressed)?;
+
+        let mut decompressed = Vec::new();
+        let decompressor = RowDecompressor::new(table.index_compression_algorithm);
+        let mut cursor = Cursor::new(decompressor.decompress(&compressed, &mut decompressed)?);
+
+        let num_indices = deserialize::read_u64(&mut cursor)?;
+
+        let mut index = TableIndex::empty(table, header);
+        for _ in 0..num_indices {
+            index.read_entry(&mut cursor)?;
+        }
+
+        // The end index directly follows the entries
+        if header.end_index_offset.is_some() {
+            compressed.clear();
+            self.reader.by_ref().take(header.end_offset - entries_end).read_to_end(&mut compressed)?;
+
+            let mut cursor = Cursor::new(decompressor.decompress(&compressed, &mut decompressed)?);
+            let num_end_indices = deserialize::read_u64(&mut cursor)?;
+
+            let end_index = (0..num_end_indices)
+                .map(|_| Ok((
+                    deserialize::read_vint64(&mut cursor)?,
+                    deserialize::read_vint64(&mut cursor)?,
+                    deserialize::read_vint64(&mut cursor)?,
+                )))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            index.end_index = Some(end_index);
+        }
+
+        Ok(index)
+    }
+}
+
+impl DatabaseQueryClient<Cursor<MappedFile>> {
+    /// Open a database by mapping its file into memory. The readers created with `RowReader::new_mapped` from the
+    /// mapped file of the client decompress their blocks straight from the mapping.
+    pub fn open_mapped(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(Cursor::new(MappedFile::open(path)?)))
+    }
+
+    pub fn mapped_file(&self) -> &MappedFile {
+        self.reader.get_ref()
+    }
+}
+
+impl<R: Read + Write + Seek> DatabaseQueryClient<R> {
+    /// Overwrite the value of a float column in the first row at a position, without rebuilding the database. Only
+    /// uncompressed datasets are supported, and only float columns have a fixed width that can be overwritten in place.
+    ///
+    /// The position is the value that is stored, like the bounds of `RowReader::query_range`. Float positions must be
+    /// scaled first with `RowReader::scale_position`, and positions in another coordinate base translated with
+    /// `DatasetHeader::translate_position`.
+    pub fn update_cell(&mut self, dataset_name: &str, chromosome: u8, position: u64, column_name: &str, value: f64) -> std::io::Result<()> {
+        let header = self.read_database_header()?;
+
+        let dataset = header.datasets.iter()
+            .find(|dataset| dataset.name == dataset_name)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Dataset '{}' not found", dataset_name)))?;
+
+        if dataset.compression_algorithm != CompressionAlgorithm::None {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Cells can only be updated in uncompressed datasets, but dataset '{}' is compressed with {:?}",
+                dataset_name, dataset.compression_algorithm,
+            )));
+        }
+
+        let column = dataset.columns.iter()
+            .position(|column| column.name == column_name)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Column '{}' not found", column_name)))?;
+
+        if dataset.columns[column].type_ != ColumnType::Float {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Column '{}' has type {:?}, but only float columns have a fixed width and can be updated",
+                column_name, dataset.columns[column].type_,
+            )));
+        }
+
+        let table = dataset.tables.iter()
+            .find(|table| table.chromosome == chromosome)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!(
+                "Chromosome {} not found in dataset '{}'", chromosome, dataset_name,
+            )))?;
+
+        // The checksum of the block is updated in place in its index entry
+        if table.index_compression_algorithm != CompressionAlgorithm::None {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Cells can only be updated in tables with an uncompressed index, but the index of chromosome {} is compressed with {:?}",
+                chromosome, table.index_compression_algorithm,
+            )));
+        }
+
+        let index = self.read_table_index(table)?;
+
+        // Rows at the position can only be in the block before it or in blocks that start at it
+        for (_, offset) in index.get_range(position, position.saturating_add(1)) {
+            let block_size = *index.compressed_block_sizes.get(&offset).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!(
+                "Missing size of the block of chromosome {} at offset {}", chromosome, offset,
+            )))?;
+
+            let mut block = vec![0; block_size as usize];
+            self.reader.seek(SeekFrom::Start(offset))?;
+            self.reader.read_exact(&mut block)?;
+
+            if let Some(cell_offset) = find_cell_offset(&block, dataset, position, column)? {
+                let cell_offset = cell_offset as usize;
+                block[cell_offset..cell_offset + size_of::<f64>()].copy_from_slice(&value.to_be_bytes());
+
+                self.reader.seek(SeekFrom::Start(offset + cell_offset as u64))?;
+                self.reader.write_all(&value.to_be_bytes())?;
+                self.write_block_checksum(table, offset, block_checksum(&block))?;
+                self.write_table_checksum(table)?;
+                return self.reader.flush();
+            }
+        }
+
+        Err(Error::new(ErrorKind::InvalidInput, format!(
+            "No row at position {} in chromosome {} of dataset '{}'", position, chromosome, dataset_name,
+        )))
+    }
+
+    /// Recompute the checksum of a table after its blocks or index were changed, and overwrite the one after its index
+    fn write_table_checksum(&mut self, table: &TableHeader) -> std::io::Result<()> {
+        let checksum = self.table_checksum(table)?;
+        let index = self.read_table_index(table)?;
+
+        self.reader.seek(SeekFrom::Start(index.index_end_offset))?;
+        self.reader.write_all(&checksum.to_be_bytes())
+    }
+
+    /// Overwrite the checksum in the index entry of the block at an offset. The index must be uncompressed, so its
+    /// entries are read one by one until the entry of the block is found.
+    fn write_block_checksum(&mut self, table: &TableHeader, offset: u64, checksum: u32) -> std::io::Result<()> {
+        let header = self.read_index_header(table)?;
+        let entries_end = TableIndex::entries_end_offset(&header);
+
+        let mut entries = Vec::new();
+        Read::by_ref(&mut self.reader).take(entries_end.saturating_sub(header.entries_offset)).read_to_end(&mut entries)?;
+
+        let mut cursor = Cursor::new(&entries[..]);
+        let num_indices = deserialize::read_u64(&mut cursor)?;
+
+        for _ in 0..num_indices {
+            let _position = deserialize::read_vint64(&mut cursor)?;
+            let entry_offset = deserialize::read_vint64(&mut cursor)?;
+            let _compressed_size = deserialize::read_vint64(&mut cursor)?;
+            let _num_rows = deserialize::read_vint64(&mut cursor)?;
+
+            if entry_offset == offset {
+                self.reader.seek(SeekFrom::Start(header.entries_offset + cursor.position()))?;
+                self.reader.write_all(&checksum.to_be_bytes())?;
+
+                if let Some(index) = self.index_cache.get_mut(&table.offset) {
+                    Arc::make_mut(index).block_checksums.insert(offset, checksum);
+                }
+                return Ok(());
+            }
+
+            deserialize::read_u32(&mut cursor)?;
+        }
+
+        Err(Error::new(ErrorKind::InvalidData, format!(
+            "Missing index entry of the block of chromosome {} at offset {}", table.chromosome, offset,
+        )))
+    }
+}
+
+/// Find the offset of a cell in the first row at a position in an uncompressed block
+fn find_cell_offset(block: &[u8], dataset: &DatasetHeader, position: u64, column: usize) -> std::io::Result<Option<u64>> {
+    let columns = &dataset.columns;
+    if dataset.columnar {
+        return find_columnar_cell_offset(block, columns, position, column);
+    }
+
+    let mut cursor: Cursor<&[u8]> = Cursor::new(block);
+
+    // Interned strings have to be read to skip them, as later rows can reference them
+    let mut string_pools: Vec<Vec<Arc<str>>> = vec![Vec::new(); columns.len()];
+
+    while (cursor.position() as usize) < block.len() {
+        let (row_position, _) = deserialize::read_zigzag_i64(&mut cursor)?;
+        if row_position as u64 > position {
+            return Ok(None);
+        }
+
+        for (i, header) in columns.iter().enumerate().skip(1) {
+            if i == column && row_position as u64 == position {
+                return Ok(Some(cursor.position()));
+            }
+
+            match header.type_ {
+                ColumnType::Integer => deserialize::skip_zigzag_i64(&mut cursor)?,
+                ColumnType::Float => deserialize::skip_f64(&mut cursor)?,
+                // Hashtable strings are written like volatile strings
+                ColumnType::VolatileString | ColumnType::HashtableString => deserialize::skip_string_varlen(&mut cursor)?,
+                ColumnType::InternedString => deserialize::skip_interned_string(&mut cursor, &mut string_pools[i])?,
+                ColumnType::Char => deserialize::skip_char(&mut cursor)?,
+                ColumnType::Boolean => deserialize::skip_bool(&mut cursor)?,
+            };
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the offset of a float cell in the first row at a position in an uncompressed columnar block
+fn find_columnar_cell_offset(block: &[u8], columns: &[ColumnHeader], position: u64, column: usize) -> std::io::Result<Option<u64>> {
+    let column_bytes = split_columns(block, columns.len())?;
+
+    let (_, positions) = column_bytes[0];
+    let mut cursor: Cursor<&[u8]> = Cursor::new(positions);
+
+    let mut i_row = 0;
+    while (cursor.position() as usize) < positions.len() {
+        let (row_position, _) = deserialize::read_zigzag_i64(&mut cursor)?;
+        if row_position as u64 > position {
+            return Ok(None);
+        }
+
+        // Floats have a fixed width, so the cell is found without reading the cells before it
+        if row_position as u64 == position {
+            let (column_offset, _) = column_bytes[column];
+            return Ok(Some(column_offset + (i_row * size_of::<f64>()) as u64));
+        }
+
+        i_row += 1;
+    }
+
+    Ok(None)
+}
+
+/// The part of a table index before its entries
+struct IndexHeader {
+    max_position: u64,
+    end_offset: u64,
+    /// The offset in the file where the end index starts, which is also where the entries end
+    end_index_offset: Option<u64>,
+    /// The position and offset relative to `entries_offset` of every `INDEX_SKIP_INTERVAL`th entry. Empty if the
+    /// index is compressed.
+    skip_table: Vec<(u64, u64)>,
+    /// The offset in the file where the entries start
+    entries_offset: u64,
 }
 
 #[derive(Clone)]
 pub struct TableIndex {
     pub inner: BTreeMap<u64, u64>,
+    /// The compressed size of each block by its offset. Blocks can be followed by padding, so this can be less than
+    /// the distance to the next block.
+    pub compressed_block_sizes: HashMap<u64, u64>,
+    /// The number of rows of each block by its offset
+    pub block_row_counts: HashMap<u64, u64>,
+    /// The CRC32 checksum of the compressed bytes of each block by its offset
+    pub block_checksums: HashMap<u64, u32>,
+    /// The chromosome of the table
+    pub chromosome: u8,
     /// The maximum position in the index (inclusive)
     pub max_position: u64,
     /// The offset in the file where the magic of the index is located
     pub index_start_offset: u64,
     /// The offset in the file where the index ends (exclusive)
     pub index_end_offset: u64,
+    /// The smallest and largest end position and the offset of every block of an interval table, sorted by the smallest
+    /// end. `None` if the table has no end index, or if only a range of the index was read.
+    pub end_index: Option<Vec<(u64, u64, u64)>>,
 }
 
 impl TableIndex {
+    fn empty(table: &TableHeader, header: &IndexHeader) -> Self {
+        Self {
+            inner: BTreeMap::new(),
+            compressed_block_sizes: HashMap::new(),
+            block_row_counts: HashMap::new(),
+            block_checksums: HashMap::new(),
+            chromosome: table.chromosome,
+            max_position: header.max_position,
+            index_start_offset: table.offset,
+            index_end_offset: header.end_offset,
+            end_index: None,
+        }
+    }
+
+    /// The offset in the file where the entries of the index end
+    fn entries_end_offset(header: &IndexHeader) -> u64 {
+        header.end_index_offset.unwrap_or(header.end_offset)
+    }
+
+    /// Read an entry of the index and add its block
+    fn read_entry(&mut self, cursor: &mut Cursor<&[u8]>) -> std::io::Result<()> {
+        let position = deserialize::read_vint64(cursor)?;
+        let offset = deserialize::read_vint64(cursor)?;
+        let compressed_size = deserialize::read_vint64(cursor)?;
+        let num_rows = deserialize::read_vint64(cursor)?;
+        let checksum = deserialize::read_u32(cursor)?;
+
+        self.inner.insert(position, offset);
+        self.compressed_block_sizes.insert(offset, compressed_size);
+        self.block_row_counts.insert(offset, num_rows);
+        self.block_checksums.insert(offset, checksum);
+
+        Ok(())
+    }
+
+    /// The position of the first row of the table
+    pub fn min_position(&self) -> u64 {
+        self.inner.keys().next().copied().unwrap_or(0)
+    }
+
+    /// The number of blocks in the table, which is the number of entries in the index
+    pub fn num_blocks(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// The number of rows in the table
+    pub fn num_rows(&self) -> u64 {
+        self.block_row_counts.values().sum()
+    }
+
+    /// The number of rows of the block at an offset, which every block in the index has
+    pub fn block_row_count(&self, offset: u64) -> std::io::Result<u64> {
+        self.block_row_counts.get(&offset).copied().ok_or_else(|| Error::new(ErrorKind::InvalidData, format!(
+            "Block at offset {} of chromosome {} has no row count in the index", offset, self.chromosome,
+        )))
+    }
+
+    /// The size of the index in the file in bytes
+    pub fn index_size(&self) -> u64 {
+        self.index_end_offset - self.index_start_offset
+    }
+
+    /// Estimate the number of rows in a range without reading any blocks. This is the number of rows of all blocks
+    /// overlapping the range, so it is never less than the actual number of rows.
+    pub fn estimate_rows(&self, start: u64, end: u64) -> u64 {
+        self.get_range(start, end).iter()
+            .map(|(_, offset)| self.block_row_counts.get(offset).copied().unwrap_or(0))
+            .sum()
+    }
+
     pub fn get_all(&self) -> Vec<(u64, u64)> {
         self.inner.iter().map(|(k, v)| (*k, *v)).collect()
     }
@@ -213,4 +984,3397 @@ impl TableIndex {
 
         indices
     }
-}
\ No newline at end of file
+}
+
+pub type Row = Vec<CellValue>;
+
+/// The tolerance used when comparing float cells, as exact float equality breaks on rounding errors.
+/// Two floats are considered equal if they are within the absolute tolerance or within the relative tolerance of the
+/// largest of both. Non-float cells are always compared exactly.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FloatTolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl FloatTolerance {
+    pub const EXACT: Self = Self { absolute: 0.0, relative: 0.0 };
+
+    pub fn floats_equal(&self, a: f64, b: f64) -> bool {
+        // Missing values are stored as NaN, so two of them are the same value
+        if a == b || (a.is_nan() && b.is_nan()) {
+            return true;
+        }
+
+        let difference = (a - b).abs();
+        difference <= self.absolute || difference <= self.relative * a.abs().max(b.abs())
+    }
+
+    pub fn cells_equal(&self, a: &CellValue, b: &CellValue) -> bool {
+        match (a, b) {
+            (CellValue::Float(a), CellValue::Float(b)) => self.floats_equal(*a, *b),
+            _ => a == b,
+        }
+    }
+
+    pub fn rows_equal(&self, a: &[CellValue], b: &[CellValue]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.cells_equal(a, b))
+    }
+}
+
+/// A cell that borrows its string from the decompressed block it was read from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellRef<'a> {
+    Integer(i64),
+    Float(f64),
+    String(&'a str),
+}
+
+impl CellRef<'_> {
+    pub fn to_cell_value(self) -> CellValue {
+        match self {
+            Self::Integer(i) => CellValue::Integer(i),
+            Self::Float(f) => CellValue::Float(f),
+            Self::String(s) => CellValue::String(s.into()),
+        }
+    }
+}
+
+/// The value of a column that rows are grouped by
+#[derive(PartialEq, Eq, Hash)]
+enum GroupKey {
+    Integer(i64),
+    String(Arc<str>),
+}
+
+/// The type that the cells of a column are converted to in the rows returned by a query
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellCoercion {
+    /// Integers are returned as floats
+    Float,
+    /// Integers and floats are returned as strings
+    String,
+}
+
+impl CellCoercion {
+    /// Whether the cells of a column of a type can be converted
+    fn accepts(&self, type_: ColumnType) -> bool {
+        match self {
+            Self::Float => matches!(type_, ColumnType::Integer | ColumnType::Boolean),
+            Self::String => matches!(type_, ColumnType::Integer | ColumnType::Float | ColumnType::Boolean),
+        }
+    }
+
+    fn apply(&self, cell: &mut CellValue) {
+        *cell = match (self, &*cell) {
+            (Self::Float, CellValue::Integer(value)) => CellValue::Float(*value as f64),
+            (Self::String, CellValue::Integer(value)) => CellValue::String(value.to_string().into()),
+            (Self::String, CellValue::Float(value)) => CellValue::String(value.to_string().into()),
+            _ => return,
+        };
+    }
+}
+
+/// The bounds of a block, as the (position, offset) of the block and the (position, offset) of the block after it. The
+/// last block of a table ends at `(u64::MAX, index_start_offset)`, as no block comes after it.
+pub type BlockBounds = ((u64, u64), (u64, u64));
+
+/// The rows of a range, with the rows just before and after it
+#[derive(Clone, Debug, Default)]
+pub struct RowsWithContext {
+    /// The rows before the start of the range, closest to the start last
+    pub before: Vec<Row>,
+    /// The rows within the range
+    pub rows: Vec<Row>,
+    /// The rows at or after the end of the range, closest to the end first
+    pub after: Vec<Row>,
+}
+
+/// A cache of decompressed blocks keyed by their offset and compressed size, which evicts the least recently used block
+/// once it holds `capacity` blocks. It can be shared by the readers of a file with `Arc<Mutex<_>>`, so readers of
+/// overlapping ranges don't each decompress the same blocks.
+pub struct BlockCache {
+    capacity: usize,
+    /// The decompressed bytes of the blocks and the tick at which they were last used
+    blocks: HashMap<BlockCacheKey, (Arc<[u8]>, u64)>,
+    /// The keys of the blocks by the tick at which they were last used, oldest first
+    recently_used: BTreeMap<u64, BlockCacheKey>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// The offset and compressed size of a block
+type BlockCacheKey = (u64, u64);
+
+pub type SharedBlockCache = Arc<Mutex<BlockCache>>;
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: HashMap::new(),
+            recently_used: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Create a cache that can be shared by several readers
+    pub fn shared(capacity: usize) -> SharedBlockCache {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    /// Get the decompressed bytes of a block and mark it as recently used
+    pub fn get(&mut self, offset: u64, compressed_size: u64) -> Option<Arc<[u8]>> {
+        self.tick += 1;
+
+        match self.blocks.get_mut(&(offset, compressed_size)) {
+            Some((bytes, last_used)) => {
+                self.recently_used.remove(last_used);
+                self.recently_used.insert(self.tick, (offset, compressed_size));
+                *last_used = self.tick;
+                self.hits += 1;
+                Some(bytes.clone())
+            },
+            None => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    /// Add the decompressed bytes of a block, evicting the least recently used blocks if the cache is full
+    pub fn insert(&mut self, offset: u64, compressed_size: u64, bytes: Arc<[u8]>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.tick += 1;
+
+        if let Some((_, last_used)) = self.blocks.insert((offset, compressed_size), (bytes, self.tick)) {
+            self.recently_used.remove(&last_used);
+        }
+        self.recently_used.insert(self.tick, (offset, compressed_size));
+
+        while self.blocks.len() > self.capacity {
+            let Some((_, key)) = self.recently_used.pop_first() else {
+                break;
+            };
+            self.blocks.remove(&key);
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of blocks in the cache
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The number of lookups that found their block in the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of lookups that had to read and decompress their block
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.recently_used.clear();
+    }
+}
+
+pub struct RowReader<R: Read + Seek> {
+    reader: R,
+    index: Arc<TableIndex>,
+    columns: Vec<ColumnHeader>,
+    compression_algorithm: CompressionAlgorithm,
+    position_decimals: Option<u8>,
+    coordinate_base: Option<u8>,
+    columnar: bool,
+    strict_bounds: bool,
+    max_rows: Option<u64>,
+    read_ahead: bool,
+    max_buffer_capacity: Option<usize>,
+    max_decompressed_size: Option<u64>,
+    block_cache: Option<SharedBlockCache>,
+    /// The file that `reader` reads from, if it is mapped into memory, so blocks can be decompressed without copying them
+    mapped_file: Option<MappedFile>,
+    /// The conversion of the cells of each column in the rows returned by `deserialize_range`
+    column_coercions: Vec<Option<CellCoercion>>,
+}
+
+/// The number of blocks that are decompressed ahead of the block that is being deserialized
+const READ_AHEAD_BLOCKS: usize = 1;
+
+impl RowReader<Cursor<MappedFile>> {
+    /// Create a reader of a table of a file that is mapped into memory. Blocks are decompressed straight from the
+    /// mapping, without reading them into a buffer first.
+    pub fn new_mapped(mapped_file: MappedFile, dataset: &DatasetHeader, index: impl Into<Arc<TableIndex>>) -> Self {
+        let mut reader = Self::new(Cursor::new(mapped_file.clone()), dataset, index);
+        reader.mapped_file = Some(mapped_file);
+        reader
+    }
+}
+
+impl<R: Read + Seek> RowReader<R> {
+    pub fn new(reader: R, dataset: &DatasetHeader, index: impl Into<Arc<TableIndex>>) -> Self {
+        Self {
+            reader,
+            index: index.into(),
+            columns: dataset.columns.clone(),
+            compression_algorithm: dataset.compression_algorithm,
+            position_decimals: dataset.position_decimals,
+            coordinate_base: dataset.coordinate_base,
+            columnar: dataset.columnar,
+            strict_bounds: cfg!(debug_assertions),
+            max_rows: None,
+            read_ahead: false,
+            max_buffer_capacity: None,
+            max_decompressed_size: None,
+            block_cache: None,
+            mapped_file: None,
+            column_coercions: vec![None; dataset.columns.len()],
+        }
+    }
+
+    /// Verify that the position of every row returned by `query_range` is within the requested range.
+    /// Enabled by default in debug builds.
+    pub fn set_strict_bounds(&mut self, strict_bounds: bool) {
+        self.strict_bounds = strict_bounds;
+    }
+
+    pub fn strict_bounds(&self) -> bool {
+        self.strict_bounds
+    }
+
+    /// Reject queries of ranges that could contain more than `max_rows` rows before any block is read
+    pub fn set_max_rows(&mut self, max_rows: Option<u64>) {
+        self.max_rows = max_rows;
+    }
+
+    pub fn max_rows(&self) -> Option<u64> {
+        self.max_rows
+    }
+
+    /// Decompress the next block on a background thread while the rows of the current block are deserialized by
+    /// `query_range`. Speeds up scans of many blocks, but costs a thread per query.
+    pub fn set_read_ahead(&mut self, read_ahead: bool) {
+        self.read_ahead = read_ahead;
+    }
+
+    pub fn read_ahead(&self) -> bool {
+        self.read_ahead
+    }
+
+    /// Shrink the buffer that blocks are decompressed into back to `max_buffer_capacity` bytes after a larger block, so a
+    /// single large block doesn't hold on to its memory for the rest of the query
+    pub fn set_max_buffer_capacity(&mut self, max_buffer_capacity: Option<usize>) {
+        self.max_buffer_capacity = max_buffer_capacity;
+    }
+
+    pub fn max_buffer_capacity(&self) -> Option<usize> {
+        self.max_buffer_capacity
+    }
+
+    /// Fail instead of decompressing a block into more than `max_decompressed_size` bytes
+    pub fn set_max_decompressed_size(&mut self, max_decompressed_size: Option<u64>) {
+        self.max_decompressed_size = max_decompressed_size;
+    }
+
+    pub fn max_decompressed_size(&self) -> Option<u64> {
+        self.max_decompressed_size
+    }
+
+    fn block_decompressor(&self) -> RowDecompressor {
+        let mut decompressor = RowDecompressor::with_max_buffer_capacity(self.compression_algorithm, self.max_buffer_capacity);
+        decompressor.set_max_decompressed_size(self.max_decompressed_size);
+        decompressor
+    }
+
+    /// Look up the decompressed blocks in a cache before reading them, and add the blocks that are read to it. The cache
+    /// can be shared with other readers of the same file. Ranges are read without read-ahead while a cache is set.
+    pub fn set_block_cache(&mut self, block_cache: Option<SharedBlockCache>) {
+        self.block_cache = block_cache;
+    }
+
+    pub fn block_cache(&self) -> Option<&SharedBlockCache> {
+        self.block_cache.as_ref()
+    }
+
+    /// Convert the cells of a column to another type in the rows returned by `query_range` and the other queries that
+    /// return rows, so the caller doesn't have to convert every cell. The position column can't be converted, as the
+    /// positions of the rows are compared to the range.
+    pub fn set_column_coercion(&mut self, column: usize, coercion: Option<CellCoercion>) -> std::io::Result<()> {
+        let header = self.columns.get(column).ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!(
+            "Column index {} is out of bounds for {} columns", column, self.columns.len(),
+        )))?;
+
+        if let Some(coercion) = coercion {
+            if column == 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Position column '{}' can't be converted", header.name)));
+            }
+            if !coercion.accepts(header.type_) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!(
+                    "Column '{}' of type {:?} can't be converted to {:?}", header.name, header.type_, coercion,
+                )));
+            }
+        }
+
+        self.column_coercions[column] = coercion;
+        Ok(())
+    }
+
+    pub fn column_coercion(&self, column: usize) -> Option<CellCoercion> {
+        self.column_coercions.get(column).copied().flatten()
+    }
+
+    /// Check that a range can't contain more rows than the limit, using the row counts of the blocks in the index
+    pub fn check_row_limit(&self, position_value_start: u64, position_value_end: u64) -> std::io::Result<()> {
+        let Some(max_rows) = self.max_rows else {
+            return Ok(());
+        };
+
+        let estimated_rows = self.index.estimate_rows(position_value_start, position_value_end);
+        if estimated_rows > max_rows {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Range [{}, {}) of chromosome {} can contain up to {} rows, which exceeds the limit of {} rows",
+                position_value_start, position_value_end, self.index.chromosome, estimated_rows, max_rows,
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn index(&self) -> &TableIndex {
+        &self.index
+    }
+
+    pub fn columns(&self) -> &[ColumnHeader] {
+        &self.columns
+    }
+
+    /// Find the indices of columns by their names, in the order of the names, such as to project a query onto them
+    pub fn column_indices<S: AsRef<str>>(&self, names: &[S]) -> std::io::Result<Vec<usize>> {
+        names.iter()
+            .map(|name| self.columns.iter()
+                .position(|column| column.name == name.as_ref())
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Column '{}' not found", name.as_ref()))))
+            .collect()
+    }
+
+    /// The compression algorithm of the blocks, as parsed from the dataset header
+    pub fn compression_algorithm(&self) -> CompressionAlgorithm {
+        self.compression_algorithm
+    }
+
+    /// Whether the cells of the blocks are stored column by column, as parsed from the dataset header
+    pub fn columnar(&self) -> bool {
+        self.columnar
+    }
+
+    /// Query a range of rows from the database
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// 
+    /// # Returns
+    /// 
+    /// A vector of rows
+    pub fn query_range(&mut self, position_value_start: u64, position_value_end: u64) -> std::io::Result<Vec<Row>> {
+        self.check_row_limit(position_value_start, position_value_end)?;
+
+        let blocks = self.get_blocks(position_value_start, position_value_end);
+
+        match blocks.first() {
+            Some(block) => self.seek_to_block(*block)?,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut rows = Vec::new();
+
+        if self.read_ahead && self.block_cache.is_none() && blocks.len() > 1 {
+            self.read_blocks_ahead(&blocks, position_value_start, position_value_end, &mut rows)?;
+        } else {
+            let mut compressed: Vec<u8> = Vec::new();
+            let mut decompressed: Vec<u8> = Vec::new();
+
+            for block in blocks {
+                self.read_block(block, position_value_start, position_value_end, &mut compressed, &mut decompressed, &mut rows)?;
+            }
+        }
+
+        if self.strict_bounds {
+            self.check_bounds(&rows, position_value_start, position_value_end)?;
+        }
+
+        Ok(rows)
+    }
+
+    /// Query a range of rows, but only read the cells of some columns. The cells of the other columns are skipped
+    /// without being deserialized.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// * `column_indices` - The indices of the columns to read. The position column is always read to find the rows in
+    ///   the range, but it is only returned if it is one of the columns.
+    /// 
+    /// # Returns
+    /// 
+    /// A vector of rows, containing the cells of the columns in the order of `column_indices`
+    pub fn query_range_projected(&mut self, position_value_start: u64, position_value_end: u64, column_indices: Vec<usize>) -> std::io::Result<Vec<Row>> {
+        if let Some(column) = column_indices.iter().find(|&&column| column >= self.columns.len()) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Column index {} is out of bounds for {} columns", column, self.columns.len(),
+            )));
+        }
+
+        // Every cell is moved into the projected row, so it can't be in there twice
+        if let Some((i, column)) = column_indices.iter().enumerate().find(|(i, column)| column_indices[..*i].contains(column)) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Column index {} is projected more than once (at {})", column, i,
+            )));
+        }
+
+        self.check_row_limit(position_value_start, position_value_end)?;
+
+        let blocks = self.get_blocks(position_value_start, position_value_end);
+
+        match blocks.first() {
+            Some(block) => self.seek_to_block(*block)?,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut compressed: Vec<u8> = Vec::new();
+        let mut decompressed: Vec<u8> = Vec::new();
+
+        let mut rows = Vec::new();
+        for block in blocks {
+            let bytes = self.read_block_bytes(block, &mut compressed, &mut decompressed)?;
+            self.deserialize_range_with(bytes, max(block.0.0, position_value_start), position_value_end, Some(&column_indices), |cells| {
+                rows.push(std::mem::replace(cells, Vec::with_capacity(column_indices.len())));
+            })?;
+        }
+
+        Ok(rows)
+    }
+
+    /// Query a range of rows of a dataset with float positions. The bounds are compared to the positions at the precision
+    /// of the dataset, and the positions of the returned rows are converted back to floats.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// 
+    /// # Returns
+    /// 
+    /// A vector of rows
+    pub fn query_float_range(&mut self, position_value_start: f64, position_value_end: f64) -> std::io::Result<Vec<Row>> {
+        let Some(position_decimals) = self.position_decimals else {
+            return Err(Error::new(ErrorKind::InvalidInput, "The positions of the dataset are integers, use query_range instead"));
+        };
+
+        let position_value_start = self.scale_position(position_value_start)?;
+        let position_value_end = self.scale_position(position_value_end)?;
+        let mut rows = self.query_range(position_value_start, position_value_end)?;
+
+        let scale = 10f64.powi(position_decimals as i32);
+        for row in rows.iter_mut() {
+            if let CellValue::Integer(position) = row[0] {
+                row[0] = CellValue::Float(position as f64 / scale);
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Scale a float position to the integer it is stored as. Integer positions are returned unchanged.
+    pub fn scale_position(&self, position: f64) -> std::io::Result<u64> {
+        let scale = 10f64.powi(self.position_decimals.unwrap_or(0) as i32);
+        let scaled = (position * scale).round();
+
+        if !scaled.is_finite() || scaled < 0.0 || scaled >= u64::MAX as f64 {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Position {} is out of range for {} decimals", position, self.position_decimals.unwrap_or(0),
+            )));
+        }
+
+        Ok(scaled as u64)
+    }
+
+    pub fn position_decimals(&self) -> Option<u8> {
+        self.position_decimals
+    }
+
+    /// Whether the positions of the dataset are 0-based or 1-based, if it is known
+    pub fn coordinate_base(&self) -> Option<u8> {
+        self.coordinate_base
+    }
+
+    /// Translate a position from the coordinate base of the caller to the coordinate base of the dataset, such as a
+    /// 0-based position to query a 1-based dataset. Fails if the coordinate base of the dataset is unknown.
+    pub fn translate_position(&self, position: u64, coordinate_base: u8) -> std::io::Result<u64> {
+        translate_position(self.coordinate_base, position, coordinate_base)
+    }
+
+    /// Query a range of rows grouped by the value of a column. The rows of each block are moved into their group as soon
+    /// as the block is read, so the rows of the entire range are never collected first.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// * `group_column` - The index of the integer or string column to group by
+    /// 
+    /// # Returns
+    /// 
+    /// The value of each group and its rows, in order of the first row of each group
+    pub fn query_range_grouped(
+        &mut self,
+        position_value_start: u64,
+        position_value_end: u64,
+        group_column: usize,
+    ) -> std::io::Result<Vec<(CellValue, Vec<Row>)>> {
+        self.check_group_column(group_column)?;
+
+        self.check_row_limit(position_value_start, position_value_end)?;
+
+        let blocks = self.get_blocks(position_value_start, position_value_end);
+
+        match blocks.first() {
+            Some(block) => self.seek_to_block(*block)?,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut compressed: Vec<u8> = Vec::new();
+        let mut decompressed: Vec<u8> = Vec::new();
+
+        let mut groups: Vec<(CellValue, Vec<Row>)> = Vec::new();
+        let mut group_indices: HashMap<GroupKey, usize> = HashMap::new();
+
+        let mut block_rows = Vec::new();
+        for block in blocks {
+            self.read_block(block, position_value_start, position_value_end, &mut compressed, &mut decompressed, &mut block_rows)?;
+
+            if self.strict_bounds {
+                self.check_bounds(&block_rows, position_value_start, position_value_end)?;
+            }
+
+            for row in block_rows.drain(..) {
+                let key = match &row[group_column] {
+                    CellValue::Integer(i) => GroupKey::Integer(*i),
+                    CellValue::String(s) => GroupKey::String(s.clone()),
+                    CellValue::Float(_) => unreachable!("Float columns can't be grouped by"),
+                };
+
+                let i_group = *group_indices.entry(key).or_insert_with(|| {
+                    groups.push((row[group_column].clone(), Vec::new()));
+                    groups.len() - 1
+                });
+                groups[i_group].1.push(row);
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Get the distinct values of a column in a range, in order of their first occurrence. The strings of the rows are
+    /// borrowed from the blocks, so only the distinct values are allocated.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `column` - The index of the integer or string column
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    pub fn distinct_values(&mut self, column: usize, position_value_start: u64, position_value_end: u64) -> std::io::Result<Vec<CellValue>> {
+        self.check_group_column(column)?;
+
+        let mut values = Vec::new();
+        let mut seen_integers: HashSet<i64> = HashSet::new();
+        let mut seen_strings: HashSet<Arc<str>> = HashSet::new();
+
+        self.query_range_with(position_value_start, position_value_end, |cells| {
+            match cells[column] {
+                CellRef::Integer(i) => {
+                    if seen_integers.insert(i) {
+                        values.push(CellValue::Integer(i));
+                    }
+                },
+                CellRef::String(s) => {
+                    if !seen_strings.contains(s) {
+                        let s: Arc<str> = s.into();
+                        seen_strings.insert(s.clone());
+                        values.push(CellValue::String(s));
+                    }
+                },
+                CellRef::Float(_) => unreachable!("Float columns don't have distinct values"),
+            }
+        })?;
+
+        Ok(values)
+    }
+
+    /// Check that a column exists and can be grouped by, which excludes float columns
+    fn check_group_column(&self, column: usize) -> std::io::Result<()> {
+        match self.columns.get(column) {
+            Some(header) if header.type_ != ColumnType::Float => Ok(()),
+            Some(header) => Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Can't group by or take the distinct values of float column '{}'", header.name,
+            ))),
+            None => Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Column index {} is out of bounds for {} columns", column, self.columns.len(),
+            ))),
+        }
+    }
+
+    /// Query a range of rows that can be unbounded on either side. An unbounded start or end includes all rows from
+    /// the start or up to the end of the table, so a range unbounded on both sides reads the entire table.
+    pub fn query_open_range(&mut self, position_value_start: Option<u64>, position_value_end: Option<u64>) -> std::io::Result<Vec<Row>> {
+        let (position_value_start, position_value_end) = self.resolve_bounds(position_value_start, position_value_end);
+        self.query_range(position_value_start, position_value_end)
+    }
+
+    /// Resolve the bounds of a range that can be unbounded on either side to the bounds of the table
+    pub fn resolve_bounds(&self, position_value_start: Option<u64>, position_value_end: Option<u64>) -> (u64, u64) {
+        (
+            position_value_start.unwrap_or_else(|| self.index.min_position()),
+            // The end is exclusive, so the maximum position has to be included explicitly
+            position_value_end.unwrap_or_else(|| self.index.max_position.saturating_add(1)),
+        )
+    }
+
+    /// Check that the position of every row is within `[position_value_start, position_value_end)`
+    pub fn check_bounds(&self, rows: &[Row], position_value_start: u64, position_value_end: u64) -> std::io::Result<()> {
+        for row in rows {
+            let position = match row.first() {
+                Some(CellValue::Integer(i)) => u64::try_from(*i).ok(),
+                _ => None,
+            };
+
+            match position {
+                Some(position) if position >= position_value_start && position < position_value_end => {},
+                _ => return Err(Error::new(ErrorKind::InvalidData, format!(
+                    "Row with position {:?} of chromosome {:?} is outside the queried range [{}, {})",
+                    row.first(), self.index.chromosome, position_value_start, position_value_end,
+                ))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the index is consistent with the blocks it points to, by reading every block and comparing the position
+    /// of its first row to the position of its entry in the index. Fails at the first entry that doesn't match.
+    pub fn validate_index(&mut self) -> std::io::Result<()> {
+        let entries = self.index.get_all();
+
+        let mut compressed: Vec<u8> = Vec::new();
+        let mut decompressed: Vec<u8> = Vec::new();
+
+        let chromosome = self.index.chromosome;
+        for (i, &(position, offset)) in entries.iter().enumerate() {
+            // The last block of the table ends at the start of the index
+            let end = entries.get(i + 1).copied().unwrap_or((u64::MAX, self.index.index_start_offset));
+            let block = ((position, offset), end);
+            let invalid_entry = |reason: String| Error::new(ErrorKind::InvalidData, format!(
+                "Index entry {} of chromosome {} with position {} at offset {} is invalid: {}",
+                i, chromosome, position, offset, reason,
+            ));
+
+            self.seek_to_block(block)?;
+            let bytes = self.read_block_bytes(block, &mut compressed, &mut decompressed)
+                .map_err(|e| invalid_entry(format!("its block can't be read ({})", e)))?;
+
+            // The positions of a columnar block are the first column, after its length
+            let positions = match self.columnar {
+                true => split_columns(bytes, self.columns.len()).map(|columns| columns[0].1),
+                false => Ok(bytes),
+            };
+
+            let (first_position, _) = positions.and_then(|positions| deserialize::read_zigzag_i64(&mut Cursor::new(positions)))
+                .map_err(|e| invalid_entry(format!("the position of the first row of its block can't be read ({})", e)))?;
+
+            if first_position != position as i64 {
+                return Err(invalid_entry(format!("the first row of its block has position {}", first_position)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the checksum of the compressed bytes of every block of the table against its index entry, without
+    /// decompressing the blocks. Returns the number of blocks that were checked, or an error for the first block of
+    /// which the checksum doesn't match.
+    pub fn verify_checksums(&mut self) -> std::io::Result<usize> {
+        let entries = self.index.get_all();
+        let mut compressed: Vec<u8> = Vec::new();
+
+        for (i, &(position, offset)) in entries.iter().enumerate() {
+            // The last block of the table ends at the start of the index
+            let end = entries.get(i + 1).copied().unwrap_or((u64::MAX, self.index.index_start_offset));
+            let block = ((position, offset), end);
+
+            self.seek_to_block(block)?;
+            self.read_compressed_block(block, &mut compressed)?;
+
+            let expected = *self.index.block_checksums.get(&offset).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!(
+                "Missing checksum of the block of chromosome {} at offset {}", self.index.chromosome, offset,
+            )))?;
+            let checksum = block_checksum(&compressed);
+
+            if checksum != expected {
+                return Err(Error::new(ErrorKind::InvalidData, format!(
+                    "Block of chromosome {} at offset {} has checksum {:#010x}, but its index entry has {:#010x}",
+                    self.index.chromosome, offset, checksum, expected,
+                )));
+            }
+        }
+
+        Ok(entries.len())
+    }
+
+    /// Read the row at an ordinal in the order of the table, such as 0 for its first row. The row counts of the blocks in
+    /// the index are used to find the block containing it, so only that block is read.
+    /// 
+    /// # Returns
+    /// 
+    /// The row, or `None` if the table has no more than `ordinal` rows
+    pub fn row_at(&mut self, ordinal: u64) -> std::io::Result<Option<Row>> {
+        let entries = self.index.get_all();
+
+        let mut rows_before = 0;
+        for (i, &(position, offset)) in entries.iter().enumerate() {
+            let num_rows = self.index.block_row_count(offset)?;
+
+            if ordinal >= rows_before + num_rows {
+                rows_before += num_rows;
+                continue;
+            }
+
+            // The last block of the table ends at the start of the index
+            let end = entries.get(i + 1).copied().unwrap_or((u64::MAX, self.index.index_start_offset));
+            let block = ((position, offset), end);
+
+            let mut rows = Vec::new();
+            self.seek_to_block(block)?;
+            self.read_block(block, 0, u64::MAX, &mut Vec::new(), &mut Vec::new(), &mut rows)?;
+
+            let row_in_block = ordinal - rows_before;
+            return match rows.into_iter().nth(row_in_block as usize) {
+                Some(row) => Ok(Some(row)),
+                None => Err(Error::new(ErrorKind::InvalidData, format!(
+                    "The block of chromosome {} at offset {} has fewer rows than the {} in the index",
+                    self.index.chromosome, offset, num_rows,
+                ))),
+            };
+        }
+
+        Ok(None)
+    }
+
+    /// Get the blocks overlapping a range
+    pub fn get_blocks(&self, position_value_start: u64, position_value_end: u64) -> Vec<BlockBounds> {
+        let mut range: Vec<(u64, u64)> = self.index.get_range(position_value_start, position_value_end);
+        if range.is_empty() {
+            return Vec::new();
+        }
+
+        // The last block ends where the block after it starts, or at the start of the index if it is the last block of the
+        // table. The bounds only depend on the index, so a row at the end of the range is excluded by the deserializer
+        // comparing it to the range, not by the bounds of its block.
+        let (last_position, _) = range[range.len() - 1];
+        let end = self.index.inner
+            .range((Bound::Excluded(last_position), Bound::Unbounded))
+            .next()
+            .map_or((u64::MAX, self.index.index_start_offset), |(position, offset)| (*position, *offset));
+        range.push(end);
+
+        range.windows(2).map(|window| {
+            let [start, end] = window else { unreachable!() };
+            (*start, *end)
+        }).collect()
+    }
+
+    /// Get the bounds of the blocks starting at the given offsets, in the order of the table. Every offset must be the
+    /// offset of a block in the index.
+    pub fn get_blocks_at(&self, offsets: &[u64]) -> std::io::Result<Vec<BlockBounds>> {
+        let mut offsets = offsets.to_vec();
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        let mut blocks = Vec::with_capacity(offsets.len());
+        let mut entries = self.index.inner.iter().peekable();
+
+        while let Some((position, offset)) = entries.next() {
+            if offsets.binary_search(offset).is_err() {
+                continue;
+            }
+
+            // The last block of the table ends at the start of the index, and contains all rows after its position
+            let end = entries.peek().map_or((u64::MAX, self.index.index_start_offset), |(position, offset)| (**position, **offset));
+            blocks.push(((*position, *offset), end));
+        }
+
+        if blocks.len() != offsets.len() {
+            let missing = offsets.iter()
+                .find(|offset| !blocks.iter().any(|((_, block_offset), _)| block_offset == *offset))
+                .unwrap();
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "Offset {} is not the start of a block of chromosome {}", missing, self.index.chromosome,
+            )));
+        }
+
+        Ok(blocks)
+    }
+
+    /// Read all rows of the blocks starting at the given offsets, without reading the blocks in between. Duplicate
+    /// offsets are read once, and the rows are returned in the order of the table.
+    pub fn query_blocks(&mut self, offsets: &[u64]) -> std::io::Result<Vec<Row>> {
+        let blocks = self.get_blocks_at(offsets)?;
+        self.check_blocks_row_limit(&blocks)?;
+        self.read_blocks(&blocks)
+    }
+
+    /// Query the rows of an interval table of which the end position is within `[position_value_start, position_value_end)`,
+    /// using the end index of the table. The rows are returned in the order of the table, which is by their start.
+    pub fn query_end_range(&mut self, position_value_start: u64, position_value_end: u64) -> std::io::Result<Vec<Row>> {
+        let end_index = self.index.end_index.as_ref().ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!(
+            "Table of chromosome {} has no end index", self.index.chromosome,
+        )))?;
+
+        // The blocks are sorted by their smallest end, so only a prefix of them can contain ends before the end of the range
+        let candidates = &end_index[..end_index.partition_point(|(min_end, _, _)| *min_end < position_value_end)];
+        let offsets: Vec<u64> = candidates.iter()
+            .filter(|(_, max_end, _)| *max_end >= position_value_start)
+            .map(|(_, _, offset)| *offset)
+            .collect();
+
+        let rows = self.query_blocks(&offsets)?;
+
+        Ok(rows.into_iter()
+            .filter(|row| match row.get(1) {
+                Some(CellValue::Integer(end)) => (position_value_start..position_value_end).contains(&(*end as u64)),
+                _ => false,
+            })
+            .collect())
+    }
+
+    /// Query the rows of an interval table of which the interval `[start, end]` overlaps
+    /// `[position_value_start, position_value_end)`, using the end index of the table to skip the blocks of which no
+    /// interval reaches the range. The rows are returned in the order of the table, which is by their start.
+    pub fn query_overlapping(&mut self, position_value_start: u64, position_value_end: u64) -> std::io::Result<Vec<Row>> {
+        let end_index = self.index.end_index.as_ref().ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!(
+            "Table of chromosome {} has no end index", self.index.chromosome,
+        )))?;
+
+        // The rows are sorted by their start, so blocks starting at or after the end of the range can't overlap it
+        let starting_before_end: HashSet<u64> = self.index.inner.range(..position_value_end)
+            .map(|(_, offset)| *offset)
+            .collect();
+        let offsets: Vec<u64> = end_index.iter()
+            .filter(|(_, max_end, offset)| *max_end >= position_value_start && starting_before_end.contains(offset))
+            .map(|(_, _, offset)| *offset)
+            .collect();
+
+        let rows = self.query_blocks(&offsets)?;
+
+        Ok(rows.into_iter()
+            .filter(|row| match (row.first(), row.get(1)) {
+                (Some(CellValue::Integer(start)), Some(CellValue::Integer(end))) =>
+                    (*start as u64) < position_value_end && (*end as u64) >= position_value_start,
+                _ => false,
+            })
+            .collect())
+    }
+
+    /// Query the rows within `window` of each of many anchor positions, which is `[anchor - window, anchor + window)`.
+    /// Overlapping windows are coalesced first, so blocks that are shared by the windows of several anchors are only
+    /// read once.
+    /// 
+    /// # Returns
+    /// 
+    /// The rows of the window of each anchor, in the order of the anchors
+    pub fn query_windows(&mut self, anchors: &[u64], window: u64) -> std::io::Result<Vec<Vec<Row>>> {
+        let windows: Vec<(u64, u64)> = anchors.iter()
+            .map(|&anchor| (anchor.saturating_sub(window), anchor.saturating_add(window)))
+            .collect();
+
+        let mut merged_windows: Vec<(u64, u64)> = windows.clone();
+        merged_windows.sort_unstable();
+        merged_windows.dedup_by(|(start, end), (_, previous_end)| {
+            let overlaps = *start <= *previous_end;
+            if overlaps {
+                *previous_end = max(*previous_end, *end);
+            }
+            overlaps
+        });
+
+        // A block can be in two merged windows if they are close together, in which case it is only read once
+        let mut blocks: Vec<BlockBounds> = Vec::new();
+        for &(start, end) in &merged_windows {
+            for block in self.get_blocks(start, end) {
+                if blocks.last() != Some(&block) {
+                    blocks.push(block);
+                }
+            }
+        }
+
+        self.check_blocks_row_limit(&blocks)?;
+        let rows = self.read_blocks(&blocks)?;
+
+        let position = |row: &Row| match row[0] {
+            CellValue::Integer(position) => position as u64,
+            _ => 0,
+        };
+
+        Ok(windows.into_iter().map(|(start, end)| {
+            let first = rows.partition_point(|row| position(row) < start);
+            let last = rows.partition_point(|row| position(row) < end);
+            rows[first..last].to_vec()
+        }).collect())
+    }
+
+    /// Query a range of rows, together with up to `context` rows directly before `position_value_start` and up to
+    /// `context` rows at or after `position_value_end`. The blocks around the range are read until they contain enough
+    /// rows, so the context can come from other blocks than the range.
+    pub fn query_range_with_context(
+        &mut self,
+        position_value_start: u64,
+        position_value_end: u64,
+        context: usize,
+    ) -> std::io::Result<RowsWithContext> {
+        if position_value_start > position_value_end {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "The start {} of the range is after its end {}", position_value_start, position_value_end,
+            )));
+        }
+
+        let entries = self.index.get_all();
+        let block_rows = |i: usize| self.index.block_row_count(entries[i].1);
+
+        // The blocks of the range are the block containing the start up to the last block starting before the end
+        let mut first = entries.partition_point(|(position, _)| *position <= position_value_start).saturating_sub(1);
+        let mut last = entries.partition_point(|(position, _)| *position < position_value_end).max(first);
+
+        // Only the blocks outside the range are counted, as the rows of the blocks at its bounds can be on either side
+        let mut rows_before = 0;
+        while first > 0 && rows_before < context as u64 {
+            first -= 1;
+            rows_before += block_rows(first)?;
+        }
+        let mut rows_after = 0;
+        while last < entries.len() && rows_after < context as u64 {
+            rows_after += block_rows(last)?;
+            last += 1;
+        }
+
+        // The last block of the table ends at the start of the index
+        let blocks: Vec<BlockBounds> = (first..last)
+            .map(|i| (entries[i], entries.get(i + 1).copied().unwrap_or((u64::MAX, self.index.index_start_offset))))
+            .collect();
+
+        self.check_blocks_row_limit(&blocks)?;
+        let mut rows = self.read_blocks(&blocks)?;
+
+        let position = |row: &Row| match row[0] {
+            CellValue::Integer(position) => position as u64,
+            _ => 0,
+        };
+
+        let after = rows.split_off(rows.partition_point(|row| position(row) < position_value_end));
+        let within = rows.split_off(rows.partition_point(|row| position(row) < position_value_start));
+        let before = rows.split_off(rows.len().saturating_sub(context));
+
+        Ok(RowsWithContext {
+            before,
+            rows: within,
+            after: after.into_iter().take(context).collect(),
+        })
+    }
+
+    /// Check that blocks don't contain more rows than the limit in total
+    fn check_blocks_row_limit(&self, blocks: &[BlockBounds]) -> std::io::Result<()> {
+        let Some(max_rows) = self.max_rows else {
+            return Ok(());
+        };
+
+        let num_rows = blocks.iter()
+            .map(|((_, offset), _)| self.index.block_row_count(*offset))
+            .sum::<std::io::Result<u64>>()?;
+
+        if num_rows > max_rows {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "{} blocks of chromosome {} contain {} rows, which exceeds the limit of {} rows",
+                blocks.len(), self.index.chromosome, num_rows, max_rows,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Read the rows of blocks in the order of the table, only seeking across the gaps between them
+    fn read_blocks(&mut self, blocks: &[BlockBounds]) -> std::io::Result<Vec<Row>> {
+        let mut compressed: Vec<u8> = Vec::new();
+        let mut decompressed: Vec<u8> = Vec::new();
+
+        let mut rows = Vec::new();
+        let mut previous_end_offset = None;
+        for &block in blocks {
+            // Only seek if there is a gap between this block and the previous one
+            let ((_, offset_start), (_, offset_end)) = block;
+            if previous_end_offset != Some(offset_start) {
+                self.seek_to_block(block)?;
+            }
+            previous_end_offset = Some(offset_end);
+
+            // Rows at the position of the next block can also be at the end of this block, so every row is read
+            let slice = self.read_block_bytes(block, &mut compressed, &mut decompressed)?;
+            self.deserialize_range(slice, 0, u64::MAX, &mut rows)?;
+        }
+
+        Ok(rows)
+    }
+
+    /// Move the reader to the start of a block. Consecutive blocks can be read without seeking in between.
+    pub fn seek_to_block(&mut self, block: BlockBounds) -> std::io::Result<()> {
+        let ((_position, offset), _) = block;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+
+    /// Read a block at the current position of the reader and deserialize its rows that are within the range.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `block` - The bounds of the block
+    /// * `position_value_start` - Skip rows until the position value is greater than or equal to this value
+    /// * `position_value_end` - Stop if the position value is greater than or equal to this value
+    /// * `compressed` - A buffer to read the compressed block into
+    /// * `decompressed` - A buffer to decompress the block into
+    /// * `out_rows` - The vector to append the rows to
+    pub fn read_block(
+        &mut self,
+        block: BlockBounds,
+        position_value_start: u64,
+        position_value_end: u64,
+        compressed: &mut Vec<u8>,
+        decompressed: &mut Vec<u8>,
+        out_rows: &mut Vec<Row>,
+    ) -> std::io::Result<()> {
+        let slice = self.read_block_bytes(block, compressed, decompressed)?;
+
+        self.deserialize_range(
+            slice,
+            max(block.0.0, position_value_start),
+            position_value_end,
+            out_rows,
+        )
+    }
+
+    /// Read consecutive blocks starting at the current position of the reader, while a background thread decompresses
+    /// the next block. The blocks are read and deserialized on the calling thread.
+    fn read_blocks_ahead(
+        &mut self,
+        blocks: &[BlockBounds],
+        position_value_start: u64,
+        position_value_end: u64,
+        out_rows: &mut Vec<Row>,
+    ) -> std::io::Result<()> {
+        let decompressor = self.block_decompressor();
+
+        thread::scope(|scope| {
+            let (compressed_sender, compressed_receiver) = mpsc::sync_channel::<(BlockBounds, Vec<u8>)>(READ_AHEAD_BLOCKS);
+            let (decompressed_sender, decompressed_receiver) = mpsc::sync_channel(READ_AHEAD_BLOCKS);
+
+            scope.spawn(move || {
+                let mut buffer: Vec<u8> = Vec::new();
+
+                for (block, compressed) in compressed_receiver {
+                    let decompressed = decompressor.decompress(&compressed, &mut buffer)
+                        .map(|bytes| (block, bytes.to_vec()))
+                        .map_err(|e| Error::new(e.kind(), format!(
+                            "Failed to decompress the block at offset {}: {}",
+                            block.0.1, e,
+                        )));
+
+                    // The receiver is dropped if the query failed
+                    if decompressed_sender.send(decompressed).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let stopped = || Error::other("The read-ahead thread stopped unexpectedly");
+
+            let deserialize_next = |reader: &Self, out_rows: &mut Vec<Row>| -> std::io::Result<()> {
+                let (block, bytes): (BlockBounds, Vec<u8>) = decompressed_receiver.recv().map_err(|_| stopped())??;
+                reader.deserialize_range(&bytes, max(block.0.0, position_value_start), position_value_end, out_rows)
+            };
+
+            let mut pending = 0;
+            for &block in blocks {
+                let mut compressed: Vec<u8> = Vec::new();
+                self.read_compressed_block(block, &mut compressed)?;
+                compressed_sender.send((block, compressed)).map_err(|_| stopped())?;
+                pending += 1;
+
+                if pending > READ_AHEAD_BLOCKS {
+                    deserialize_next(self, out_rows)?;
+                    pending -= 1;
+                }
+            }
+
+            drop(compressed_sender);
+
+            for _ in 0..pending {
+                deserialize_next(self, out_rows)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Read a block at the current position of the reader and decompress it
+    fn read_block_bytes<'a>(
+        &mut self,
+        block: BlockBounds,
+        compressed: &'a mut Vec<u8>,
+        decompressed: &'a mut Vec<u8>,
+    ) -> std::io::Result<&'a [u8]> {
+        let Some(block_cache) = self.block_cache.clone() else {
+            return self.read_and_decompress_block(block, compressed, decompressed);
+        };
+
+        let ((_, offset_start), (_, offset_end)) = block;
+        let compressed_size = self.index.compressed_block_sizes.get(&offset_start).copied().unwrap_or(0);
+
+        let cached = block_cache.lock().map_err(|_| Error::other("The block cache is poisoned"))?.get(offset_start, compressed_size);
+        if let Some(bytes) = cached {
+            // Move past the block, as if it was read, so the next block can be read without seeking to it
+            self.reader.seek(SeekFrom::Start(offset_end))?;
+            decompressed.clear();
+            decompressed.extend_from_slice(&bytes);
+            return Ok(decompressed);
+        }
+
+        let bytes = self.read_and_decompress_block(block, compressed, decompressed)?;
+        block_cache.lock().map_err(|_| Error::other("The block cache is poisoned"))?
+            .insert(offset_start, compressed_size, Arc::from(bytes));
+
+        Ok(bytes)
+    }
+
+    /// Read a block at the current position of the reader and decompress it. The blocks of a mapped file are decompressed
+    /// straight from the mapping, so `compressed` is only used for files that aren't mapped.
+    fn read_and_decompress_block<'a>(
+        &mut self,
+        block: BlockBounds,
+        compressed: &'a mut Vec<u8>,
+        decompressed: &'a mut Vec<u8>,
+    ) -> std::io::Result<&'a [u8]> {
+        let Some(mapped_file) = self.mapped_file.clone() else {
+            self.read_compressed_block(block, compressed)?;
+            return self.decompress_block(block, compressed, decompressed);
+        };
+
+        let ((_, offset_start), (_, offset_end)) = block;
+        let (block_size, _) = self.compressed_block_size(block)?;
+        let range = offset_start as usize..(offset_start + block_size) as usize;
+        let bytes = mapped_file.as_ref().get(range).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, format!(
+            "The block of chromosome {} at offset {} ends after the end of the file", self.index.chromosome, offset_start,
+        )))?;
+
+        // Move past the block, as if it was read, so the next block can be read without seeking to it
+        self.reader.seek(SeekFrom::Start(offset_end))?;
+
+        // Uncompressed blocks are returned as they are by the decompressor, so they are copied to outlive the mapping
+        if self.compression_algorithm == CompressionAlgorithm::None {
+            decompressed.clear();
+            decompressed.extend_from_slice(bytes);
+        } else {
+            self.decompress_block(block, bytes, decompressed)?;
+        }
+
+        Ok(decompressed)
+    }
+
+    /// Decompress the compressed bytes of a block
+    fn decompress_block<'a>(&self, block: BlockBounds, compressed: &'a [u8], decompressed: &'a mut Vec<u8>) -> std::io::Result<&'a [u8]> {
+        let decompressor = self.block_decompressor();
+        match decompressor.decompress(compressed, decompressed) {
+            Ok(res) => Ok(res),
+            Err(e) => {
+                eprintln!("Decompression failed: {:?}", e);
+                eprint!("{}", rhexdumps!(compressed, block.0.1));
+                Err(e)
+            },
+        }
+    }
+
+    /// Read the compressed bytes of a block at the current position of the reader, skipping the padding after it
+    fn read_compressed_block(&mut self, block: BlockBounds, compressed: &mut Vec<u8>) -> std::io::Result<()> {
+        let (block_size, block_distance) = self.compressed_block_size(block)?;
+
+        compressed.clear();
+        self.reader.by_ref().take(block_size).read_to_end(compressed)?;
+
+        // Skip the padding, so the next block can be read without seeking to it
+        let padding = block_distance - block_size;
+        if padding > 0 {
+            self.reader.seek(SeekFrom::Current(padding as i64))?;
+        }
+
+        Ok(())
+    }
+
+    /// The compressed size of a block and the distance to the next block, which is larger if the block is padded
+    fn compressed_block_size(&self, block: BlockBounds) -> std::io::Result<(u64, u64)> {
+        let (start, end) = block;
+
+        let block_distance = end.1.checked_sub(start.1).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!(
+            "Invalid block of chromosome {:?}: offset {} of the next block is before its offset {}",
+            self.index.chromosome, end.1, start.1,
+        )))?;
+
+        let block_size = match self.index.compressed_block_sizes.get(&start.1) {
+            Some(&block_size) if block_size <= block_distance => block_size,
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!(
+                "Invalid block of chromosome {:?} at offset {}: missing or invalid compressed size",
+                self.index.chromosome, start.1,
+            ))),
+        };
+
+        Ok((block_size, block_distance))
+    }
+
+    /// Visit the rows of a range without allocating a row or string for every row. The cells borrow their strings from
+    /// the decompressed block, so they are only valid during the call to `visit`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// * `visit` - Called with the cells of every row in the range
+    pub fn query_range_with<F>(&mut self, position_value_start: u64, position_value_end: u64, mut visit: F) -> std::io::Result<()>
+    where
+        F: FnMut(&[CellRef<'_>]),
+    {
+        self.check_row_limit(position_value_start, position_value_end)?;
+
+        let blocks = self.get_blocks(position_value_start, position_value_end);
+
+        match blocks.first() {
+            Some(block) => self.seek_to_block(*block)?,
+            None => return Ok(()),
+        };
+
+        let mut compressed: Vec<u8> = Vec::new();
+        let mut decompressed: Vec<u8> = Vec::new();
+
+        for block in blocks {
+            let bytes = self.read_block_bytes(block, &mut compressed, &mut decompressed)?;
+            self.visit_block(bytes, max(block.0.0, position_value_start), position_value_end, &mut visit)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fold the rows of a range into a single value without collecting them. The cells passed to `f` are decoded into a
+    /// buffer that is reused for every row.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `position_value_start` - The start of the range (inclusive)
+    /// * `position_value_end` - The end of the range (exclusive)
+    /// * `init` - The initial value of the accumulator
+    /// * `f` - Called with the accumulator and the cells of every row in the range, returning the new accumulator
+    pub fn fold_range<B, F>(&mut self, position_value_start: u64, position_value_end: u64, init: B, mut f: F) -> std::io::Result<B>
+    where
+        F: FnMut(B, &[CellValue]) -> B,
+    {
+        self.check_row_limit(position_value_start, position_value_end)?;
+
+        let blocks = self.get_blocks(position_value_start, position_value_end);
+
+        match blocks.first() {
+            Some(block) => self.seek_to_block(*block)?,
+            None => return Ok(init),
+        };
+
+        let mut compressed: Vec<u8> = Vec::new();
+        let mut decompressed: Vec<u8> = Vec::new();
+
+        // The accumulator is moved into `f` and back for every row
+        let mut acc = Some(init);
+        for block in blocks {
+            let bytes = self.read_block_bytes(block, &mut compressed, &mut decompressed)?;
+            self.deserialize_range_with(bytes, max(block.0.0, position_value_start), position_value_end, None, |cells| {
+                acc = acc.take().map(|acc| f(acc, cells));
+            })?;
+        }
+
+        Ok(acc.expect("accumulator is always put back"))
+    }
+
+    fn visit_block<'a, F>(&self, bytes: &'a [u8], position_value_start: u64, position_value_end: u64, visit: &mut F) -> std::io::Result<()>
+    where
+        F: FnMut(&[CellRef<'_>]),
+    {
+        if self.columnar {
+            return self.visit_columnar_block(bytes, position_value_start, position_value_end, visit);
+        }
+
+        let mut cursor: Cursor<&'a [u8]> = Cursor::new(bytes);
+
+        // Interned strings are only valid within the block they were written in
+        let mut string_pools: Vec<Vec<&'a str>> = vec![Vec::new(); self.columns.len()];
+        let mut cells: Vec<CellRef<'a>> = Vec::with_capacity(self.columns.len());
+
+        while cursor.position() < bytes.len() as u64 {
+            cells.clear();
+
+            for (i, column) in self.columns.iter().enumerate() {
+                let cell = match column.type_ {
+                    ColumnType::Integer => CellRef::Integer(deserialize::read_zigzag_i64(&mut cursor)?.0),
+                    ColumnType::Float => CellRef::Float(deserialize::read_f64(&mut cursor)?),
+                    // Hashtable strings are written like volatile strings
+                    ColumnType::VolatileString | ColumnType::HashtableString => CellRef::String(deserialize::read_str_varlen(&mut cursor)?),
+                    ColumnType::InternedString => CellRef::String(deserialize::read_interned_str(&mut cursor, &mut string_pools[i])?),
+                    ColumnType::Char => CellRef::String(deserialize::read_char_str(&mut cursor)?),
+                    ColumnType::Boolean => CellRef::Integer(deserialize::read_bool(&mut cursor)? as i64),
+                };
+                cells.push(cell);
+            }
+
+            let position = match cells[0] {
+                CellRef::Integer(i) => u64::try_from(i).map_err(|_| Error::new(ErrorKind::InvalidData, format!(
+                    "Invalid negative position {} in block of chromosome {:?}", i, self.index.chromosome,
+                )))?,
+                _ => panic!("First column must be an integer"),
+            };
+
+            if position >= position_value_end {
+                break;
+            } else if position >= position_value_start {
+                visit(&cells);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deserialize a range of bytes from the reader using raw offsets. Unless you know what you're doing, use `query_range` instead.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `bytes` - The bytes to deserialize
+    /// * `position_value_start` - Skip rows until the position value is greater than or equal to this value
+    /// * `position_value_end` - Stop if the position value is greater than or equal to this value
+    /// * `out_rows` - The vector to append the rows to
+    pub fn deserialize_range(
+        &self,
+        bytes: &[u8],
+        position_value_start: u64,
+        position_value_end: u64,
+        out_rows: &mut Vec<Row>,
+    ) -> std::io::Result<()> {
+        let num_columns = self.columns.len();
+        let has_coercions = self.column_coercions.iter().any(Option::is_some);
+
+        self.deserialize_range_with(bytes, position_value_start, position_value_end, None, |cells| {
+            if has_coercions {
+                for (cell, coercion) in cells.iter_mut().zip(&self.column_coercions) {
+                    if let Some(coercion) = coercion {
+                        coercion.apply(cell);
+                    }
+                }
+            }
+
+            out_rows.push(std::mem::replace(cells, Vec::with_capacity(num_columns)));
+        })
+    }
+
+    /// Deserialize a range of bytes like `deserialize_range`, but pass every row to `on_row` instead of collecting them.
+    /// The cells of a row are reused for the next row, unless `on_row` takes them.
+    /// 
+    /// If `projection` is given, only the cells of those columns are read, in that order. The cells are still decoded in
+    /// the order they are stored, but each is placed straight into its column of the projected row. The other columns
+    /// are skipped, except for the position column, which is always read to compare it to the range.
+    fn deserialize_range_with<F>(
+        &self,
+        bytes: &[u8],
+        position_value_start: u64,
+        position_value_end: u64,
+        projection: Option<&[usize]>,
+        mut on_row: F,
+    ) -> std::io::Result<()>
+    where
+        F: FnMut(&mut Vec<CellValue>),
+    {
+        if self.columnar {
+            return self.deserialize_columnar_with(bytes, position_value_start, position_value_end, projection, on_row);
+        }
+
+        let offset_start: u64 = 0;
+        let offset_end = bytes.len() as u64;
+
+        let mut cursor: Cursor<&[u8]> = Cursor::new(bytes);
+
+        let skip_lambdas: Vec<_> = self.columns.iter()
+            .skip(1) // Skip the first position column, as we always want to read it
+            .map(|column| {
+                match column.type_ {
+                    ColumnType::Integer => {
+                        |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                            deserialize::skip_zigzag_i64(cursor)
+                        }
+                    },
+                    ColumnType::Float => {
+                        |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                            deserialize::skip_f64(cursor)
+                        }
+                    },
+                    // Hashtable strings are serialized like volatile strings
+                    ColumnType::VolatileString | ColumnType::HashtableString => {
+                        |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                            deserialize::skip_string_varlen(cursor)
+                        }
+                    },
+                    ColumnType::InternedString => {
+                        |cursor: &mut Cursor<&[u8]>, pool: &mut Vec<Arc<str>>| {
+                            deserialize::skip_interned_string(cursor, pool)
+                        }
+                    },
+                    ColumnType::Char => {
+                        |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                            deserialize::skip_char(cursor)
+                        }
+                    },
+                    ColumnType::Boolean => {
+                        |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                            deserialize::skip_bool(cursor)
+                        }
+                    },
+                }
+            }).collect();
+
+        let read_lambdas: Vec<_> = self.columns.iter().map(|column| {
+            match column.type_ {
+                ColumnType::Integer => {
+                    |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                        let (value, len) = deserialize::read_zigzag_i64(cursor)?;
+                        Ok((CellValue::Integer(value), len))
+                    }
+                },
+                ColumnType::Float => {
+                    |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| Ok((CellValue::Float(deserialize::read_f64(cursor)?), 8))
+                },
+                // Hashtable strings are serialized like volatile strings
+                ColumnType::VolatileString | ColumnType::HashtableString => {
+                    |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| {
+                        let start = cursor.position();
+                        let string = match deserialize::read_shared_string_varlen(cursor) {
+                            Ok(string) => string,
+                            Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!(
+                                "Reading string failed: {:?}", e
+                            ))),
+                        };
+                        let bytes_read = (cursor.position() - start) as usize;
+                        Ok((CellValue::String(string), bytes_read))
+                    }
+                },
+                ColumnType::InternedString => {
+                    |cursor: &mut Cursor<&[u8]>, pool: &mut Vec<Arc<str>>| {
+                        let (string, bytes_read) = deserialize::read_interned_string(cursor, pool)?;
+                        Ok((CellValue::String(string), bytes_read))
+                    }
+                },
+                ColumnType::Char => {
+                    |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| Ok((CellValue::String(deserialize::read_char(cursor)?), 1))
+                },
+                ColumnType::Boolean => {
+                    |cursor: &mut Cursor<&[u8]>, _pool: &mut Vec<Arc<str>>| Ok((CellValue::Integer(deserialize::read_bool(cursor)? as i64), 1))
+                },
+            }
+        }).collect();
+
+        // Interned strings are only valid within the block they were written in
+        let mut string_pools: Vec<Vec<Arc<str>>> = vec![Vec::new(); self.columns.len()];
+
+        // The index in the returned row of the cell of every column, or `None` if the column isn't projected
+        let output_cells: Vec<Option<usize>> = (0..self.columns.len())
+            .map(|i| match projection {
+                Some(projection) => projection.iter().position(|&column| column == i),
+                None => Some(i),
+            })
+            .collect();
+        let num_cells = projection.map_or(self.columns.len(), <[usize]>::len);
+
+        let mut cells = Vec::with_capacity(num_cells);
+
+        // The position of the last row of which every cell was read, to tell which row follows the one that can't be read
+        let mut last_position: Option<u64> = None;
+        let read_error = |column: usize, row_offset: u64, last_position: Option<u64>, e: Error| Error::new(ErrorKind::InvalidData, format!(
+            "Failed to read column '{}' of the row at offset {} of {} bytes in block of chromosome {:?}, {}: {}",
+            self.columns[column].name, row_offset, offset_end, self.index.chromosome,
+            match last_position {
+                Some(position) => format!("which follows the row at position {}", position),
+                None => "which is the first row of the block".to_string(),
+            },
+            e,
+        ));
+
+        let mut offset_in_block = offset_start;
+        'row_loop: loop {
+            if offset_in_block >= offset_end {
+                break;
+            }
+
+            let row_offset = offset_in_block;
+            let mut row_position = 0;
+
+            // The cells are overwritten in the order they are read, which isn't the order of a projected row
+            cells.clear();
+            cells.resize(num_cells, CellValue::Integer(0));
+
+            for (i, lambda) in read_lambdas.iter().enumerate() {
+                if i != 0 && output_cells[i].is_none() {
+                    offset_in_block += skip_lambdas[i - 1](&mut cursor, &mut string_pools[i])
+                        .map_err(|e| read_error(i, row_offset, last_position, e))? as u64;
+                    continue;
+                }
+
+                let (value, bytes_read) = lambda(&mut cursor, &mut string_pools[i])
+                    .map_err(|e| read_error(i, row_offset, last_position, e))?;
+
+                offset_in_block += bytes_read as u64;
+
+                if i == 0 {
+                    match value {
+                        CellValue::Integer(i) => {
+                            // Positions are stored as signed integers, so a corrupt block could contain a negative one
+                            let position = u64::try_from(i).map_err(|_| Error::new(ErrorKind::InvalidData, format!(
+                                "Invalid negative position {} at offset {} in block of chromosome {:?}",
+                                i, offset_in_block, self.index.chromosome,
+                            )))?;
+
+                            if position >= position_value_end {
+                                break 'row_loop;
+                            } else if position < position_value_start {
+                                // Skip this row
+                                for (i_skip, lambda) in skip_lambdas.iter().enumerate() {
+                                    let bytes_skipped = lambda(&mut cursor, &mut string_pools[i_skip + 1])
+                                        .map_err(|e| read_error(i_skip + 1, row_offset, last_position, e))?;
+                                    offset_in_block += bytes_skipped as u64;
+                                }
+                                last_position = Some(position);
+                                continue 'row_loop;
+                            }
+
+                            row_position = position;
+                        },
+                        _ => panic!("First column must be an integer"),
+                    }
+                }
+
+                if let Some(i_cell) = output_cells[i] {
+                    cells[i_cell] = value;
+                }
+            }
+
+            last_position = Some(row_position);
+            on_row(&mut cells);
+        }
+
+        Ok(())
+    }
+
+    /// Deserialize the rows of a columnar block like `deserialize_range_with`. The positions are read first to find the
+    /// rows in the range, and only the cells of those rows are decoded. The columns that aren't projected are skipped
+    /// without reading any of their bytes.
+    fn deserialize_columnar_with<F>(
+        &self,
+        bytes: &[u8],
+        position_value_start: u64,
+        position_value_end: u64,
+        projection: Option<&[usize]>,
+        mut on_row: F,
+    ) -> std::io::Result<()>
+    where
+        F: FnMut(&mut Vec<CellValue>),
+    {
+        let columns = split_columns(bytes, self.columns.len())?;
+        let (first_row, end_row) = self.find_columnar_rows(columns[0].1, position_value_start, position_value_end)?;
+
+        let read_columns: Vec<usize> = match projection {
+            Some(projection) => projection.to_vec(),
+            None => (0..self.columns.len()).collect(),
+        };
+
+        let mut column_cells = read_columns.iter()
+            .map(|&column| self.read_column_cells(columns[column].1, column, first_row, end_row).map(Vec::into_iter))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let mut cells = Vec::with_capacity(read_columns.len());
+        for _ in first_row..end_row {
+            cells.clear();
+            cells.extend(column_cells.iter_mut().map(|cells| cells.next().expect("every column has a cell for every row")));
+            on_row(&mut cells);
+        }
+
+        Ok(())
+    }
+
+    /// Visit the rows of a columnar block like `visit_block`
+    fn visit_columnar_block<'a, F>(&self, bytes: &'a [u8], position_value_start: u64, position_value_end: u64, visit: &mut F) -> std::io::Result<()>
+    where
+        F: FnMut(&[CellRef<'_>]),
+    {
+        let columns = split_columns(bytes, self.columns.len())?;
+        let (first_row, end_row) = self.find_columnar_rows(columns[0].1, position_value_start, position_value_end)?;
+
+        let mut column_cells = columns.iter().enumerate()
+            .map(|(column, (_, column_bytes))| self.read_column_refs(column_bytes, column, first_row, end_row).map(Vec::into_iter))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let mut cells: Vec<CellRef<'a>> = Vec::with_capacity(self.columns.len());
+        for _ in first_row..end_row {
+            cells.clear();
+            cells.extend(column_cells.iter_mut().map(|cells| cells.next().expect("every column has a cell for every row")));
+            visit(&cells);
+        }
+
+        Ok(())
+    }
+
+    /// Find the rows of a columnar block that are within a range from its position column
+    /// 
+    /// # Returns
+    /// 
+    /// The index of the first row in the range and of the row after the last row in the range
+    fn find_columnar_rows(&self, positions: &[u8], position_value_start: u64, position_value_end: u64) -> std::io::Result<(usize, usize)> {
+        let mut cursor: Cursor<&[u8]> = Cursor::new(positions);
+
+        let mut first_row = 0;
+        let mut end_row = 0;
+        while cursor.position() < positions.len() as u64 {
+            let (position, _) = deserialize::read_zigzag_i64(&mut cursor)?;
+
+            // Positions are stored as signed integers, so a corrupt block could contain a negative one
+            let position = u64::try_from(position).map_err(|_| Error::new(ErrorKind::InvalidData, format!(
+                "Invalid negative position {} of row {} in block of chromosome {:?}",
+                position, end_row, self.index.chromosome,
+            )))?;
+
+            if position >= position_value_end {
+                break;
+            } else if position < position_value_start {
+                first_row += 1;
+            }
+            end_row += 1;
+        }
+
+        Ok((first_row, end_row))
+    }
+
+    /// Read the cells of the rows `[first_row, end_row)` of a column of a columnar block. The cells before the first
+    /// row are skipped, but new strings of interned columns are still added to the pool, as later rows can reference them.
+    fn read_column_cells(&self, bytes: &[u8], column: usize, first_row: usize, end_row: usize) -> std::io::Result<Vec<CellValue>> {
+        let mut cursor: Cursor<&[u8]> = Cursor::new(bytes);
+        let mut pool: Vec<Arc<str>> = Vec::new();
+
+        let type_ = self.columns[column].type_;
+        let column_error = |i_row: usize, e: Error| Error::new(ErrorKind::InvalidData, format!(
+            "Failed to read row {} of column {} in block of chromosome {:?}: {:?}",
+            i_row, column, self.index.chromosome, e,
+        ));
+
+        // The values of boolean columns are packed into bits, so a row can be read without skipping the rows before it
+        if type_ == ColumnType::Boolean {
+            return (first_row..end_row)
+                .map(|i_row| deserialize::read_packed_bool(bytes, i_row)
+                    .map(|value| CellValue::Integer(value as i64))
+                    .map_err(|e| column_error(i_row, e)))
+                .collect();
+        }
+
+        for i_row in 0..first_row {
+            match type_ {
+                ColumnType::Integer => deserialize::skip_zigzag_i64(&mut cursor),
+                ColumnType::Float => deserialize::skip_f64(&mut cursor),
+                // Hashtable strings are written like volatile strings
+                ColumnType::VolatileString | ColumnType::HashtableString => deserialize::skip_string_varlen(&mut cursor),
+                ColumnType::InternedString => deserialize::skip_interned_string(&mut cursor, &mut pool),
+                ColumnType::Char => deserialize::skip_char(&mut cursor),
+                ColumnType::Boolean => unreachable!("boolean columns are packed into bits"),
+            }.map_err(|e| column_error(i_row, e))?;
+        }
+
+        (first_row..end_row).map(|i_row| match type_ {
+            ColumnType::Integer => deserialize::read_zigzag_i64(&mut cursor).map(|(value, _)| CellValue::Integer(value)),
+            ColumnType::Float => deserialize::read_f64(&mut cursor).map(CellValue::Float),
+            ColumnType::VolatileString | ColumnType::HashtableString => deserialize::read_shared_string_varlen(&mut cursor).map(CellValue::String),
+            ColumnType::InternedString => deserialize::read_interned_string(&mut cursor, &mut pool).map(|(string, _)| CellValue::String(string)),
+            ColumnType::Char => deserialize::read_char(&mut cursor).map(CellValue::String),
+            ColumnType::Boolean => unreachable!("boolean columns are packed into bits"),
+        }.map_err(|e| column_error(i_row, e))).collect()
+    }
+
+    /// Read the cells of a column of a columnar block like `read_column_cells`, borrowing the strings from the block
+    fn read_column_refs<'a>(&self, bytes: &'a [u8], column: usize, first_row: usize, end_row: usize) -> std::io::Result<Vec<CellRef<'a>>> {
+        let mut cursor: Cursor<&'a [u8]> = Cursor::new(bytes);
+        let mut pool: Vec<&'a str> = Vec::new();
+
+        let type_ = self.columns[column].type_;
+        let mut cells = Vec::with_capacity(end_row - first_row);
+
+        if type_ == ColumnType::Boolean {
+            for i_row in first_row..end_row {
+                cells.push(CellRef::Integer(deserialize::read_packed_bool(bytes, i_row)? as i64));
+            }
+            return Ok(cells);
+        }
+
+        for i_row in 0..end_row {
+            let cell = match type_ {
+                ColumnType::Integer => CellRef::Integer(deserialize::read_zigzag_i64(&mut cursor)?.0),
+                ColumnType::Float => CellRef::Float(deserialize::read_f64(&mut cursor)?),
+                // Hashtable strings are written like volatile strings
+                ColumnType::VolatileString | ColumnType::HashtableString => CellRef::String(deserialize::read_str_varlen(&mut cursor)?),
+                ColumnType::InternedString => CellRef::String(deserialize::read_interned_str(&mut cursor, &mut pool)?),
+                ColumnType::Char => CellRef::String(deserialize::read_char_str(&mut cursor)?),
+                ColumnType::Boolean => unreachable!("boolean columns are packed into bits"),
+            };
+
+            // Borrowing the cells before the first row costs nothing, so they are decoded like the others
+            if i_row >= first_row {
+                cells.push(cell);
+            }
+        }
+
+        Ok(cells)
+    }
+}
+
+/// Split the bytes of a columnar block into the bytes of every column, with their offset in the block. Every column is
+/// prefixed with its length, so the columns are found without reading their cells.
+fn split_columns(bytes: &[u8], num_columns: usize) -> std::io::Result<Vec<(u64, &[u8])>> {
+    let mut cursor: Cursor<&[u8]> = Cursor::new(bytes);
+
+    (0..num_columns).map(|column| {
+        let len = deserialize::read_vint64(&mut cursor)?;
+        let start = cursor.position();
+
+        let column_bytes = start.checked_add(len)
+            .and_then(|end| bytes.get(start as usize..usize::try_from(end).ok()?))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!(
+                "Column {} of {} bytes at offset {} extends past the end of the block of {} bytes",
+                column, len, start, bytes.len(),
+            )))?;
+        cursor.set_position(start + len);
+
+        Ok((start, column_bytes))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::test_utils::{count_allocations, row_reader, TestDatabase};
+
+    /// A table of 500 rows with a score for every position, which is split into many blocks
+    fn scores_tsv() -> String {
+        let mut tsv = String::from("pos\tscore\n");
+        for i in 0..500 {
+            tsv.push_str(&format!("{}\t{}\n", i * 7, i as f64 / 4.0));
+        }
+        tsv
+    }
+
+    fn scores_config(index_compression_algorithm: &str) -> String {
+        r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            index_compression_algorithm = "{index_compression_algorithm}"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#.replace("{index_compression_algorithm}", index_compression_algorithm)
+    }
+
+    fn read_index(database: &TestDatabase) -> (TableHeader, TableIndex) {
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let table = client.read_database_header().unwrap().datasets.remove(0).tables.remove(0);
+        let index = TableIndex::clone(&client.read_table_index(&table).unwrap());
+        (table, index)
+    }
+
+    #[test]
+    fn compressed_index_queries_like_uncompressed_index() {
+        let tsv = scores_tsv();
+        let (_, expected) = read_index(&TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &tsv)]));
+        assert_eq!(expected.inner.len(), 125);
+
+        for (name, algorithm) in [("gzip", CompressionAlgorithm::Gzip), ("lz4", CompressionAlgorithm::LZ4)] {
+            let (table, index) = read_index(&TestDatabase::build(&scores_config(name), &[("chr1.tsv", &tsv)]));
+
+            assert_eq!(table.index_compression_algorithm, algorithm);
+            assert_eq!(index.max_position, expected.max_position);
+            assert_eq!(index.get_all(), expected.get_all());
+
+            for (start, end) in [(0, 1), (0, u64::MAX), (700, 1400), (1001, 1002), (3490, 3500)] {
+                assert_eq!(index.get_range(start, end), expected.get_range(start, end));
+            }
+        }
+    }
+
+    #[test]
+    fn index_compression_level_trades_index_size_and_keeps_query_results() {
+        let tsv = scores_tsv();
+        let build = |index_compression_level: u32| {
+            let config = scores_config("gzip").replace(
+                "rows_per_index = 4",
+                &format!("rows_per_index = 4\nindex_compression_level = {}", index_compression_level),
+            );
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+
+            let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+            let dataset = client.read_database_header().unwrap().datasets.remove(0);
+            let index = client.read_table_index(&dataset.tables[0]).unwrap();
+            let index_size = index.index_size();
+
+            let mut reader = RowReader::new(File::open(&database.path).unwrap(), &dataset, index);
+            let rows: Vec<_> = [(0, 1), (700, 1400), (1001, 1002), (0, u64::MAX)].into_iter()
+                .map(|(start, end)| reader.query_range(start, end).unwrap())
+                .collect();
+            (index_size, rows)
+        };
+
+        let (stored_size, stored_rows) = build(0);
+        let (smallest_size, smallest_rows) = build(9);
+
+        assert!(smallest_size < stored_size, "level 9 ({} bytes) isn't smaller than level 0 ({} bytes)", smallest_size, stored_size);
+        assert_eq!(smallest_rows, stored_rows);
+        assert_eq!(stored_rows[3].len(), 500);
+    }
+
+    #[test]
+    fn compression_algorithm_is_read_from_the_file() {
+        let tsv = scores_tsv();
+        for (name, algorithm) in [("none", CompressionAlgorithm::None), ("lz4", CompressionAlgorithm::LZ4), ("zstd", CompressionAlgorithm::Zstd)] {
+            let config = scores_config("gzip").replace("\n            compression_algorithm = \"gzip\"", &format!("\n            compression_algorithm = \"{}\"", name));
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+
+            let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+            assert_eq!(client.compression_algorithm("scores").unwrap(), algorithm);
+            assert_eq!(client.compression_algorithm("genes").unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn has_chromosome_finds_tables_of_any_dataset() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1, 7]
+            path = "genes_chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "volatile-string" },
+            ]
+
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [2]
+            path = "scores_chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let database = TestDatabase::build(config, &[
+            ("genes_chr1.tsv", "pos\tgene\n10\tBRCA2\n"),
+            ("genes_chr7.tsv", "pos\tgene\n20\tCFTR\n"),
+            ("scores_chr2.tsv", &scores_tsv()),
+        ]);
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+
+        assert!(client.has_chromosome("genes", 7).unwrap());
+        assert!(client.has_chromosome("genes", 1).unwrap());
+        assert!(!client.has_chromosome("genes", 2).unwrap());
+        assert!(client.has_chromosome("scores", 2).unwrap());
+        assert!(!client.has_chromosome("scores", 7).unwrap());
+        assert!(!client.has_chromosome("variants", 1).unwrap());
+    }
+
+    #[test]
+    fn hashtable_string_column_is_read_like_volatile_string() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 2
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "hashtable-string" },
+                { name = "name", type = "volatile-string" },
+            ]
+        "#;
+        let database = TestDatabase::build(config, &[
+            ("chr1.tsv", "pos\tgene\tname\n10\tBRCA2\ta\n20\tBRCA2\tb\n30\tCFTR\tc\n40\tBRCA2\td\n"),
+        ]);
+
+        let rows = row_reader(&database, "genes", 1).query_range(15, 45).unwrap();
+        assert_eq!(rows, vec![
+            vec![CellValue::Integer(20), CellValue::String("BRCA2".into()), CellValue::String("b".into())],
+            vec![CellValue::Integer(30), CellValue::String("CFTR".into()), CellValue::String("c".into())],
+            vec![CellValue::Integer(40), CellValue::String("BRCA2".into()), CellValue::String("d".into())],
+        ]);
+    }
+
+    #[test]
+    fn interned_strings_of_a_block_share_their_allocation() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "interned-string" },
+            ]
+        "#;
+        let database = TestDatabase::build(config, &[
+            ("chr1.tsv", "pos\tgene\n10\tBRCA2\n20\tCFTR\n30\tBRCA2\n40\tBRCA2\n50\tCFTR\n60\tBRCA2\n"),
+        ]);
+
+        let rows = row_reader(&database, "genes", 1).query_range(0, 100).unwrap();
+        let genes: Vec<Arc<str>> = rows.iter().map(|row| match &row[1] {
+            CellValue::String(gene) => gene.clone(),
+            cell => panic!("{:?}", cell),
+        }).collect();
+
+        assert_eq!(genes.iter().map(|gene| &**gene).collect::<Vec<_>>(), ["BRCA2", "CFTR", "BRCA2", "BRCA2", "CFTR", "BRCA2"]);
+
+        // The first block holds the first 4 rows, after which the pool starts over
+        assert!(Arc::ptr_eq(&genes[0], &genes[2]) && Arc::ptr_eq(&genes[0], &genes[3]));
+        assert!(!Arc::ptr_eq(&genes[0], &genes[5]));
+        assert!(!Arc::ptr_eq(&genes[1], &genes[4]));
+    }
+
+    /// The positions of the rows
+    fn positions(rows: &[Row]) -> Vec<i64> {
+        rows.iter().map(|row| match row[0] {
+            CellValue::Integer(position) => position,
+            ref cell => panic!("{:?}", cell),
+        }).collect()
+    }
+
+    #[test]
+    fn query_range_near_block_boundary_returns_no_rows_outside_the_range() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        // Blocks hold 4 rows 7 positions apart, so every block starts at a multiple of 28
+        for strict_bounds in [false, true] {
+            reader.set_strict_bounds(strict_bounds);
+
+            for (start, end) in [(27, 28), (28, 29), (21, 28), (21, 29), (22, 56), (28, 56), (28, 57), (55, 57), (3486, 3500)] {
+                let expected: Vec<i64> = (0..500).map(|i| i * 7).filter(|&position| position >= start && position < end).collect();
+                let rows = reader.query_range(start as u64, end as u64).unwrap();
+                assert_eq!(positions(&rows), expected, "{}..{}", start, end);
+            }
+        }
+    }
+
+    #[test]
+    fn row_at_the_end_of_the_range_is_excluded_but_included_by_the_next_position() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        // A row that starts a block, a row in the middle of a block and the row at the max position of the table
+        for strict_bounds in [false, true] {
+            reader.set_strict_bounds(strict_bounds);
+
+            for end in [28, 35, 3493] {
+                let exclusive = reader.query_range(end - 14, end).unwrap();
+                assert_eq!(positions(&exclusive), [end as i64 - 14, end as i64 - 7], "..{}", end);
+
+                let inclusive = reader.query_range(end - 14, end + 1).unwrap();
+                assert_eq!(positions(&inclusive), [end as i64 - 14, end as i64 - 7, end as i64], "..={}", end);
+            }
+        }
+
+        // The last block of the table ends at the index instead of at a position
+        let (_, last_block_end) = *reader.get_blocks(3493, 3494).last().unwrap();
+        assert_eq!(last_block_end, (u64::MAX, reader.index().index_start_offset));
+    }
+
+    #[test]
+    fn block_without_a_row_count_is_invalid_data() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let (_, mut index) = read_index(&database);
+        assert_eq!(index.block_row_count(index.inner[&28]).unwrap(), 4);
+
+        let offset = index.inner[&28];
+        index.block_row_counts.remove(&offset);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let dataset = client.read_database_header().unwrap().datasets.remove(0);
+        let mut reader = RowReader::new(File::open(&database.path).unwrap(), &dataset, index);
+        reader.set_max_rows(Some(1000));
+
+        let e = reader.query_blocks(&[offset]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("has no row count in the index"), "{}", e);
+    }
+
+    #[test]
+    fn context_rows_are_the_neighbors_of_the_range() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        // The range is within a single block, but the context comes from the blocks around it
+        let result = reader.query_range_with_context(35, 42, 6).unwrap();
+        assert_eq!(positions(&result.before), [0, 7, 14, 21, 28]);
+        assert_eq!(positions(&result.rows), [35]);
+        assert_eq!(positions(&result.after), [42, 49, 56, 63, 70, 77]);
+
+        let result = reader.query_range_with_context(700, 800, 2).unwrap();
+        assert_eq!(positions(&result.before), [686, 693]);
+        assert_eq!(positions(&result.rows), (100..115).map(|i| i * 7).collect::<Vec<i64>>());
+        assert_eq!(positions(&result.after), [805, 812]);
+
+        // Without context, the rows are those of the range
+        let result = reader.query_range_with_context(700, 800, 0).unwrap();
+        assert!(result.before.is_empty() && result.after.is_empty());
+        assert_eq!(result.rows, reader.query_range(700, 800).unwrap());
+
+        // At the ends of the table, fewer context rows are returned
+        let result = reader.query_range_with_context(3486, u64::MAX, 3).unwrap();
+        assert_eq!(positions(&result.before), [3465, 3472, 3479]);
+        assert_eq!(positions(&result.rows), [3486, 3493]);
+        assert!(result.after.is_empty());
+    }
+
+    #[test]
+    fn context_rows_include_duplicates_across_block_boundaries() {
+        // Blocks of 4 rows: [0, 10, 20, 20], [20, 20, 20, 30], [30, 30, 40, 50]
+        let tsv = "pos\tscore\n0\t0\n10\t1\n20\t2\n20\t3\n20\t4\n20\t5\n20\t6\n30\t7\n30\t8\n30\t9\n40\t10\n50\t11\n";
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", tsv)]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        let scores = |rows: &[Row]| -> Vec<f64> {
+            rows.iter().map(|row| match row[1] {
+                CellValue::Float(score) => score,
+                _ => panic!("{:?}", row),
+            }).collect()
+        };
+
+        // Every row at the start and end positions is on the right side, whichever block it is in
+        let result = reader.query_range_with_context(20, 30, 1).unwrap();
+        assert_eq!(scores(&result.before), [1.0]);
+        assert_eq!(scores(&result.rows), [2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(scores(&result.after), [7.0]);
+
+        let result = reader.query_range_with_context(30, 40, 3).unwrap();
+        assert_eq!(scores(&result.before), [4.0, 5.0, 6.0]);
+        assert_eq!(scores(&result.rows), [7.0, 8.0, 9.0]);
+        assert_eq!(scores(&result.after), [10.0, 11.0]);
+    }
+
+    #[test]
+    fn mapped_reader_returns_the_rows_of_a_file_reader() {
+        for compression_algorithm in ["none", "gzip"] {
+            let config = scores_config("none").replace(r#"compression_algorithm = "gzip""#, &format!(r#"compression_algorithm = "{}""#, compression_algorithm));
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &scores_tsv())]);
+
+            let mut client = DatabaseQueryClient::open_mapped(&database.path).unwrap();
+            let dataset = client.read_database_header().unwrap().datasets.remove(0);
+            let index = client.read_table_index(&dataset.tables[0]).unwrap();
+            let mut mapped = RowReader::new_mapped(client.mapped_file().clone(), &dataset, index);
+            let mut file = row_reader(&database, "scores", 1);
+
+            for (start, end) in [(0, u64::MAX), (30, 40), (700, 1400), (3493, 3494)] {
+                assert_eq!(mapped.query_range(start, end).unwrap(), file.query_range(start, end).unwrap(), "{} {}..{}", compression_algorithm, start, end);
+            }
+
+            // Consecutive blocks are read without seeking between them
+            mapped.set_read_ahead(true);
+            assert_eq!(mapped.query_range(0, 2000).unwrap(), file.query_range(0, 2000).unwrap(), "{}", compression_algorithm);
+            assert_eq!(mapped.verify_checksums().unwrap(), 125);
+        }
+    }
+
+    #[test]
+    fn row_at_reads_the_nth_row_of_the_table() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+        let num_rows = reader.index().num_rows();
+        assert_eq!(num_rows, 500);
+
+        assert_eq!(positions(&[reader.row_at(0).unwrap().unwrap()]), [0]);
+        assert_eq!(positions(&[reader.row_at(num_rows - 1).unwrap().unwrap()]), [3493]);
+
+        // The first and last rows of a block in the middle of the table
+        assert_eq!(positions(&[reader.row_at(4).unwrap().unwrap()]), [28]);
+        assert_eq!(positions(&[reader.row_at(7).unwrap().unwrap()]), [49]);
+
+        assert!(reader.row_at(num_rows).unwrap().is_none());
+    }
+
+    /// Create a reader of an uncompressed table with only a position column, of which the blocks start at the offsets in
+    /// the index, over bytes in memory
+    fn position_only_reader(blocks: &[(u64, u64)], bytes: Vec<u8>) -> RowReader<Cursor<Vec<u8>>> {
+        let dataset = DatasetHeader {
+            name: "scores".to_string(),
+            compression_algorithm: CompressionAlgorithm::None,
+            rows_per_index: 1,
+            position_decimals: None,
+            coordinate_base: None,
+            columnar: false,
+            columns: vec![ColumnHeader { type_: ColumnType::Integer, name: "pos".to_string() }],
+            tables: Vec::new(),
+        };
+        let index = TableIndex {
+            inner: BTreeMap::from_iter(blocks.iter().copied()),
+            compressed_block_sizes: HashMap::new(),
+            block_row_counts: HashMap::new(),
+            block_checksums: HashMap::new(),
+            chromosome: 1,
+            max_position: blocks.last().map_or(0, |(position, _)| *position),
+            index_start_offset: 1000,
+            index_end_offset: 1100,
+            end_index: None,
+        };
+
+        RowReader::new(Cursor::new(bytes), &dataset, index)
+    }
+
+    #[test]
+    fn negative_position_in_block_is_invalid_data() {
+        let reader = position_only_reader(&[(0, 0)], Vec::new());
+
+        let mut bytes = Vec::new();
+        for position in [3, -5, 8] {
+            bytes.extend_from_slice(vint64::signed::encode(position).as_ref());
+        }
+
+        let mut rows = Vec::new();
+        let e = reader.deserialize_range(&bytes, 0, 10, &mut rows).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("Invalid negative position -5"), "{}", e);
+    }
+
+    #[test]
+    fn block_ending_before_its_start_is_invalid_data() {
+        let mut reader = position_only_reader(&[(0, 200), (10, 100)], vec![0; 300]);
+
+        let mut rows = Vec::new();
+        let e = reader.read_block(((0, 200), (10, 100)), 0, 10, &mut Vec::new(), &mut Vec::new(), &mut rows).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("offset 100 of the next block is before its offset 200"), "{}", e);
+    }
+
+    #[test]
+    fn readers_sharing_a_block_cache_decompress_overlapping_blocks_once() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let block_cache = BlockCache::shared(100);
+
+        let mut first = row_reader(&database, "scores", 1);
+        first.set_block_cache(Some(block_cache.clone()));
+        let mut second = row_reader(&database, "scores", 1);
+        second.set_block_cache(Some(block_cache.clone()));
+
+        // Blocks hold 4 rows 7 positions apart, so 0..280 has 10 blocks and 140..420 has the last 5 of them
+        let hits_and_misses = || {
+            let block_cache = block_cache.lock().unwrap();
+            (block_cache.hits(), block_cache.misses())
+        };
+
+        let first_rows = first.query_range(0, 280).unwrap();
+        assert_eq!(hits_and_misses(), (0, 10));
+
+        let second_rows = second.query_range(140, 420).unwrap();
+        assert_eq!(hits_and_misses(), (5, 15));
+
+        // Cached blocks are read like blocks from the file, including the block after them
+        let mut uncached = row_reader(&database, "scores", 1);
+        assert_eq!(first_rows, uncached.query_range(0, 280).unwrap());
+        assert_eq!(second_rows, uncached.query_range(140, 420).unwrap());
+    }
+
+    #[test]
+    fn block_cache_evicts_the_least_recently_used_block() {
+        let mut block_cache = BlockCache::new(2);
+        block_cache.insert(0, 10, Arc::from(&b"first"[..]));
+        block_cache.insert(10, 10, Arc::from(&b"second"[..]));
+
+        // Using the first block makes the second block the least recently used
+        assert_eq!(block_cache.get(0, 10).as_deref(), Some(&b"first"[..]));
+        block_cache.insert(20, 10, Arc::from(&b"third"[..]));
+
+        assert_eq!(block_cache.len(), 2);
+        assert!(block_cache.get(10, 10).is_none());
+        assert!(block_cache.get(0, 10).is_some());
+        assert!(block_cache.get(20, 10).is_some());
+        assert!(block_cache.get(20, 20).is_none());
+        assert_eq!((block_cache.hits(), block_cache.misses()), (3, 2));
+    }
+
+    #[test]
+    fn check_bounds_rejects_rows_outside_the_range() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let reader = row_reader(&database, "scores", 1);
+
+        let rows = vec![vec![CellValue::Integer(28)], vec![CellValue::Integer(56)]];
+        assert!(reader.check_bounds(&rows, 28, 57).is_ok());
+
+        let e = reader.check_bounds(&rows, 28, 56).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("outside the queried range [28, 56)"), "{}", e);
+    }
+
+    #[test]
+    fn undersized_last_block_is_merged_into_the_previous_block() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            min_rows_last_block = {min_rows_last_block}
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        // 10 rows don't fill the third block of 4 rows
+        let tsv: String = std::iter::once("pos\tscore\n".to_string())
+            .chain((0..10).map(|i| format!("{}\t{}\n", i * 7, i as f64 / 4.0)))
+            .collect();
+
+        for (min_rows_last_block, expected_block_positions) in [(0, vec![0, 28, 56]), (2, vec![0, 28, 56]), (3, vec![0, 28])] {
+            let config = config.replace("{min_rows_last_block}", &min_rows_last_block.to_string());
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+
+            let (_, index) = read_index(&database);
+            let block_positions: Vec<u64> = index.get_all().into_iter().map(|(position, _)| position).collect();
+            assert_eq!(block_positions, expected_block_positions, "min_rows_last_block = {}", min_rows_last_block);
+
+            let rows = row_reader(&database, "scores", 1).query_range(0, 100).unwrap();
+            assert_eq!(positions(&rows), (0..10).map(|i| i * 7).collect::<Vec<i64>>());
+        }
+    }
+
+    #[test]
+    fn hexdump_of_an_index_region_shows_the_index_magic() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let (_, index) = read_index(&database);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        assert_eq!(client.read_region(index.index_start_offset, INDEX_MAGIC.len() as u64).unwrap(), INDEX_MAGIC);
+
+        let dump = client.hexdump_region(index.index_start_offset, 16).unwrap();
+        // The ASCII view of the hexdump shows the magic as text
+        assert!(dump.contains("INDEX"), "{}", dump);
+    }
+
+    #[test]
+    fn open_range_resolves_unbounded_sides_to_the_table_bounds() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+        let all: Vec<i64> = (0..500).map(|i| i * 7).collect();
+
+        let rows = reader.query_open_range(None, Some(30)).unwrap();
+        assert_eq!(positions(&rows), [0, 7, 14, 21, 28]);
+
+        let rows = reader.query_open_range(Some(3470), None).unwrap();
+        assert_eq!(positions(&rows), [3472, 3479, 3486, 3493]);
+
+        // The last row is at the maximum position, which an exclusive end must still include
+        let rows = reader.query_open_range(None, None).unwrap();
+        assert_eq!(positions(&rows), all);
+        assert_eq!(reader.resolve_bounds(None, None), (0, 3494));
+    }
+
+    #[test]
+    fn compression_algorithm_of_a_dataset_round_trips_through_the_header() {
+        let tsv = scores_tsv();
+
+        for (name, algorithm) in [("none", CompressionAlgorithm::None), ("gzip", CompressionAlgorithm::Gzip), ("lz4", CompressionAlgorithm::LZ4)] {
+            let config = scores_config("none").replace(r#"compression_algorithm = "gzip""#, &format!(r#"compression_algorithm = "{}""#, name));
+            let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+
+            let header = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap();
+            assert_eq!(header.datasets[0].compression_algorithm, algorithm);
+
+            let mut reader = row_reader(&database, "scores", 1);
+            assert_eq!(reader.compression_algorithm(), algorithm);
+            assert_eq!(reader.query_range(0, 3500).unwrap().len(), 500);
+        }
+    }
+
+    /// A reader that counts how often it is read from
+    struct CountingReader<R> {
+        inner: R,
+        reads: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn prefetched_indices_are_read_without_touching_the_file() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1, 2, 3]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv = scores_tsv();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv), ("chr2.tsv", &tsv), ("chr3.tsv", &tsv)]);
+
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut client = DatabaseQueryClient::new(CountingReader { inner: File::open(&database.path).unwrap(), reads: reads.clone() });
+        let dataset = client.read_database_header().unwrap().datasets.remove(0);
+
+        // Without prefetching, every index is read from the file
+        let expected: Vec<Arc<TableIndex>> = dataset.tables.iter().map(|table| client.read_table_index(table).unwrap()).collect();
+        assert!(reads.get() > 0);
+
+        client.prefetch_indices(&dataset, || File::open(&database.path)).unwrap();
+        reads.set(0);
+
+        for (table, expected) in dataset.tables.iter().zip(expected) {
+            let index = client.read_table_index(table).unwrap();
+            assert_eq!(index.chromosome, table.chromosome);
+            assert_eq!(index.get_all(), expected.get_all());
+        }
+        assert_eq!(reads.get(), 0);
+
+        client.clear_index_cache();
+        client.read_table_index(&dataset.tables[0]).unwrap();
+        assert!(reads.get() > 0);
+    }
+
+    #[test]
+    fn tables_past_the_end_of_a_truncated_database_are_not_available_yet() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1, 2]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv = scores_tsv();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv), ("chr2.tsv", &tsv)]);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let tables = client.read_database_header().unwrap().datasets.remove(0).tables;
+        let first_index = client.read_table_index(&tables[0]).unwrap();
+        assert!(first_index.index_end_offset <= tables[1].offset);
+
+        // Cut the file off right after the first table
+        File::options().write(true).open(&database.path).unwrap().set_len(first_index.index_end_offset).unwrap();
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let dataset = client.read_database_header().unwrap().datasets.remove(0);
+
+        let index = client.read_table_index(&dataset.tables[0]).unwrap();
+        let mut reader = RowReader::new(std::io::BufReader::new(File::open(&database.path).unwrap()), &dataset, index);
+        assert_eq!(reader.query_range(0, 3500).unwrap().len(), 500);
+
+        let e = match client.read_table_index(&dataset.tables[1]) {
+            Ok(_) => panic!("The second table was read from a truncated database"),
+            Err(e) => e,
+        };
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        assert!(e.to_string().contains("Table of chromosome 2 is not available yet"), "{}", e);
+    }
+
+    #[test]
+    fn aligned_blocks_start_at_a_multiple_of_the_alignment() {
+        let tsv = scores_tsv();
+        let config = scores_config("none").replace("rows_per_index = 4", "rows_per_index = 4\n            block_alignment = 4096");
+        let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+
+        let (_, index) = read_index(&database);
+        assert_eq!(index.inner.len(), 125);
+        for (position, offset) in index.get_all() {
+            assert_eq!(offset % 4096, 0, "block at position {} starts at offset {}", position, offset);
+            assert!(index.compressed_block_sizes[&offset] < 4096);
+        }
+
+        // Consecutive blocks are read without seeking, so the padding must be skipped
+        let mut reader = row_reader(&database, "scores", 1);
+        let expected: Vec<i64> = (0..500).map(|i| i * 7).collect();
+        assert_eq!(positions(&reader.query_range(0, 3500).unwrap()), expected);
+        assert_eq!(positions(&reader.query_range(30, 100).unwrap()), [35, 42, 49, 56, 63, 70, 77, 84, 91, 98]);
+    }
+
+    #[test]
+    fn grouped_query_matches_grouping_the_rows_afterwards() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "hashtable-string" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let mut tsv = String::from("pos\tgene\tscore\n");
+        for i in 0..100 {
+            tsv.push_str(&format!("{}\tGENE{}\t{}\n", i * 7, i % 3, i as f64 / 4.0));
+        }
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let mut reader = row_reader(&database, "genes", 1);
+
+        let rows = reader.query_range(30, 400).unwrap();
+        let mut expected: Vec<(CellValue, Vec<Row>)> = Vec::new();
+        for row in rows {
+            match expected.iter_mut().find(|(gene, _)| *gene == row[1]) {
+                Some((_, group)) => group.push(row),
+                None => expected.push((row[1].clone(), vec![row])),
+            }
+        }
+        assert_eq!(expected.len(), 3);
+
+        assert_eq!(reader.query_range_grouped(30, 400, 1).unwrap(), expected);
+
+        let e = reader.query_range_grouped(30, 400, 2).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn mismatched_assembly_is_rejected() {
+        let tsv = scores_tsv();
+        let config = format!("assembly = \"GRCh38\"\n{}", scores_config("none"));
+        let database = TestDatabase::build(&config, &[("chr1.tsv", &tsv)]);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        assert_eq!(client.read_database_header().unwrap().assembly.as_deref(), Some("GRCh38"));
+        client.check_assembly("GRCh38").unwrap();
+
+        let e = client.check_assembly("GRCh37").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("built for assembly 'GRCh38', but 'GRCh37' was expected"), "{}", e);
+
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &tsv)]);
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        assert_eq!(client.read_database_header().unwrap().assembly, None);
+        assert!(client.check_assembly("GRCh38").is_err());
+    }
+
+    #[test]
+    fn visiting_rows_borrows_strings_without_allocating_per_row() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 1000
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "interned-string" },
+                { name = "name", type = "volatile-string" },
+                { name = "label", type = "hashtable-string" },
+            ]
+        "#;
+        let mut tsv = String::from("pos\tgene\tname\tlabel\n");
+        for i in 0..1000 {
+            tsv.push_str(&format!("{}\tGENE{}\tname-{}\tlabel{}\n", i, i % 7, i, i % 3));
+        }
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let mut reader = row_reader(&database, "genes", 1);
+
+        let expected = reader.query_range(0, 1000).unwrap();
+
+        let mut visited = 0;
+        let mut name_bytes = 0;
+        let (result, allocations) = count_allocations(|| reader.query_range_with(0, 1000, |cells| {
+            visited += 1;
+            if let CellRef::String(name) = cells[2] {
+                name_bytes += name.len();
+            }
+        }));
+        result.unwrap();
+
+        assert_eq!(visited, 1000);
+        assert_eq!(name_bytes, (0..1000).map(|i| format!("name-{}", i).len()).sum::<usize>());
+        // The buffers of the block are allocated once, not for every row
+        assert!(allocations < 20, "{} allocations", allocations);
+
+        let mut rows = Vec::new();
+        reader.query_range_with(0, 1000, |cells| rows.push(cells.iter().map(|cell| cell.to_cell_value()).collect::<Row>())).unwrap();
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn query_exceeding_the_row_limit_fails_before_reading_any_block() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let (_, index) = read_index(&database);
+        let dataset = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap().datasets.remove(0);
+
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let file = CountingReader { inner: File::open(&database.path).unwrap(), reads: reads.clone() };
+        let mut reader = RowReader::new(file, &dataset, index);
+        reader.set_max_rows(Some(40));
+
+        // Every block has 4 rows, so the estimate is exact for ranges that start and end at a block boundary
+        assert_eq!(reader.index().estimate_rows(0, 280), 40);
+        assert_eq!(reader.index().estimate_rows(0, 281), 44);
+
+        let e = reader.query_range(0, u64::MAX).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("can contain up to 500 rows, which exceeds the limit of 40 rows"), "{}", e);
+        assert!(reader.query_range_with(0, 281, |_| {}).is_err());
+        assert_eq!(reads.get(), 0);
+
+        assert_eq!(reader.query_range(0, 280).unwrap().len(), 40);
+        assert!(reads.get() > 0);
+    }
+
+    #[test]
+    fn index_offsets_and_max_position_are_populated() {
+        let database = TestDatabase::build(&scores_config("gzip"), &[("chr1.tsv", &scores_tsv())]);
+        let (table, index) = read_index(&database);
+
+        assert_eq!(index.index_start_offset, table.offset);
+        assert_eq!(index.max_position, 499 * 7);
+
+        // The index of the only table is at the end of the file, after its last block and followed by its checksum
+        assert_eq!(index.index_end_offset + size_of::<u64>() as u64, std::fs::metadata(&database.path).unwrap().len());
+        let (_, last_block_offset) = *index.get_all().last().unwrap();
+        assert!(last_block_offset < index.index_start_offset);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        assert_eq!(client.read_region(index.index_start_offset, INDEX_MAGIC.len() as u64).unwrap(), INDEX_MAGIC);
+    }
+
+    #[test]
+    fn folding_a_range_sums_like_the_collected_rows() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        let sum = reader.fold_range(100, 2000, 0, |sum, cells| match cells[0] {
+            CellValue::Integer(position) => sum + position,
+            ref cell => panic!("{:?}", cell),
+        }).unwrap();
+
+        let expected: i64 = positions(&reader.query_range(100, 2000).unwrap()).iter().sum();
+        assert_eq!(sum, expected);
+        assert_eq!(expected, (15..286).map(|i| i * 7).sum::<i64>());
+
+        assert_eq!(reader.fold_range(5000, 6000, 42, |_, cells| panic!("{:?}", cells)).unwrap(), 42);
+    }
+
+    #[test]
+    fn build_timestamp_and_version_round_trip_through_the_header() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let header = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap();
+
+        assert!(header.build_timestamp > 0);
+        assert_eq!(header.build_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(header.datasets[0].name, "scores");
+    }
+
+    #[test]
+    fn querying_blocks_skips_the_blocks_in_between() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let (_, index) = read_index(&database);
+        let offsets: Vec<u64> = index.inner.values().copied().collect();
+
+        // Overwrite the block in between with garbage, which fails to decompress if it is read
+        let mut bytes = std::fs::read(&database.path).unwrap();
+        bytes[offsets[2] as usize..offsets[3] as usize].fill(0xff);
+        std::fs::write(&database.path, bytes).unwrap();
+
+        let mut reader = row_reader(&database, "scores", 1);
+        assert!(reader.query_range(0, 100).is_err());
+
+        let rows = reader.query_blocks(&[offsets[3], offsets[1], offsets[3]]).unwrap();
+        assert_eq!(positions(&rows), vec![28, 35, 42, 49, 84, 91, 98, 105]);
+
+        let e = reader.query_blocks(&[offsets[1] + 1]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn querying_a_block_returns_rows_at_the_position_of_the_next_block() {
+        let tsv = "pos\tscore\n0\t0\n1\t0\n2\t0\n3\t1\n3\t2\n3\t3\n4\t0\n5\t0\n";
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", tsv)]);
+        let (_, index) = read_index(&database);
+        let offsets: Vec<u64> = index.inner.values().copied().collect();
+
+        let mut reader = row_reader(&database, "scores", 1);
+        assert_eq!(positions(&reader.query_blocks(&offsets[..1]).unwrap()), vec![0, 1, 2, 3]);
+        assert_eq!(positions(&reader.query_blocks(&offsets[1..]).unwrap()), vec![3, 3, 4, 5]);
+        assert_eq!(positions(&reader.query_blocks(&offsets).unwrap()), vec![0, 1, 2, 3, 3, 3, 4, 5]);
+    }
+
+    #[test]
+    fn distinct_values_match_deduplicating_the_rows() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "interned-string" },
+                { name = "count", type = "integer" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let mut tsv = String::from("pos\tgene\tcount\tscore\n");
+        for i in 0..100 {
+            tsv.push_str(&format!("{}\tGENE{}\t{}\t{}\n", i * 7, i % 5, i % 3, i as f64 / 4.0));
+        }
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let mut reader = row_reader(&database, "genes", 1);
+
+        let rows = reader.query_range(30, 400).unwrap();
+        for column in [1, 2] {
+            let mut expected: Vec<CellValue> = Vec::new();
+            for row in &rows {
+                if !expected.contains(&row[column]) {
+                    expected.push(row[column].clone());
+                }
+            }
+
+            assert_eq!(reader.distinct_values(column, 30, 400).unwrap(), expected);
+        }
+        assert_eq!(reader.distinct_values(1, 30, 400).unwrap().len(), 5);
+
+        let e = reader.distinct_values(3, 30, 400).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    /// A reader that counts how many bytes are read from it
+    struct ByteCountingReader<R> {
+        inner: R,
+        bytes: std::rc::Rc<std::cell::Cell<u64>>,
+    }
+
+    impl<R: Read> Read for ByteCountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.inner.read(buf)?;
+            self.bytes.set(self.bytes.get() + len as u64);
+            Ok(len)
+        }
+    }
+
+    impl<R: Seek> Seek for ByteCountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn reading_the_index_of_a_narrow_range_reads_fewer_bytes() {
+        let mut tsv = String::from("pos\tscore\n");
+        for i in 0..2000 {
+            tsv.push_str(&format!("{}\t{}\n", i * 7, i as f64 / 4.0));
+        }
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &tsv)]);
+        let (table, full_index) = read_index(&database);
+        assert_eq!(full_index.inner.len(), 500);
+
+        let read_index_bytes = |start: Option<u64>, end: u64| {
+            let bytes = std::rc::Rc::new(std::cell::Cell::new(0));
+            let mut client = DatabaseQueryClient::new(ByteCountingReader { inner: File::open(&database.path).unwrap(), bytes: bytes.clone() });
+            let index = match start {
+                Some(start) => client.read_table_index_range(&table, start, end).unwrap(),
+                None => client.read_table_index(&table).unwrap(),
+            };
+            (index, bytes.get())
+        };
+
+        let (_, full_bytes) = read_index_bytes(None, 0);
+        let (index, narrow_bytes) = read_index_bytes(Some(7000), 7100);
+        assert!(narrow_bytes * 2 < full_bytes, "{} of {} bytes", narrow_bytes, full_bytes);
+        assert!(index.inner.len() < full_index.inner.len());
+
+        let dataset = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap().datasets.remove(0);
+        let mut reader = RowReader::new(std::io::BufReader::new(File::open(&database.path).unwrap()), &dataset, index);
+        let rows = reader.query_range(7000, 7100).unwrap();
+        assert_eq!(positions(&rows), (1000..1015).map(|i| i * 7).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reading_ahead_returns_the_same_rows() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+        let mut reader_ahead = row_reader(&database, "scores", 1);
+        reader_ahead.set_read_ahead(true);
+
+        for (start, end) in [(0, u64::MAX), (0, 28), (27, 29), (100, 2000), (3486, 3500)] {
+            let expected = reader.query_range(start, end).unwrap();
+
+            let started = std::time::Instant::now();
+            assert_eq!(reader_ahead.query_range(start, end).unwrap(), expected, "{}..{}", start, end);
+            println!("{}..{}: {} rows in {:?} with read-ahead", start, end, expected.len(), started.elapsed());
+        }
+    }
+
+    #[test]
+    fn float_positions_are_queried_by_a_float_range() {
+        let config = r#"
+            [datasets.map]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            position_decimals = 3
+            columns = [
+                { name = "cm", type = "float", role = "position" },
+                { name = "rate", type = "float" },
+            ]
+        "#;
+        let mut tsv = String::from("cm\trate\n");
+        for i in 0..100 {
+            tsv.push_str(&format!("{}\t{}\n", i as f64 * 0.125, i));
+        }
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let mut reader = row_reader(&database, "map", 1);
+        assert_eq!(reader.position_decimals(), Some(3));
+
+        let rows = reader.query_float_range(1.5, 2.25).unwrap();
+        let float_positions: Vec<f64> = rows.iter().map(|row| match row[0] {
+            CellValue::Float(position) => position,
+            ref cell => panic!("{:?}", cell),
+        }).collect();
+        assert_eq!(float_positions, vec![1.5, 1.625, 1.75, 1.875, 2.0, 2.125]);
+        assert_eq!(rows[0][1], CellValue::Float(12.0));
+
+        // The stored positions are the floats scaled by 10^3
+        assert_eq!(positions(&reader.query_range(1500, 1700).unwrap()), vec![1500, 1625]);
+        assert_eq!(reader.scale_position(1.0005).unwrap(), 1001);
+        assert!(reader.scale_position(-1.0).is_err());
+    }
+
+    #[test]
+    fn summary_counts_the_datasets_chromosomes_and_tables() {
+        let config = r#"
+            [datasets.scores]
+            file_per_chromosome = true
+            chromosomes = [1, 2, 3]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+
+            [datasets.more_scores]
+            file_per_chromosome = true
+            chromosomes = [2, 4]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv = scores_tsv();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv), ("chr2.tsv", &tsv), ("chr3.tsv", &tsv), ("chr4.tsv", &tsv)]);
+
+        let summary = DatabaseQueryClient::new(File::open(&database.path).unwrap()).summary().unwrap();
+        assert_eq!(summary, DatabaseSummary { num_datasets: 2, num_chromosomes: 4, num_tables: 5 });
+    }
+
+    #[test]
+    fn description_of_a_table_matches_its_header_and_index() {
+        let database = TestDatabase::build(&scores_config("gzip"), &[("chr1.tsv", &scores_tsv())]);
+        let (_, index) = read_index(&database);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let description = client.describe_table("scores", 1).unwrap();
+
+        assert_eq!(description.dataset_name, "scores");
+        assert_eq!(description.chromosome, 1);
+        let columns: Vec<(&str, ColumnType)> = description.columns.iter().map(|column| (column.name.as_str(), column.type_)).collect();
+        assert_eq!(columns, vec![("pos", ColumnType::Integer), ("score", ColumnType::Float)]);
+        assert_eq!((description.min_position, description.max_position), (0, 499 * 7));
+        assert_eq!((description.num_rows, description.num_blocks), (500, 125));
+        assert_eq!(description.compression_algorithm, CompressionAlgorithm::Gzip);
+        assert_eq!(description.index_compression_algorithm, CompressionAlgorithm::Gzip);
+        assert_eq!(description.index_size, index.index_end_offset - index.index_start_offset);
+
+        assert_eq!(client.describe_table("scores", 2).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(client.describe_table("genes", 1).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    fn open_writable(database: &TestDatabase) -> DatabaseQueryClient<File> {
+        DatabaseQueryClient::new(std::fs::OpenOptions::new().read(true).write(true).open(&database.path).unwrap())
+    }
+
+    #[test]
+    fn update_cell_overwrites_float_in_uncompressed_dataset() {
+        let config = scores_config("none").replace(r#"compression_algorithm = "gzip""#, r#"compression_algorithm = "none""#);
+        let database = TestDatabase::build(&config, &[("chr1.tsv", &scores_tsv())]);
+
+        let mut client = open_writable(&database);
+        client.update_cell("scores", 1, 35, "score", 42.5).unwrap();
+        client.update_cell("scores", 1, 499 * 7, "score", -1.0).unwrap();
+
+        let rows = row_reader(&database, "scores", 1).query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows.len(), 500);
+        assert_eq!(rows[5], vec![CellValue::Integer(35), CellValue::Float(42.5)]);
+        assert_eq!(rows[499], vec![CellValue::Integer(499 * 7), CellValue::Float(-1.0)]);
+        assert_eq!(rows[4][1], CellValue::Float(1.0));
+        assert_eq!(rows[6][1], CellValue::Float(1.5));
+    }
+
+    #[test]
+    fn update_cell_rejects_what_it_cannot_overwrite() {
+        let config = scores_config("none").replace(r#"compression_algorithm = "gzip""#, r#"compression_algorithm = "none""#);
+        let database = TestDatabase::build(&config, &[("chr1.tsv", &scores_tsv())]);
+        let mut client = open_writable(&database);
+
+        // The largest position doesn't overflow looking for the blocks that contain it
+        for position in [1, 36, u64::MAX] {
+            let e = client.update_cell("scores", 1, position, "score", 1.0).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            assert!(e.to_string().starts_with(&format!("No row at position {} ", position)), "{}", e);
+        }
+
+        let e = client.update_cell("scores", 1, 35, "pos", 1.0).unwrap_err();
+        assert!(e.to_string().contains("only float columns have a fixed width"), "{}", e);
+
+        let compressed = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let e = open_writable(&compressed).update_cell("scores", 1, 35, "score", 1.0).unwrap_err();
+        assert!(e.to_string().starts_with("Cells can only be updated in uncompressed datasets"), "{}", e);
+    }
+
+    #[test]
+    fn verify_checksums_finds_the_corrupted_block() {
+        let config = scores_config("none").replace(r#"compression_algorithm = "gzip""#, r#"compression_algorithm = "none""#);
+        let database = TestDatabase::build(&config, &[("chr1.tsv", &scores_tsv())]);
+        assert_eq!(row_reader(&database, "scores", 1).verify_checksums().unwrap(), 125);
+
+        // Updating a cell updates the checksum of its block
+        open_writable(&database).update_cell("scores", 1, 35, "score", 42.5).unwrap();
+        assert_eq!(row_reader(&database, "scores", 1).verify_checksums().unwrap(), 125);
+
+        let offset = row_reader(&database, "scores", 1).index().inner[&28];
+        let mut bytes = std::fs::read(&database.path).unwrap();
+        bytes[offset as usize + 1] ^= 0xff;
+        std::fs::write(&database.path, bytes).unwrap();
+
+        let e = row_reader(&database, "scores", 1).verify_checksums().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().starts_with(&format!("Block of chromosome 1 at offset {} has checksum", offset)), "{}", e);
+
+        // The checksum of a block can't be updated in a compressed index
+        let compressed_index = TestDatabase::build(&config.replace(r#"index_compression_algorithm = "none""#, r#"index_compression_algorithm = "gzip""#), &[("chr1.tsv", &scores_tsv())]);
+        let e = open_writable(&compressed_index).update_cell("scores", 1, 35, "score", 1.0).unwrap_err();
+        assert!(e.to_string().starts_with("Cells can only be updated in tables with an uncompressed index"), "{}", e);
+    }
+
+    #[test]
+    fn windows_match_filtering_all_rows() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+        let all_rows = reader.query_range(0, u64::MAX).unwrap();
+
+        // Overlapping, nested, distant, unsorted and out of range anchors
+        let anchors = [700, 30, 35, 1000, 3, 5000, 30];
+        let windows = reader.query_windows(&anchors, 20).unwrap();
+        assert_eq!(windows.len(), anchors.len());
+
+        for (anchor, rows) in anchors.into_iter().zip(windows) {
+            let expected: Vec<Row> = all_rows.iter()
+                .filter(|row| matches!(row[0], CellValue::Integer(p) if p as u64 + 20 >= anchor && (p as u64) < anchor + 20))
+                .cloned()
+                .collect();
+            assert_eq!(rows, expected, "anchor {}", anchor);
+        }
+    }
+
+    #[test]
+    fn windows_return_rows_at_the_position_of_the_next_block() {
+        let tsv = "pos\tscore\n0\t0\n1\t0\n2\t0\n3\t1\n3\t2\n3\t3\n4\t0\n5\t0\n";
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", tsv)]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        // The first row at position 3 is at the end of the first block, the others start the second block
+        let windows = reader.query_windows(&[3, 4, 1], 1).unwrap();
+        let windows: Vec<Vec<i64>> = windows.iter().map(|rows| positions(rows)).collect();
+        assert_eq!(windows, vec![vec![2, 3, 3, 3], vec![3, 3, 3, 4], vec![0, 1]]);
+    }
+
+    #[test]
+    fn zero_based_bounds_query_a_one_based_dataset() {
+        let config = scores_config("none").replace("rows_per_index = 4", "rows_per_index = 4\ncoordinate_base = 1");
+        let tsv = "pos\tscore\n1\t0.5\n2\t1.5\n10\t2.5\n11\t3.5\n20\t4.5\n";
+        let database = TestDatabase::build(&config, &[("chr1.tsv", tsv)]);
+
+        let header = DatabaseQueryClient::new(File::open(&database.path).unwrap()).read_database_header().unwrap();
+        assert_eq!(header.datasets[0].coordinate_base, Some(1));
+
+        // The 0-based half-open range [9, 11) covers the 1-based positions 10 and 11
+        let mut reader = row_reader(&database, "scores", 1);
+        let start = reader.translate_position(9, 0).unwrap();
+        let end = reader.translate_position(11, 0).unwrap();
+        assert_eq!((start, end), (10, 12));
+        assert_eq!(positions(&reader.query_range(start, end).unwrap()), vec![10, 11]);
+
+        assert_eq!(reader.translate_position(0, 1).unwrap(), 0);
+        assert_eq!(header.datasets[0].translate_position(u64::MAX, 0).unwrap(), u64::MAX);
+
+        // Positions can't be translated if the coordinate base of the dataset is unknown
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", tsv)]);
+        let e = row_reader(&database, "scores", 1).translate_position(9, 0).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    /// Intervals of which the ends are in a different order than the starts, split into blocks of 4 rows
+    fn intervals_database(end_index: bool) -> TestDatabase {
+        let config = r#"
+            [datasets.intervals]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            end_index = {end_index}
+            columns = [
+                { name = "start", type = "integer", role = "position-start" },
+                { name = "end", type = "integer", role = "position-end" },
+                { name = "name", type = "volatile-string" },
+            ]
+        "#.replace("{end_index}", &end_index.to_string());
+
+        let tsv: String = std::iter::once("start\tend\tname\n".to_string())
+            .chain((0..40).map(|i| format!("{}\t{}\tinterval-{}\n", i * 10, i * 10 + 5 + (i % 7) * 100, i)))
+            .collect();
+
+        TestDatabase::build(&config, &[("chr1.tsv", &tsv)])
+    }
+
+    #[test]
+    fn intervals_are_queried_by_their_start_or_their_end() {
+        let database = intervals_database(true);
+        let mut reader = row_reader(&database, "intervals", 1);
+        let all_rows = reader.query_range(0, u64::MAX).unwrap();
+        assert_eq!(all_rows.len(), 40);
+
+        let column = |rows: &[Row], i: usize| -> Vec<i64> {
+            rows.iter().map(|row| match row[i] { CellValue::Integer(value) => value, _ => panic!() }).collect()
+        };
+
+        for (start, end) in [(0, 100), (95, 305), (300, 700), (0, u64::MAX), (10_000, 20_000)] {
+            let in_range = |value: &i64| (start..end).contains(&(*value as u64));
+
+            let by_start = reader.query_range(start, end).unwrap();
+            assert_eq!(column(&by_start, 0), column(&all_rows, 0).into_iter().filter(in_range).collect::<Vec<_>>());
+
+            // The rows with an end in the range are returned in the order of their start
+            let by_end = reader.query_end_range(start, end).unwrap();
+            let expected: Vec<Row> = all_rows.iter()
+                .filter(|row| matches!(row[1], CellValue::Integer(end) if in_range(&end)))
+                .cloned()
+                .collect();
+            assert_eq!(by_end, expected, "[{}, {})", start, end);
+        }
+
+        // The same range selects different intervals by start than by end
+        assert_eq!(column(&reader.query_range(95, 305).unwrap(), 0), vec![100, 110, 120, 130, 140, 150, 160, 170, 180, 190, 200, 210, 220, 230, 240, 250, 260, 270, 280, 290, 300]);
+        let by_end = reader.query_end_range(95, 305).unwrap();
+        assert_eq!(column(&by_end, 0), vec![10, 20, 80, 90, 140, 150, 210, 280]);
+        assert_eq!(column(&by_end, 1), vec![115, 225, 185, 295, 145, 255, 215, 285]);
+    }
+
+    #[test]
+    fn querying_by_end_requires_an_end_index() {
+        let database = intervals_database(false);
+        let mut reader = row_reader(&database, "intervals", 1);
+        let e = reader.query_end_range(0, 100).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("has no end index"), "{}", e);
+    }
+
+    #[test]
+    fn projected_rows_match_picking_the_cells_of_the_full_rows() {
+        let config = r#"
+            [datasets.wide]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 16
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "interned-string" },
+                { name = "score", type = "float" },
+                { name = "note", type = "volatile-string" },
+                { name = "count", type = "integer" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tgene\tscore\tnote\tcount\n".to_string())
+            .chain((0..200).map(|i| format!("{}\tgene-{}\t{}\tnote about row {}\t{}\n", i * 5, i % 3, i as f64 / 8.0, i, i * i)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let mut reader = row_reader(&database, "wide", 1);
+
+        let full = reader.query_range(100, 600).unwrap();
+        let (projected, projected_allocations) = count_allocations(|| reader.query_range_projected(100, 600, vec![2, 0]).unwrap());
+
+        let expected: Vec<Row> = full.iter().map(|row| vec![row[2].clone(), row[0].clone()]).collect();
+        assert_eq!(projected, expected);
+        assert_eq!(projected.len(), 100);
+
+        // Projecting every column reads the same rows, but also allocates the note of every row
+        let (all_columns, all_columns_allocations) = count_allocations(|| reader.query_range_projected(100, 600, vec![0, 1, 2, 3, 4]).unwrap());
+        assert_eq!(all_columns, full);
+        assert!(
+            projected_allocations + projected.len() <= all_columns_allocations,
+            "{} + {} > {}", projected_allocations, projected.len(), all_columns_allocations,
+        );
+
+        // The position column is read to find the rows, but only returned if it is projected
+        let scores = reader.query_range_projected(100, 600, vec![4]).unwrap();
+        assert_eq!(scores.first(), Some(&vec![CellValue::Integer(400)]));
+        assert_eq!(scores.len(), 100);
+
+        for column_indices in [vec![5], vec![1, 3, 1]] {
+            let e = reader.query_range_projected(0, 100, column_indices).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn columns_projected_by_name_are_returned_in_the_order_of_the_names() {
+        let config = r#"
+            [datasets.variants]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "gzip"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "af", type = "float" },
+                { name = "gene", type = "volatile-string" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\taf\tgene\n".to_string())
+            .chain((0..20).map(|i| format!("{}\t{}\tgene-{}\n", i * 10, i as f64 / 20.0, i)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let mut reader = row_reader(&database, "variants", 1);
+
+        let column_indices = reader.column_indices(&["af", "pos"]).unwrap();
+        assert_eq!(column_indices, [1, 0]);
+
+        let rows = reader.query_range_projected(30, 60, column_indices).unwrap();
+        assert_eq!(rows, [
+            vec![CellValue::Float(0.15), CellValue::Integer(30)],
+            vec![CellValue::Float(0.2), CellValue::Integer(40)],
+            vec![CellValue::Float(0.25), CellValue::Integer(50)],
+        ]);
+
+        let e = reader.column_indices(&["af", "score"]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(e.to_string(), "Column 'score' not found");
+    }
+
+    #[test]
+    fn validating_the_index_reports_the_first_entry_that_points_to_the_wrong_block() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        row_reader(&database, "scores", 1).validate_index().unwrap();
+
+        // Point the entry of the 4th block at the 5th block, which starts 4 rows later, and drop the entry of the 5th block
+        let (table, mut index) = read_index(&database);
+        let offsets: Vec<u64> = index.inner.values().copied().collect();
+        index.inner.remove(&(4 * 4 * 7));
+        index.inner.insert(3 * 4 * 7, offsets[4]);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let dataset = client.read_database_header().unwrap().datasets.remove(0);
+        assert_eq!(dataset.tables[0].offset, table.offset);
+
+        let mut reader = RowReader::new(File::open(&database.path).unwrap(), &dataset, index);
+        let e = reader.validate_index().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), format!(
+            "Index entry 3 of chromosome 1 with position 84 at offset {} is invalid: the first row of its block has position 112",
+            offsets[4],
+        ));
+    }
+
+    #[test]
+    fn overlapping_intervals_match_filtering_all_rows() {
+        let database = intervals_database(true);
+        let mut reader = row_reader(&database, "intervals", 1);
+        let all_rows = reader.query_range(0, u64::MAX).unwrap();
+
+        for (start, end) in [(0, 1), (5, 6), (95, 305), (390, 400), (1000, 2000), (0, u64::MAX)] {
+            let expected: Vec<Row> = all_rows.iter()
+                .filter(|row| match (&row[0], &row[1]) {
+                    (CellValue::Integer(row_start), CellValue::Integer(row_end)) => (*row_start as u64) < end && (*row_end as u64) >= start,
+                    _ => false,
+                })
+                .cloned()
+                .collect();
+            assert_eq!(reader.query_overlapping(start, end).unwrap(), expected, "[{}, {})", start, end);
+        }
+
+        // An interval that starts long before the range but ends in it is found, and the end is inclusive
+        let overlapping = reader.query_overlapping(665, 666).unwrap();
+        assert_eq!(overlapping[0], vec![CellValue::Integer(60), CellValue::Integer(665), CellValue::String("interval-6".into())]);
+
+        let e = row_reader(&intervals_database(false), "intervals", 1).query_overlapping(0, 100).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn projecting_a_column_of_a_columnar_block_never_reads_the_other_columns() {
+        let config = r#"
+            [datasets.wide]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 16
+            compression_algorithm = "none"
+            columnar = true
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "note", type = "volatile-string" },
+                { name = "score", type = "float" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tnote\tscore\n".to_string())
+            .chain((0..100).map(|i| format!("{}\tnote about row {:02}\t{}\n", i * 5, i, i as f64 / 8.0)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+
+        let mut reader = row_reader(&database, "wide", 1);
+        assert!(reader.columnar());
+        let rows = reader.query_range(0, u64::MAX).unwrap();
+        assert_eq!(rows[42], vec![CellValue::Integer(210), CellValue::String("note about row 42".into()), CellValue::Float(5.25)]);
+
+        // Replace every note with bytes that aren't valid UTF-8, so reading any of them fails
+        let mut bytes = std::fs::read(&database.path).unwrap();
+        let note_len = "note about row 00".len();
+        let mut num_notes = 0;
+        while let Some(start) = bytes.windows(note_len).position(|window| window.starts_with(b"note about row ")) {
+            bytes[start..start + note_len].fill(0xFF);
+            num_notes += 1;
+        }
+        assert_eq!(num_notes, 100);
+        std::fs::write(&database.path, &bytes).unwrap();
+
+        let mut reader = row_reader(&database, "wide", 1);
+        assert_eq!(reader.query_range(0, u64::MAX).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let scores = reader.query_range_projected(0, u64::MAX, vec![2]).unwrap();
+        let expected: Vec<Row> = rows.iter().map(|row| vec![row[2].clone()]).collect();
+        assert_eq!(scores, expected);
+    }
+
+    #[test]
+    fn cached_table_index_is_shared() {
+        let database = TestDatabase::build(&scores_config("gzip"), &[("chr1.tsv", &scores_tsv())]);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let table = client.read_database_header().unwrap().datasets.remove(0).tables.remove(0);
+
+        let index = client.read_table_index(&table).unwrap();
+        assert!(Arc::ptr_eq(&index, &client.read_table_index(&table).unwrap()));
+        assert!(Arc::ptr_eq(&index, &client.read_table_index_range(&table, 100, 200).unwrap()));
+
+        client.clear_index_cache();
+        assert_eq!(Arc::strong_count(&index), 1);
+
+        let reread = client.read_table_index(&table).unwrap();
+        assert!(!Arc::ptr_eq(&index, &reread));
+        assert_eq!(reread.get_all(), index.get_all());
+    }
+
+    #[test]
+    fn coerced_integer_column_is_returned_as_floats() {
+        let database = intervals_database(false);
+        let mut reader = row_reader(&database, "intervals", 1);
+        let rows = reader.query_range(0, 100).unwrap();
+
+        reader.set_column_coercion(1, Some(CellCoercion::Float)).unwrap();
+        assert_eq!(reader.column_coercion(1), Some(CellCoercion::Float));
+
+        let coerced = reader.query_range(0, 100).unwrap();
+        let expected: Vec<Row> = rows.iter().map(|row| match row[..] {
+            [CellValue::Integer(start), CellValue::Integer(end), ref name] => {
+                vec![CellValue::Integer(start), CellValue::Float(end as f64), name.clone()]
+            },
+            _ => panic!("{:?}", row),
+        }).collect();
+        assert_eq!(coerced, expected);
+
+        // Without a coercion, the values are returned as they are stored
+        reader.set_column_coercion(1, None).unwrap();
+        assert_eq!(reader.query_range(0, 100).unwrap(), rows);
+    }
+
+    #[test]
+    fn numbers_are_coerced_to_strings_and_other_coercions_are_rejected() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+        let mut reader = row_reader(&database, "scores", 1);
+
+        reader.set_column_coercion(1, Some(CellCoercion::String)).unwrap();
+        assert_eq!(reader.query_range(35, 42).unwrap(), [vec![CellValue::Integer(35), CellValue::String("1.25".into())]]);
+
+        for (column, coercion, message) in [
+            (0, CellCoercion::Float, "Position column 'pos' can't be converted"),
+            (1, CellCoercion::Float, "Column 'score' of type Float can't be converted to Float"),
+            (2, CellCoercion::String, "Column index 2 is out of bounds for 2 columns"),
+        ] {
+            let e = reader.set_column_coercion(column, Some(coercion)).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            assert_eq!(e.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn table_checksum_detects_a_corrupted_byte() {
+        let database = TestDatabase::build(&scores_config("none"), &[("chr1.tsv", &scores_tsv())]);
+
+        let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+        let table = client.read_database_header().unwrap().datasets.remove(0).tables.remove(0);
+        let index = client.read_table_index(&table).unwrap();
+        let region_start = *index.inner.values().min().unwrap() as usize;
+        let region_end = index.index_end_offset as usize;
+
+        // The stored checksum is the xxHash64 of everything from the first block to the end of the index
+        let mut bytes = std::fs::read(&database.path).unwrap();
+        let checksum = twox_hash::XxHash64::oneshot(0, &bytes[region_start..region_end]);
+        assert_eq!(client.read_table_checksum(&table).unwrap(), checksum);
+        assert_eq!(bytes[region_end..region_end + 8], checksum.to_be_bytes());
+        client.verify_table_checksum(&table).unwrap();
+
+        for offset in [region_start + 3, region_end - 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset] ^= 0x40;
+            std::fs::write(&database.path, &corrupted).unwrap();
+
+            let e = DatabaseQueryClient::new(File::open(&database.path).unwrap()).verify_table_checksums().unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+            assert!(e.to_string().starts_with("Dataset 'scores': Table of chromosome 1 has checksum "), "{}", e);
+        }
+
+        // A corrupted checksum is a mismatch as well
+        bytes[region_end + 7] ^= 1;
+        std::fs::write(&database.path, &bytes).unwrap();
+        let e = DatabaseQueryClient::new(File::open(&database.path).unwrap()).verify_table_checksum(&table).unwrap_err();
+        assert!(e.to_string().ends_with(&format!("but {:#018x} is stored after its index", checksum ^ 1)), "{}", e);
+    }
+
+    #[test]
+    fn corrupted_row_error_names_the_last_good_position() {
+        let config = r#"
+            [datasets.genes]
+            file_per_chromosome = true
+            chromosomes = [1]
+            path = "chr{chromosome}.tsv"
+            rows_per_index = 4
+            compression_algorithm = "none"
+            columns = [
+                { name = "pos", type = "integer", role = "position" },
+                { name = "gene", type = "volatile-string" },
+            ]
+        "#;
+        let tsv: String = std::iter::once("pos\tgene\n".to_string())
+            .chain(('a'..='f').zip(1..).map(|(name, i)| format!("{}\tgene-{}\n", i * 100, name)))
+            .collect();
+        let database = TestDatabase::build(config, &[("chr1.tsv", &tsv)]);
+        let blocks = row_reader(&database, "genes", 1).index().get_all();
+
+        let mut bytes = std::fs::read(&database.path).unwrap();
+        let find = |bytes: &[u8], needle: &[u8]| bytes.windows(needle.len()).position(|window| window == needle).unwrap();
+
+        // The first byte of a string can't be 0xFF in UTF-8, so the gene of the rows at 300 and 500 can't be read
+        let gene_c = find(&bytes, b"gene-c");
+        let gene_e = find(&bytes, b"gene-e");
+        bytes[gene_c] = 0xFF;
+        bytes[gene_e] = 0xFF;
+        std::fs::write(&database.path, &bytes).unwrap();
+
+        // The row of gene-c starts where the row of gene-b ends
+        let row_offset = find(&bytes, b"gene-b") + "gene-b".len() - blocks[0].1 as usize;
+        for (start, end) in [(0, u64::MAX), (300, 400)] {
+            let e = row_reader(&database, "genes", 1).query_range(start, end).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+            assert!(e.to_string().starts_with(&format!("Failed to read column 'gene' of the row at offset {} of ", row_offset)), "{}", e);
+            assert!(e.to_string().contains("which follows the row at position 200: "), "{}", e);
+        }
+
+        let e = row_reader(&database, "genes", 1).query_range(500, 600).unwrap_err();
+        assert!(e.to_string().starts_with("Failed to read column 'gene' of the row at offset 0 of "), "{}", e);
+        assert!(e.to_string().contains("which is the first row of the block: "), "{}", e);
+    }
+}
@@ -0,0 +1,110 @@
+//! Helpers for tests that build small databases from files in a temporary directory.
+
+use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell, fs::File, io::BufReader, path::PathBuf};
+
+use tempfile::TempDir;
+
+use crate::{config::Config, database::Database, query::{DatabaseQueryClient, RowReader}};
+
+/// A database that was built in a temporary directory. The directory is removed when it is dropped.
+pub struct TestDatabase {
+    pub dir: TempDir,
+    pub path: PathBuf,
+}
+
+impl TestDatabase {
+    /// Write the files to a temporary directory and build a database from a config in TOML. The paths of the datasets
+    /// are relative to the directory.
+    pub fn build(config: &str, files: &[(&str, &str)]) -> Self {
+        let dir = write_files(files);
+        let config = load_config(&dir, config);
+        let path = dir.path().join("test.zygosdb");
+
+        Database::new(path.clone(), config).save().unwrap();
+
+        Self { dir, path }
+    }
+}
+
+/// Create a reader of the table of a chromosome of a dataset
+pub fn row_reader(database: &TestDatabase, dataset_name: &str, chromosome: u8) -> RowReader<BufReader<File>> {
+    let mut client = DatabaseQueryClient::new(File::open(&database.path).unwrap());
+    let header = client.read_database_header().unwrap();
+
+    let dataset = header.datasets.iter().find(|dataset| dataset.name == dataset_name).unwrap();
+    let table = dataset.tables.iter().find(|table| table.chromosome == chromosome).unwrap();
+    let index = client.read_table_index(table).unwrap();
+
+    RowReader::new(BufReader::new(File::open(&database.path).unwrap()), dataset, index)
+}
+
+/// Write files with their contents to a new temporary directory
+pub fn write_files(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+
+    for (name, contents) in files {
+        std::fs::write(dir.path().join(name), contents).unwrap();
+    }
+
+    dir
+}
+
+/// Parse and validate a config in TOML, of which the paths of the datasets are relative to a directory
+pub fn load_config(dir: &TempDir, config: &str) -> Config {
+    let config = parse_config(dir, config);
+    config.validate().unwrap();
+    config
+}
+
+/// Parse a config in TOML without validating it, of which the paths of the datasets are relative to a directory
+pub fn parse_config(dir: &TempDir, config: &str) -> Config {
+    Config::from_str(config, dir.path().to_path_buf()).unwrap()
+}
+
+/// Counts the allocations and allocated bytes of each thread, so tests can assert how often and how much a piece of code
+/// allocates.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    /// Bytes allocated minus bytes deallocated by the thread, which is negative if it freed memory of other threads
+    static ALLOCATED_BYTES: Cell<isize> = const { Cell::new(0) };
+    static PEAK_ALLOCATED_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        let allocated = ALLOCATED_BYTES.with(|bytes| {
+            bytes.set(bytes.get() + layout.size() as isize);
+            bytes.get()
+        });
+        PEAK_ALLOCATED_BYTES.with(|peak| peak.set(peak.get().max(allocated)));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED_BYTES.with(|bytes| bytes.set(bytes.get() - layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Count the allocations made by the current thread while running a function
+pub fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let res = f();
+    let after = ALLOCATIONS.with(Cell::get);
+    (res, after - before)
+}
+
+/// Measure the most memory the current thread had allocated at once while running a function, relative to before it
+pub fn peak_allocated_bytes<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED_BYTES.with(Cell::get);
+    PEAK_ALLOCATED_BYTES.with(|peak| peak.set(before));
+    let res = f();
+    let peak = PEAK_ALLOCATED_BYTES.with(Cell::get);
+    (res, (peak - before) as usize)
+}
@@ -1,16 +1,21 @@
+use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::num::IntErrorKind;
+use std::sync::Arc;
 use clap::ValueEnum;
+use regex::Regex;
 
-use flate2::read::MultiGzDecoder;
-use serde::Deserialize;
+use flate2::Crc;
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+use serde::{Deserialize, Serialize};
 
 use crate::config::{Column, ColumnRole};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum ColumnType {
     /// Column contains only integers.
@@ -20,30 +25,78 @@ pub enum ColumnType {
     /// Column contains a lot of different strings, but there can be duplicates.
     VolatileString = 2,
     /// Column contains strings that are repeated many times.
+    ///
+    /// Stored like `VolatileString` until its dictionary is implemented. The dictionary should reserve index 0 for a
+    /// sentinel, so an empty string written by `MissingValuePolicy::ReplaceWithEmptyString` can be told apart from a
+    /// genuinely empty value.
     HashtableString = 3,
+    /// Column contains strings that are often repeated within a block. Each distinct string is stored once per block.
+    InternedString = 4,
+    /// Column contains single ASCII characters, such as a strand or a base. Each character is stored as one byte.
+    Char = 5,
+    /// Column contains `true` or `false`, or `1` or `0`. The values are read as the integers 1 and 0, and stored as one
+    /// byte per row, or as one bit per row in columnar blocks.
+    Boolean = 6,
 }
 
 impl ColumnType {
-    fn get_cell_value(&self, value: &str) -> Result<CellValue, String> {
+    fn get_cell_value(&self, value: &str, coerce_whole_floats: bool) -> Result<CellValue, String> {
         match self {
             Self::Integer => {
-                match value.parse() {
+                match value.parse::<i64>() {
                     Ok(value) => Ok(CellValue::Integer(value)),
-                    Err(_) => Err(format!("Failed to parse value '{:?}' as integer.", value)),
+                    Err(e) if matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => Err(format!(
+                        "Value '{}' is out of range for an integer (must be between {} and {}).",
+                        value, i64::MIN, i64::MAX,
+                    )),
+                    Err(_) => match parse_whole_float(value) {
+                        Some(value) if coerce_whole_floats => Ok(CellValue::Integer(value)),
+                        _ => Err(format!("Failed to parse value '{:?}' as integer.", value)),
+                    },
                 }
             },
             Self::Float => {
-                match value.parse() {
+                match value.parse::<f64>() {
+                    // Values too large for a float are parsed as infinity, which is only valid if it was written as such
+                    Ok(parsed) if parsed.is_infinite() && !is_infinity_literal(value) => Err(format!(
+                        "Value '{}' is out of range for a float (must be between {:e} and {:e}).",
+                        value, f64::MIN, f64::MAX,
+                    )),
                     Ok(value) => Ok(CellValue::Float(value)),
                     Err(_) => Err(format!("Failed to parse value '{:?}' as float.", value)),
                 }
             },
-            Self::VolatileString => Ok(CellValue::String(value.to_owned())),
-            Self::HashtableString => Ok(CellValue::String(value.to_owned())),
+            Self::VolatileString => Ok(CellValue::String(value.into())),
+            Self::HashtableString => Ok(CellValue::String(value.into())),
+            Self::InternedString => Ok(CellValue::String(value.into())),
+            // An empty value is stored as 0, which can't be a character of a text file
+            Self::Char if value.is_empty() || (value.len() == 1 && value.is_ascii() && value != "\0") => Ok(CellValue::String(value.into())),
+            Self::Char => Err(format!("Value '{:?}' is not a single ASCII character.", value)),
+            Self::Boolean if value == "1" || value.eq_ignore_ascii_case("true") => Ok(CellValue::Integer(1)),
+            Self::Boolean if value == "0" || value.eq_ignore_ascii_case("false") => Ok(CellValue::Integer(0)),
+            Self::Boolean => Err(format!("Failed to parse value '{:?}' as boolean.", value)),
         }
     }
 }
 
+/// Check if a value is written as infinity, such as `inf` or `-Infinity`
+fn is_infinity_literal(value: &str) -> bool {
+    let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+    unsigned.eq_ignore_ascii_case("inf") || unsigned.eq_ignore_ascii_case("infinity")
+}
+
+/// Parse a float without a fractional part, such as `3.0`, as an integer
+fn parse_whole_float(value: &str) -> Option<i64> {
+    let value: f64 = value.parse().ok()?;
+
+    // i64::MAX can't be represented exactly as a float, so the upper bound is exclusive
+    if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        Some(value as i64)
+    } else {
+        None
+    }
+}
+
 impl TryFrom<u8> for ColumnType {
     type Error = ();
 
@@ -53,13 +106,16 @@ impl TryFrom<u8> for ColumnType {
             1 => Ok(Self::Float),
             2 => Ok(Self::VolatileString),
             3 => Ok(Self::HashtableString),
+            4 => Ok(Self::InternedString),
+            5 => Ok(Self::Char),
+            6 => Ok(Self::Boolean),
             _ => Err(()),
         }
     }
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
 pub enum MissingValuePolicy {
     /// Omit the row if there is a missing value in it.
     OmitRow,
@@ -75,36 +131,120 @@ impl Default for MissingValuePolicy {
     }
 }
 
-#[derive(Debug)]
+/// A built-in parser that transforms a raw value into the standard format of its column type before it is parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParseFormat {
+    /// Percentages such as `5%` are parsed as fractions, like `0.05`.
+    Percent,
+    /// Doubles with a `D` exponent from Fortran output such as `1.5D+03`.
+    FortranDouble,
+    /// Hexadecimal integers such as `0x1F`.
+    HexInteger,
+}
+
+impl ParseFormat {
+    /// The column type the transformed values are parsed as
+    pub fn column_type(&self) -> ColumnType {
+        match self {
+            Self::Percent | Self::FortranDouble => ColumnType::Float,
+            Self::HexInteger => ColumnType::Integer,
+        }
+    }
+
+    pub fn transform<'a>(&self, value: &'a str) -> Result<Cow<'a, str>, String> {
+        // Missing values are left to the missing value policy
+        if value.is_empty() {
+            return Ok(Cow::Borrowed(value));
+        }
+
+        match self {
+            Self::Percent => {
+                let percentage: f64 = value.trim_end_matches('%').trim_end().parse()
+                    .map_err(|_| format!("Failed to parse value '{:?}' as percentage.", value))?;
+                Ok(Cow::Owned((percentage / 100.0).to_string()))
+            },
+            Self::FortranDouble => Ok(Cow::Owned(value.replace(['D', 'd'], "E"))),
+            Self::HexInteger => {
+                let (negative, digits) = match value.strip_prefix('-') {
+                    Some(digits) => (true, digits),
+                    None => (false, value),
+                };
+                let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits);
+
+                let integer = i64::from_str_radix(digits, 16)
+                    .map_err(|_| format!("Failed to parse value '{:?}' as hexadecimal integer.", value))?;
+                Ok(Cow::Owned(if negative { -integer } else { integer }.to_string()))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum ConstraintViolationPolicy {
+    /// Omit the row if a value in it violates the column constraints.
+    OmitRow,
+    /// Return an error if a value in the row violates the column constraints.
+    #[default]
+    Throw,
+    /// Treat the value as missing and apply the missing value policy of the column.
+    TreatAsMissing,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum CellValue {
     Integer(i64),
     Float(f64),
-    String(String),
+    String(Arc<str>),
 }
 
 #[derive(Debug)]
-pub struct NotEnoughLinesError;
+pub enum GuessColumnTypesError {
+    /// The file has fewer lines than the minimum sample size.
+    NotEnoughLines,
+    /// A column of which the missing value policy is `Throw` has a missing value on a line.
+    MissingValue { column: usize, line: usize },
+}
 
-impl std::fmt::Display for NotEnoughLinesError {
+impl std::fmt::Display for GuessColumnTypesError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Not enough lines to guess column types.")
+        match self {
+            Self::NotEnoughLines => write!(f, "Not enough lines to guess column types."),
+            Self::MissingValue { column, line } => write!(f, "Line {}: Missing value in column {}.", line, column),
+        }
     }
 }
 
+/// When to stop sampling the lines of a file to guess the types of its columns, once the minimum number of lines is read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleLimit {
+    /// Stop after reading this many lines.
+    Lines(usize),
+    /// Stop after reading this fraction between 0 and 1 of the bytes of the file. Compressed files are measured by their
+    /// compressed size, which is an estimate as they are decompressed ahead of the lines that are read.
+    Fraction(f64),
+}
+
 pub enum FileReader {
     Regular(File),
     Gzipped(MultiGzDecoder<File>),
+    Bgzf(BgzfReader<File>),
 }
 
 impl FileReader {
     pub fn new(file: File) -> Self {
-        let mut magic_bytes = [0; 2];
+        // Enough to read the first subfield of the extra field of a BGZF block
+        let mut header = Vec::with_capacity(14);
 
-        file.try_clone().unwrap().read_exact(magic_bytes.as_mut()).unwrap();
+        file.try_clone().unwrap().take(14).read_to_end(&mut header).unwrap();
 
         file.try_clone().unwrap().seek(SeekFrom::Start(0)).unwrap();
 
-        if magic_bytes == [0x1f, 0x8b] {
+        if BgzfReader::<File>::is_bgzf_header(&header) {
+            return Self::Bgzf(BgzfReader::new(file));
+        }
+
+        if header.starts_with(&[0x1f, 0x8b]) {
             return Self::Gzipped(MultiGzDecoder::new(file));
         } else {
             return Self::Regular(file);
@@ -112,22 +252,153 @@ impl FileReader {
     }
 }
 
+impl FileReader {
+    /// The file that is read, which is still compressed for gzip files
+    fn file(&self) -> &File {
+        match self {
+            Self::Regular(file) => file,
+            Self::Gzipped(gzipped_file) => gzipped_file.get_ref(),
+            Self::Bgzf(bgzf_file) => bgzf_file.get_ref(),
+        }
+    }
+}
+
 impl Read for FileReader {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
             Self::Regular(file) => file.read(buf),
             Self::Gzipped(gzipped_file) => gzipped_file.read(buf),
+            Self::Bgzf(bgzf_file) => bgzf_file.read(buf),
+        }
+    }
+}
+
+/// Reads files in the BGZF format written by bgzip, which is a series of gzip members of at most 64 KiB each. The
+/// compressed size of every member is stored in the `BC` subfield of its extra field, so the members are decoded one
+/// block at a time. Empty members, such as the EOF marker at the end of the file, are skipped.
+pub struct BgzfReader<R: Read> {
+    reader: R,
+    compressed: Vec<u8>,
+    block: Vec<u8>,
+    position_in_block: usize,
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            compressed: Vec::new(),
+            block: Vec::new(),
+            position_in_block: 0,
+        }
+    }
+
+    /// The underlying reader, which is still compressed
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// The flag of a gzip header that indicates it has an extra field
+    const FEXTRA: u8 = 0x04;
+
+    /// Check if the start of a file is the header of a gzip member with a `BC` subfield as its first extra subfield
+    pub fn is_bgzf_header(header: &[u8]) -> bool {
+        header.len() >= 14
+            && header[0..3] == [0x1f, 0x8b, 0x08]
+            && header[3] & Self::FEXTRA != 0
+            && header[12..14] == *b"BC"
+    }
+
+    /// Read and decompress the next block. Returns false at the end of the file.
+    fn read_block(&mut self) -> std::io::Result<bool> {
+        let mut header = [0; 12];
+        if self.reader.read(&mut header[..1])? == 0 {
+            return Ok(false);
+        }
+        self.reader.read_exact(&mut header[1..])?;
+
+        if header[0..3] != [0x1f, 0x8b, 0x08] || header[3] & Self::FEXTRA == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid BGZF block header {:02x?}", header)));
+        }
+
+        let extra_len = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut extra = vec![0; extra_len];
+        self.reader.read_exact(&mut extra)?;
+
+        // The block size is stored minus one, as it can't exceed 64 KiB
+        let block_size = Self::find_block_size(&extra)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "BGZF block is missing the BC subfield with its size"))? as usize + 1;
+
+        let remaining_len = block_size.checked_sub(header.len() + extra_len)
+            .filter(|len| *len >= 8)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Invalid BGZF block size {}", block_size)))?;
+
+        self.compressed.resize(remaining_len, 0);
+        self.reader.read_exact(&mut self.compressed)?;
+
+        // The deflated data is followed by the CRC32 and the size of the uncompressed data
+        let (deflated, trailer) = self.compressed.split_at(remaining_len - 8);
+        let expected_crc = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+        let expected_len = u32::from_le_bytes(trailer[4..8].try_into().unwrap()) as usize;
+
+        self.block.clear();
+        self.position_in_block = 0;
+        DeflateDecoder::new(deflated).read_to_end(&mut self.block)?;
+
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+
+        if self.block.len() != expected_len || crc.sum() != expected_crc {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "Corrupt BGZF block: expected {} bytes with CRC32 {:08x}, got {} bytes with CRC32 {:08x}",
+                expected_len, expected_crc, self.block.len(), crc.sum(),
+            )));
+        }
+
+        Ok(true)
+    }
+
+    /// Find the value of the `BC` subfield in the extra field of a block
+    fn find_block_size(mut extra: &[u8]) -> Option<u16> {
+        while extra.len() >= 4 {
+            let subfield_len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+            let data = extra.get(4..4 + subfield_len)?;
+
+            if extra[0..2] == *b"BC" && subfield_len == 2 {
+                return Some(u16::from_le_bytes([data[0], data[1]]));
+            }
+
+            extra = &extra[4 + subfield_len..];
+        }
+
+        None
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position_in_block == self.block.len() {
+            if !self.read_block()? {
+                return Ok(0);
+            }
         }
+
+        let len = buf.len().min(self.block.len() - self.position_in_block);
+        buf[..len].copy_from_slice(&self.block[self.position_in_block..self.position_in_block + len]);
+        self.position_in_block += len;
+
+        Ok(len)
     }
 }
 
-/// A fast iterator that splits a string by a character, but ignores the character if it is inside a string.
+/// A fast iterator that splits a string by a character, but ignores the character if it is inside a quoted value.
+/// Quotes are handled as in RFC 4180: a value is quoted if it starts with `"`, and a doubled quote `""` inside it is a
+/// literal quote. The quotes around a value are removed, and quotes inside an unquoted value are kept as they are.
+/// Values are only copied if they contain a doubled quote.
 pub struct FastSplit<'a> {
     buf: &'a str,
     split_on: char,
     start: usize,
-    end: usize,
-    is_in_string: bool,
 }
 
 impl<'a> FastSplit<'a> {
@@ -136,51 +407,80 @@ impl<'a> FastSplit<'a> {
             buf,
             split_on,
             start: 0,
-            end: 0,
-            is_in_string: false,
         }
     }
-}
 
-impl<'a> Iterator for FastSplit<'a> {
-    type Item = &'a str;
+    /// Read a quoted value, without its opening quote. Returns the value and the length of the quoted value in `quoted`,
+    /// up to the separator after it.
+    fn split_quoted(quoted: &'a str, split_on: char) -> (Cow<'a, str>, usize) {
+        let mut value = Cow::Borrowed("");
+        // The value is only copied if it consists of several parts, such as around a doubled quote
+        let append = |value: &mut Cow<'a, str>, part: &'a str| {
+            if value.is_empty() {
+                *value = Cow::Borrowed(part);
+            } else if !part.is_empty() {
+                value.to_mut().push_str(part);
+            }
+        };
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.end == self.buf.len() {
-            return None;
-        }
+        let mut i = 0;
+        loop {
+            let Some(quote) = quoted[i..].find('"').map(|quote| i + quote) else {
+                // The value isn't closed, so it continues until the end of the line
+                append(&mut value, &quoted[i..]);
+                return (value, quoted.len());
+            };
 
-        let mut in_string = self.is_in_string;
-        let start = self.start;
-        let mut end = self.end;
+            if quoted[quote + 1..].starts_with('"') {
+                // Keep the first quote of a doubled quote
+                append(&mut value, &quoted[i..=quote]);
+                i = quote + 2;
+                continue;
+            }
 
-        for (i, c) in self.buf[self.end..].char_indices() {
-            end = self.end + i;
+            // Anything between the closing quote and the separator is kept as it is
+            let after = &quoted[quote + 1..];
+            let after_len = after.find(split_on).unwrap_or(after.len());
 
-            if c == '"' {
-                in_string = !in_string;
-            }
+            append(&mut value, &quoted[i..quote]);
+            append(&mut value, &after[..after_len]);
+            return (value, quote + 1 + after_len);
+        }
+    }
+}
 
-            if c == self.split_on && !in_string {
-                self.start = end + 1;
-                self.end = end + 1;
-                self.is_in_string = in_string;
+impl<'a> Iterator for FastSplit<'a> {
+    type Item = Cow<'a, str>;
 
-                return Some(&self.buf[start..end]);
-            }
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.buf.len() {
+            return None;
         }
 
-        self.start = end + 1;
-        self.end = end + 1;
-        self.is_in_string = in_string;
+        let rest = &self.buf[self.start..];
+        let (value, len) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, len) = Self::split_quoted(quoted, self.split_on);
+                (value, '"'.len_utf8() + len)
+            },
+            None => {
+                let len = rest.find(self.split_on).unwrap_or(rest.len());
+                (Cow::Borrowed(&rest[..len]), len)
+            },
+        };
 
-        Some(&self.buf[start..end + 1])
+        // Continue after the separator
+        self.start += len + self.split_on.len_utf8();
+
+        Some(value)
     }
 }
 
 pub struct TabSeparatedFileReader {
     reader: BufReader<FileReader>,
     split_on: char,
+    /// The number of lines read so far, including the header, which is the line number of the last line read
+    lines_read: usize,
 }
 
 impl TabSeparatedFileReader {
@@ -192,25 +492,61 @@ impl TabSeparatedFileReader {
         Self {
             reader: BufReader::with_capacity(capacity, FileReader::new(file)),
             split_on: '\t',
+            lines_read: 0,
+        }
+    }
+
+    /// The number of lines read so far, including the header
+    pub fn lines_read(&self) -> usize {
+        self.lines_read
+    }
+
+    /// The number of bytes of the file that have been read. Lines of plain files that are buffered but not read yet
+    /// aren't counted, but compressed files are read ahead by the decompressor.
+    fn bytes_read(&self) -> std::io::Result<u64> {
+        let mut file = self.reader.get_ref().file();
+        let position = file.stream_position()?;
+
+        match self.reader.get_ref() {
+            FileReader::Regular(_) => Ok(position - self.reader.buffer().len() as u64),
+            FileReader::Gzipped(_) | FileReader::Bgzf(_) => Ok(position),
         }
     }
 
     /// Reads a line from the file and splits it by tabs.
     pub fn read_line_and_split<'a>(&'a mut self, line_buf: &'a mut String) -> Option<FastSplit> {
+        if !self.read_line(line_buf) {
+            return None;
+        }
+
+        Some(FastSplit::new(line_buf.trim_end(), self.split_on))
+    }
+
+    /// Reads a line from the file into the buffer. Returns false at the end of the file.
+    pub fn read_line(&mut self, line_buf: &mut String) -> bool {
         line_buf.clear();
         self.reader.read_line(line_buf).unwrap();
 
         if line_buf.is_empty() {
-            return None;
+            return false;
         }
 
-        Some(FastSplit::new(line_buf.trim_end(), self.split_on))
+        self.lines_read += 1;
+
+        // Some programs start a file with a UTF-8 byte order mark, which isn't part of the first column name
+        if self.lines_read == 1 && line_buf.starts_with('\u{feff}') {
+            line_buf.drain(..'\u{feff}'.len_utf8());
+        }
+
+        true
     }
 
     /// Skips a number of lines in the file.
     pub fn skip_lines(&mut self, n: usize) -> std::io::Result<()>{
         for _ in 0..n {
-            self.reader.read_line(&mut String::new())?;
+            if self.reader.read_line(&mut String::new())? > 0 {
+                self.lines_read += 1;
+            }
         }
 
         Ok(())
@@ -221,7 +557,7 @@ impl TabSeparatedFileReader {
         let mut line_buf = String::new();
 
         let split_tabs: Vec<_> = match self.read_line_and_split(&mut line_buf) {
-            Some(header) => header.map(|s| s.to_owned()).collect(),
+            Some(header) => header.map(Cow::into_owned).collect(),
             None => return Err("Empty file.".to_string()),
         };
 
@@ -230,7 +566,7 @@ impl TabSeparatedFileReader {
         }
 
 
-        let split_commas: Vec<_> = FastSplit::new(&line_buf.trim_end(), ',').map(|s| s.to_owned()).collect();
+        let split_commas: Vec<_> = FastSplit::new(&line_buf.trim_end(), ',').map(Cow::into_owned).collect();
 
         if split_commas.len() > 1 {
             self.split_on = ',';
@@ -263,8 +599,10 @@ impl TabSeparatedFileReader {
     /// # Arguments
     /// 
     /// * `column_indices` - The indices of the columns to guess the types of.
+    /// * `missing_values` - Values such as `NA` or `.` that denote a missing value, in addition to an empty value.
     /// * `volatile_threshold_fraction` - The fraction between 0 and 1 of the number of distinct values in a column that determines if the column is considered a volatile string column.
     /// * `min_sample_size` - The minimum number of lines to read to guess the column types.
+    /// * `sample_limit` - When to stop reading lines after `min_sample_size` lines, instead of reading the entire file.
     /// 
     /// # Returns
     /// 
@@ -272,26 +610,56 @@ impl TabSeparatedFileReader {
     pub fn guess_column_types_but_better(
         &mut self,
         columns: HashMap<usize, MissingValuePolicy>,
+        missing_values: &[String],
         volatile_threshold_fraction: f32,
-        min_sample_size: usize
-    ) -> Result<HashMap<usize, ColumnType>, NotEnoughLinesError> {
+        min_sample_size: usize,
+        sample_limit: Option<SampleLimit>,
+        whole_float_threshold_fraction: Option<f32>,
+    ) -> Result<HashMap<usize, ColumnType>, GuessColumnTypesError> {
         let mut sorted_column_indices: Vec<usize> = columns.keys().copied().collect();
         sorted_column_indices.sort();
 
         let mut column_possibly_float: Vec<bool> = sorted_column_indices.iter().map(|_| true).collect();
         let mut column_possibly_integer: Vec<bool> = sorted_column_indices.iter().map(|_| true).collect();
         let mut column_possibly_hashtable_string: Vec<bool> = sorted_column_indices.iter().map(|_| true).collect();
+        // Columns that could be integers if whole-valued floats such as `3.0` are coerced, and the number of such values
+        let mut column_possibly_whole: Vec<bool> = sorted_column_indices.iter().map(|_| true).collect();
+        let mut column_whole_float_counts: Vec<usize> = sorted_column_indices.iter().map(|_| 0).collect();
 
         // We only keep track of the hashes of the values to save memory, as we don't need to store the actual values.
         let mut column_value_hashes: HashMap<usize, HashSet<u64>> = HashMap::new();
 
         let mut loop_counter: usize = 0;
 
+        // The size of the file is only needed to stop after a fraction of it
+        let file_len = match sample_limit {
+            Some(SampleLimit::Fraction(_)) => self.reader.get_ref().file().metadata().ok().map(|metadata| metadata.len()),
+            _ => None,
+        };
+
+        let is_missing = |value: &str| value.is_empty() || missing_values.iter().any(|token| token == value);
+
         let mut line_buf = String::new();
         
         'row_loop: loop {
             loop_counter += 1;
-            let mut cell_bufs: Vec<&str> = sorted_column_indices.iter().map(|_| "").collect();
+
+            // Stop sampling early, assuming the lines that have been read are representative of the rest of the file
+            let lines_sampled = loop_counter - 1;
+            let limit_reached = lines_sampled >= min_sample_size && match sample_limit {
+                Some(SampleLimit::Lines(max_lines)) => lines_sampled >= max_lines,
+                Some(SampleLimit::Fraction(fraction)) => file_len.is_some_and(|file_len| {
+                    self.bytes_read().is_ok_and(|bytes_read| bytes_read as f64 >= fraction * file_len as f64)
+                }),
+                None => false,
+            };
+
+            if limit_reached {
+                println!("Stopped sampling after {} lines.", lines_sampled);
+                break;
+            }
+
+            let mut cell_bufs: Vec<Cow<str>> = sorted_column_indices.iter().map(|_| Cow::Borrowed("")).collect();
 
             let row = match self.read_line_and_split(&mut line_buf) {
                 Some(row) => row,
@@ -304,31 +672,51 @@ impl TabSeparatedFileReader {
                     continue;
                 }
 
+                let missing = is_missing(&value);
                 cell_bufs[current_cell_buf_index] = value;
                 current_cell_buf_index += 1;
 
-                if value.is_empty() {
+                if missing {
                     match columns[&wide_index] {
                         MissingValuePolicy::OmitRow => continue 'row_loop,
-                        MissingValuePolicy::Throw => panic!("Missing value in column {} in row {}.", wide_index, loop_counter),
+                        MissingValuePolicy::Throw => return Err(GuessColumnTypesError::MissingValue {
+                            column: wide_index,
+                            line: self.lines_read,
+                        }),
                         MissingValuePolicy::ReplaceWithEmptyString => {}, // Do nothing, as the value is already an empty string.
                     }
                 }
             }
             
-            for (narrow_index, value) in cell_bufs.iter().enumerate() {
+            for (narrow_index, value) in cell_bufs.iter().map(AsRef::as_ref).enumerate() {
+                // Missing values are stored as NaN in float columns, but integer columns can't store them
+                if is_missing(value) {
+                    column_possibly_integer[narrow_index] = false;
+                    column_possibly_whole[narrow_index] = false;
+                    continue;
+                }
+
+                let is_integer = value.parse::<i64>().is_ok();
 
                 if column_possibly_integer[narrow_index] {
-                    if value.parse::<i64>().is_err() {
+                    if !is_integer {
                         println!("Failed to parse value {:?} as integer in column {}.", value, sorted_column_indices[narrow_index]);
-                        column_possibly_integer.insert(narrow_index, false);
+                        column_possibly_integer[narrow_index] = false;
+                    }
+                }
+
+                if column_possibly_whole[narrow_index] && !is_integer {
+                    if parse_whole_float(value).is_some() {
+                        column_whole_float_counts[narrow_index] += 1;
+                    } else {
+                        column_possibly_whole[narrow_index] = false;
                     }
                 }
 
                 if column_possibly_float[narrow_index] {
                     if value.parse::<f64>().is_err() {
                         println!("Failed to parse value {:?} as float in column {}.", value, sorted_column_indices[narrow_index]);
-                        column_possibly_float.insert(narrow_index, false);
+                        column_possibly_float[narrow_index] = false;
                     }
                 }
 
@@ -342,7 +730,7 @@ impl TabSeparatedFileReader {
 
                     if loop_counter >= min_sample_size && hashes.len() > (loop_counter as f32 * volatile_threshold_fraction) as usize {
                         println!("Determined column {} to be volatile after {} iterations.", sorted_column_indices[narrow_index], loop_counter);
-                        column_possibly_hashtable_string.insert(narrow_index, false);
+                        column_possibly_hashtable_string[narrow_index] = false;
                         column_value_hashes.remove(&narrow_index);
                     }
                 }
@@ -350,7 +738,7 @@ impl TabSeparatedFileReader {
         }
 
         if loop_counter < min_sample_size {
-            return Err(NotEnoughLinesError);
+            return Err(GuessColumnTypesError::NotEnoughLines);
         }
 
         let mut column_types = HashMap::new();
@@ -361,6 +749,20 @@ impl TabSeparatedFileReader {
                 continue;
             }
 
+            // Promote columns with only whole values to integers if enough of them are written as integers
+            if let Some(threshold_fraction) = whole_float_threshold_fraction {
+                let integer_fraction = 1.0 - column_whole_float_counts[narrow_index] as f32 / loop_counter as f32;
+
+                if column_possibly_whole[narrow_index] && integer_fraction >= threshold_fraction {
+                    println!(
+                        "Determined column {} to be integer, {} whole-valued floats need 'coerce_whole_floats'.",
+                        wide_index, column_whole_float_counts[narrow_index],
+                    );
+                    column_types.insert(*wide_index, ColumnType::Integer);
+                    continue;
+                }
+            }
+
             if column_possibly_float[narrow_index] {
                 column_types.insert(*wide_index, ColumnType::Float);
                 continue;
@@ -377,51 +779,196 @@ impl TabSeparatedFileReader {
     }
 
     pub fn read_all(&mut self, columns: &Vec<(usize, &Column)>) -> Result<Vec<Vec<CellValue>>, String> {
-        let mut line_buf = String::new();
-        let mut loop_counter: usize = 0;
+        let mut rows: Vec<Vec<CellValue>> = Vec::new();
+
+        self.read_rows(columns, |row| {
+            rows.push(row);
+            Ok(())
+        })?;
+
+        Ok(rows)
+    }
 
+    /// Parse the rows one at a time and pass them to `on_row`, without collecting them. An error of `on_row` stops
+    /// reading and is returned with the line number of the row.
+    pub fn read_rows(&mut self, columns: &Vec<(usize, &Column)>, on_row: impl FnMut(Vec<CellValue>) -> Result<(), String>) -> Result<(), String> {
+        self.read_rows_limited(columns, None, on_row)
+    }
+
+    /// Parse the first `max_rows` rows that aren't omitted, such as to sample a file without reading all of it.
+    pub fn read_head(&mut self, columns: &Vec<(usize, &Column)>, max_rows: usize) -> Result<Vec<Vec<CellValue>>, String> {
         let mut rows: Vec<Vec<CellValue>> = Vec::new();
 
-        'row_loop: loop {
-            loop_counter += 1;
+        self.read_rows_limited(columns, Some(max_rows), |row| {
+            rows.push(row);
+            Ok(())
+        })?;
 
-            let row: Vec<&str> = match self.read_line_and_split(&mut line_buf) {
-                Some(row) => row.collect(),
-                None => break,
+        Ok(rows)
+    }
+
+    /// Parse the rows like `read_rows`, stopping after `max_rows` rows have been passed to `on_row` if it is set
+    fn read_rows_limited(
+        &mut self,
+        columns: &Vec<(usize, &Column)>,
+        max_rows: Option<usize>,
+        mut on_row: impl FnMut(Vec<CellValue>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut line_buf = String::new();
+        let mut rows_read: usize = 0;
+
+        let patterns = columns.iter()
+            .map(|(_, column)| column.constraints.compile_pattern())
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // The indices of the start and end columns of interval datasets
+        let interval_columns = match (
+            columns.iter().position(|(_, column)| column.role == ColumnRole::PositionStart),
+            columns.iter().position(|(_, column)| column.role == ColumnRole::PositionEnd),
+        ) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None,
+        };
+
+        while max_rows.is_none_or(|max_rows| rows_read < max_rows) && self.read_line(&mut line_buf) {
+            let line = line_buf.trim_end();
+            let row: Vec<Cow<str>> = FastSplit::new(line, self.split_on).collect();
+
+            let res = match Self::parse_row(columns, &patterns, interval_columns, &row) {
+                Ok(Some(parsed)) => {
+                    rows_read += 1;
+                    on_row(parsed)
+                },
+                Ok(None) => Ok(()), // The row is omitted
+                Err(e) => Err(e),
             };
 
-            for (wide_index, column) in columns.iter() {
-                match row.get(*wide_index) {
-                    Some(_) => {},
-                    None => {
-                        match column.missing_value_policy {
-                            MissingValuePolicy::OmitRow => continue 'row_loop,
-                            MissingValuePolicy::Throw => return Err(format!("Missing value in column {} in row {}.", wide_index, loop_counter)),
-                            MissingValuePolicy::ReplaceWithEmptyString => {}, // Do nothing, as the value is already an empty string.
-                        }
-                    }
-                };
+            if let Err(e) = res {
+                return Err(format!("Line {}: {}\n\t{:?}", self.lines_read, e, line));
             }
+        }
 
-            let parsed = columns.iter().map(|(wide_index, column)| {
-                let value = row.get(*wide_index).expect("Column index out of bounds");
+        Ok(())
+    }
 
-                column.type_.get_cell_value(value)
-            }).collect::<Result<Vec<CellValue>, String>>();
+    /// Parse the values of a row and check the constraints of the columns. Returns `None` if the row should be omitted.
+    fn parse_row(
+        columns: &[(usize, &Column)],
+        patterns: &[Option<Regex>],
+        interval_columns: Option<(usize, usize)>,
+        row: &[Cow<str>],
+    ) -> Result<Option<Vec<CellValue>>, String> {
+        for (wide_index, column) in columns.iter() {
+            let missing = match row.get(*wide_index) {
+                Some(value) => column.is_missing_value(value),
+                None => true,
+            };
 
-            match parsed {
-                Ok(parsed) => rows.push(parsed),
-                Err(e) => return Err(e),
+            if missing {
+                match column.missing_value_policy {
+                    MissingValuePolicy::OmitRow => return Ok(None),
+                    MissingValuePolicy::Throw => return Err(format!("Missing value in column '{}'.", column.name)),
+                    MissingValuePolicy::ReplaceWithEmptyString => {}, // The value is replaced with an empty string below.
+                }
             }
         }
 
-        Ok(rows)
+        let parsed = columns.iter().map(|(wide_index, column)| {
+            let value = match row.get(*wide_index) {
+                Some(value) if !column.is_missing_value(value) => value.as_ref(),
+                _ => return Self::missing_cell_value(column),
+            };
+
+            let value = match column.parse_format {
+                Some(parse_format) => parse_format.transform(value)?,
+                None => Cow::Borrowed(value),
+            };
+
+            column.type_.get_cell_value(&value, column.coerce_whole_floats)
+                .map_err(|e| format!("Column '{}': {}", column.name, e))
+        }).collect::<Result<Vec<CellValue>, String>>();
+
+        let mut parsed = parsed?;
+
+        for (((_, column), cell), pattern) in columns.iter().zip(parsed.iter_mut()).zip(patterns.iter()) {
+            if column.constraints.is_empty() {
+                continue;
+            }
+
+            let violation = match column.constraints.check(cell, pattern.as_ref()) {
+                Ok(_) => continue,
+                Err(violation) => violation,
+            };
+
+            if !Self::apply_violation_policy(column, cell, violation)? {
+                return Ok(None);
+            }
+        }
+
+        // Intervals can't end before they start, which is handled as a violation of the end column
+        if let Some((start, end)) = interval_columns {
+            if let (CellValue::Integer(start_value), CellValue::Integer(end_value)) = (&parsed[start], &parsed[end]) {
+                if end_value < start_value {
+                    let violation = format!("interval must not end before it starts at {}", start_value);
+                    let (_, column) = columns[end];
+
+                    if !Self::apply_violation_policy(column, &mut parsed[end], violation)? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        Ok(Some(parsed))
+    }
+
+    /// Apply the constraint violation policy of a column to a cell that violates a constraint. Returns false if the row
+    /// should be omitted.
+    fn apply_violation_policy(column: &Column, cell: &mut CellValue, violation: String) -> Result<bool, String> {
+        match column.constraint_violation_policy {
+            ConstraintViolationPolicy::OmitRow => Ok(false),
+            ConstraintViolationPolicy::Throw => Err(format!(
+                "Value {:?} in column '{}' violates a constraint: {}.",
+                cell, column.name, violation
+            )),
+            ConstraintViolationPolicy::TreatAsMissing => {
+                match column.missing_value_policy {
+                    MissingValuePolicy::OmitRow => Ok(false),
+                    MissingValuePolicy::Throw => Err(format!(
+                        "Missing value in column '{}' ({}).",
+                        column.name, violation
+                    )),
+                    MissingValuePolicy::ReplaceWithEmptyString => {
+                        *cell = Self::missing_cell_value(column)?;
+                        Ok(true)
+                    },
+                }
+            },
+        }
     }
 
-    pub fn convert_read_data(&mut self, columns: &Vec<Column>, mut rows: Vec<Vec<CellValue>>) -> Result<Vec<Vec<CellValue>>, String> {
+    /// The cell of a missing value under the `ReplaceWithEmptyString` policy. A missing float is stored as NaN, as an
+    /// empty string can't be parsed as a float.
+    fn missing_cell_value(column: &Column) -> Result<CellValue, String> {
+        match column.type_ {
+            ColumnType::Float => Ok(CellValue::Float(f64::NAN)),
+            _ => column.type_.get_cell_value("", column.coerce_whole_floats)
+                .map_err(|e| format!("Column '{}': {}", column.name, e)),
+        }
+    }
+
+    /// Sort the rows by their position. Float positions are first scaled by `10^position_decimals` to integers.
+    pub fn convert_read_data(&mut self, columns: &[Column], position_decimals: Option<u8>, mut rows: Vec<Vec<CellValue>>) -> Result<Vec<Vec<CellValue>>, String> {
         assert!(columns[0].role == ColumnRole::Position || columns[0].role == ColumnRole::PositionStart, "First column must be a position.");
 
-        println!("First row: {:?}", rows[0]);
+        // Empty tables are rejected with an error when the dataset is serialized
+        if rows.is_empty() {
+            return Ok(rows);
+        }
+
+        for row in rows.iter_mut() {
+            Self::scale_position(columns, position_decimals, row)?;
+        }
 
         rows.sort_by(|a, b| {
             match (&a[0], &b[0]) {
@@ -432,4 +979,501 @@ impl TabSeparatedFileReader {
 
         Ok(rows)
     }
+
+    /// Scale a float position by `10^position_decimals` to an integer. Rows of datasets with integer positions are left
+    /// unchanged.
+    pub fn scale_position(columns: &[Column], position_decimals: Option<u8>, row: &mut [CellValue]) -> Result<(), String> {
+        let Some(position_decimals) = position_decimals else {
+            return Ok(());
+        };
+
+        if let CellValue::Float(position) = row[0] {
+            let scaled = (position * 10f64.powi(position_decimals as i32)).round();
+            if !scaled.is_finite() || scaled.abs() >= i64::MAX as f64 {
+                return Err(format!(
+                    "Position {} in column '{}' can't be stored with {} decimals",
+                    position, columns[0].name, position_decimals,
+                ));
+            }
+
+            row[0] = CellValue::Integer(scaled as i64);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::write_files;
+
+    /// Parse a column of a config from TOML
+    fn column(toml: &str) -> Column {
+        toml::from_str(toml).unwrap()
+    }
+
+    /// Read all rows of a TSV file with the columns of a config, which are in the same order as in the file
+    fn read_all(tsv: &str, columns: &[Column]) -> Result<Vec<Vec<CellValue>>, String> {
+        let dir = write_files(&[("data.tsv", tsv)]);
+        let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+        reader.read_header()?;
+
+        let columns: Vec<(usize, &Column)> = columns.iter().enumerate().collect();
+        reader.read_all(&columns)
+    }
+
+    #[test]
+    fn out_of_range_value_is_rejected_under_throw_policy() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "af"
+                type = "float"
+                constraints = { min = 0.0, max = 1.0 }
+                constraint_violation_policy = "Throw""#),
+        ];
+
+        assert_eq!(read_all("pos\taf\n1\t0.5\n2\t1.0\n", &columns).unwrap().len(), 2);
+
+        let e = read_all("pos\taf\n1\t0.5\n2\t1.5\n", &columns).unwrap_err();
+        assert!(e.contains("Line 3: Value Float(1.5) in column 'af' violates a constraint: value must be at most 1"), "{}", e);
+    }
+
+    #[test]
+    fn out_of_range_value_is_omitted_under_omit_row_policy() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "gene"
+                type = "volatile-string"
+                constraints = { pattern = "^[A-Z0-9]+$" }
+                constraint_violation_policy = "OmitRow""#),
+        ];
+
+        let rows = read_all("pos\tgene\n1\tBRCA1\n2\tnot a gene\n3\tTP53\n", &columns).unwrap();
+        let positions: Vec<_> = rows.iter().map(|row| match row[0] {
+            CellValue::Integer(pos) => pos,
+            _ => panic!("{:?}", row),
+        }).collect();
+        assert_eq!(positions, vec![1, 3]);
+    }
+
+    #[test]
+    fn missing_value_tokens_are_handled_by_the_missing_value_policy() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "count"
+                type = "integer"
+                missing_value_policy = "OmitRow"
+                missing_values = ["NA", "."]"#),
+            column(r#"name = "gene"
+                type = "volatile-string"
+                missing_value_policy = "ReplaceWithEmptyString"
+                missing_values = ["NA"]"#),
+        ];
+
+        let rows = read_all("pos\tcount\tgene\n1\t5\tBRCA1\n2\tNA\tTP53\n3\t.\tTP53\n4\t7\tNA\n", &columns).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(&rows[0][..], [CellValue::Integer(1), CellValue::Integer(5), CellValue::String(gene)] if &**gene == "BRCA1"), "{:?}", rows[0]);
+        assert!(matches!(&rows[1][..], [CellValue::Integer(4), CellValue::Integer(7), CellValue::String(gene)] if gene.is_empty()), "{:?}", rows[1]);
+
+        // Without the tokens, NA is parsed like any other value
+        let mut columns = columns;
+        columns[1].missing_values.clear();
+        assert!(read_all("pos\tcount\tgene\n2\tNA\tTP53\n", &columns).is_err());
+    }
+
+    #[test]
+    fn out_of_range_float_treated_as_missing_is_nan() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "af"
+                type = "float"
+                constraints = { min = 0.0, max = 1.0 }
+                constraint_violation_policy = "TreatAsMissing"
+                missing_value_policy = "ReplaceWithEmptyString""#),
+        ];
+
+        let rows = read_all("pos\taf\n1\t0.5\n2\t-0.5\n", &columns).unwrap();
+        assert!(matches!(rows[0][..], [CellValue::Integer(1), CellValue::Float(af)] if af == 0.5), "{:?}", rows[0]);
+        assert!(matches!(rows[1][..], [CellValue::Integer(2), CellValue::Float(af)] if af.is_nan()), "{:?}", rows[1]);
+    }
+
+    #[test]
+    fn mostly_integer_column_with_whole_floats_is_guessed_and_read_as_integer() {
+        // Every 20th count is written as a whole-valued float
+        let mut tsv = String::from("pos\tcount\n");
+        for i in 0..100 {
+            if i % 20 == 0 { tsv.push_str(&format!("{}\t{}.0\n", i, i)) } else { tsv.push_str(&format!("{}\t{}\n", i, i)) }
+        }
+
+        let guess = |whole_float_threshold_fraction| {
+            let dir = write_files(&[("data.tsv", &tsv)]);
+            let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+            reader.read_header().unwrap();
+
+            let columns = HashMap::from([(1, MissingValuePolicy::Throw)]);
+            reader.guess_column_types_but_better(columns, &[], 0.2, 50, None, whole_float_threshold_fraction).unwrap()[&1]
+        };
+
+        assert_eq!(guess(None), ColumnType::Float);
+        assert_eq!(guess(Some(0.9)), ColumnType::Integer);
+        assert_eq!(guess(Some(0.99)), ColumnType::Float);
+
+        let pos = || column(r#"name = "pos"
+            type = "integer"
+            role = "position""#);
+        let coerced = column(r#"name = "count"
+            type = "integer"
+            coerce_whole_floats = true"#);
+        let rows = read_all(&tsv, &[pos(), coerced]).unwrap();
+        assert_eq!(rows[20], vec![CellValue::Integer(20), CellValue::Integer(20)]);
+        assert_eq!(rows.len(), 100);
+
+        let strict = column(r#"name = "count"
+            type = "integer""#);
+        let e = read_all(&tsv, &[pos(), strict]).unwrap_err();
+        assert!(e.contains("Failed to parse value '\"0.0\"' as integer"), "{}", e);
+    }
+
+    #[test]
+    fn missing_value_tokens_are_replaced_like_violations() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "af"
+                type = "float"
+                missing_values = [".", "NA"]
+                constraints = { min = 0.0 }
+                constraint_violation_policy = "TreatAsMissing"
+                missing_value_policy = "ReplaceWithEmptyString""#),
+            column(r#"name = "gene"
+                type = "volatile-string"
+                missing_values = ["NA"]
+                missing_value_policy = "ReplaceWithEmptyString""#),
+        ];
+
+        // The af of the first three rows is missing and of the fourth violates the constraint
+        let tsv = "pos\taf\tgene\n1\t.\tNA\n2\tNA\tBRCA1\n3\t\tNA\n4\t-1\tTP53\n5\t0.25\tNAN\n";
+        let rows = read_all(tsv, &columns).unwrap();
+        let missing_af: Vec<bool> = rows.iter().map(|row| matches!(row[1], CellValue::Float(af) if af.is_nan())).collect();
+        assert_eq!(missing_af, [true, true, true, true, false]);
+
+        let genes: Vec<&CellValue> = rows.iter().map(|row| &row[2]).collect();
+        assert_eq!(genes, [
+            &CellValue::String("".into()),
+            &CellValue::String("BRCA1".into()),
+            &CellValue::String("".into()),
+            &CellValue::String("TP53".into()),
+            &CellValue::String("NAN".into()),
+        ]);
+    }
+
+    fn split(line: &str, split_on: char) -> Vec<Cow<'_, str>> {
+        FastSplit::new(line, split_on).collect()
+    }
+
+    #[test]
+    fn doubled_quote_in_quoted_value_is_a_literal_quote() {
+        assert_eq!(split(r#""a""b",c"#, ','), ["a\"b", "c"]);
+        assert_eq!(split(r#""a,b",c"#, ','), ["a,b", "c"]);
+        assert_eq!(split(r#"x,"y""z""",w,"""q",e"#, ','), ["x", "y\"z\"", "w", "\"q", "e"]);
+        assert_eq!(split("1\t\"free \"\"text\"\", with\ttab\"\t\t2", '\t'), ["1", "free \"text\", with\ttab", "", "2"]);
+
+        // A quote within an unquoted value is kept, and a value that isn't closed continues until the end of the line
+        assert_eq!(split(r#"a"b,"c,d"#, ','), ["a\"b", "c,d"]);
+
+        // Values are split on characters, so multibyte characters are kept whole wherever they are
+        assert_eq!(split("é\t\"ü\"\"ß\"\tα", '\t'), ["é", "ü\"ß", "α"]);
+
+        // Values without quotes are borrowed from the line
+        assert!(split(r#"a,"b",c"#, ',').iter().all(|value| matches!(value, Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn guesser_stops_after_sample_limit() {
+        // The first half of the file is representative, but the count column has a string in the second half
+        let mut tsv = String::from("count\tscore\n");
+        for i in 0..2000 {
+            if i < 1000 {
+                tsv.push_str(&format!("{:04}\t{:06.2}\n", i, i as f64 / 7.0));
+            } else {
+                tsv.push_str(&format!("n{:03}\t{:06.2}\n", i % 1000, i as f64 / 7.0));
+            }
+        }
+        let dir = write_files(&[("data.tsv", &tsv)]);
+
+        let guess = |sample_limit: Option<SampleLimit>| {
+            let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+            reader.read_header().unwrap();
+
+            let columns = HashMap::from([(0, MissingValuePolicy::Throw)]);
+            let column_types = reader.guess_column_types_but_better(columns, &[], 0.2, 100, sample_limit, None).unwrap();
+            (column_types[&0], reader.lines_read() - 1)
+        };
+
+        assert_eq!(guess(None), (ColumnType::VolatileString, 2000));
+        assert_eq!(guess(Some(SampleLimit::Lines(300))), (ColumnType::Integer, 300));
+
+        // Every line has the same length, so a quarter of the file is about 500 lines
+        let (count_type, lines_sampled) = guess(Some(SampleLimit::Fraction(0.25)));
+        assert_eq!(count_type, ColumnType::Integer);
+        assert!((490..=510).contains(&lines_sampled), "{}", lines_sampled);
+
+        // At least the minimum number of lines is read
+        assert_eq!(guess(Some(SampleLimit::Fraction(0.001))).1, 100);
+    }
+
+    #[test]
+    fn guessing_a_missing_value_under_throw_policy_is_an_error() {
+        let dir = write_files(&[("data.tsv", "score\tpos\n0.5\t1\n\t2\n1.5\t3\n")]);
+
+        let guess = |missing_value_policy: MissingValuePolicy| {
+            let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+            reader.read_header().unwrap();
+
+            let columns = HashMap::from([(0, missing_value_policy), (1, MissingValuePolicy::Throw)]);
+            reader.guess_column_types_but_better(columns, &[], 0.2, 1, None, None)
+        };
+
+        let e = guess(MissingValuePolicy::Throw).unwrap_err();
+        assert!(matches!(e, GuessColumnTypesError::MissingValue { column: 0, line: 3 }), "{:?}", e);
+        assert_eq!(e.to_string(), "Line 3: Missing value in column 0.");
+
+        assert_eq!(guess(MissingValuePolicy::OmitRow).unwrap()[&0], ColumnType::Float);
+    }
+
+    #[test]
+    fn parse_format_transforms_values_before_they_are_parsed() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "fraction"
+                type = "float"
+                parse_format = "percent""#),
+            column(r#"name = "flags"
+                type = "integer"
+                parse_format = "hex-integer""#),
+            column(r#"name = "energy"
+                type = "float"
+                parse_format = "fortran-double""#),
+        ];
+
+        let rows = read_all("pos\tfraction\tflags\tenergy\n1\t5%\t0x1F\t1.5D+03\n2\t12.5 %\t-ff\t2d-1\n", &columns).unwrap();
+        assert_eq!(rows, vec![
+            vec![CellValue::Integer(1), CellValue::Float(0.05), CellValue::Integer(31), CellValue::Float(1500.0)],
+            vec![CellValue::Integer(2), CellValue::Float(0.125), CellValue::Integer(-255), CellValue::Float(0.2)],
+        ]);
+
+        let e = read_all("pos\tfraction\tflags\tenergy\n1\t5%\t0x1G\t1.0\n", &columns).unwrap_err();
+        assert!(e.contains("as hexadecimal integer"), "{}", e);
+    }
+
+    #[test]
+    fn converting_no_rows_returns_no_rows() {
+        let dir = write_files(&[("data.tsv", "pos\tscore\n")]);
+        let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+        reader.read_header().unwrap();
+
+        let columns = vec![
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "score"
+                type = "float""#),
+        ];
+
+        let rows = reader.read_all(&columns.iter().enumerate().collect::<Vec<_>>()).unwrap();
+        assert_eq!(reader.convert_read_data(&columns, None, rows).unwrap(), Vec::<Vec<CellValue>>::new());
+    }
+
+    #[test]
+    fn integer_and_float_overflow_are_reported_with_the_column_name() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "score"
+                type = "float""#),
+        ];
+
+        let e = read_all("pos\tscore\n1\t0.5\n9223372036854775808\t0.5\n", &columns).unwrap_err();
+        assert_eq!(e, "Line 3: Column 'pos': Value '9223372036854775808' is out of range for an integer (must be between -9223372036854775808 and 9223372036854775807).\n\t\"9223372036854775808\\t0.5\"");
+
+        let e = read_all("pos\tscore\n1\t1e400\n", &columns).unwrap_err();
+        assert_eq!(e, "Line 2: Column 'score': Value '1e400' is out of range for a float (must be between -1.7976931348623157e308 and 1.7976931348623157e308).\n\t\"1\\t1e400\"");
+
+        let rows = read_all("pos\tscore\n1\t-inf\n", &columns).unwrap();
+        assert!(matches!(rows[0][..], [CellValue::Integer(1), CellValue::Float(score)] if score == f64::NEG_INFINITY), "{:?}", rows[0]);
+    }
+
+    #[test]
+    fn char_column_rejects_values_that_are_not_a_single_ascii_character() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "strand"
+                type = "char""#),
+        ];
+
+        let rows = read_all("pos\tstrand\n1\t+\n2\t-\n", &columns).unwrap();
+        assert_eq!(rows[1], [CellValue::Integer(2), CellValue::String("-".into())]);
+
+        let e = read_all("pos\tstrand\n1\t+\n2\tAB\n", &columns).unwrap_err();
+        assert_eq!(e, "Line 3: Column 'strand': Value '\"AB\"' is not a single ASCII character.\n\t\"2\\tAB\"");
+
+        let [pos, strand] = columns;
+        let e = read_all("strand\tpos\né\t1\n", &[strand, pos]).unwrap_err();
+        assert!(e.contains("is not a single ASCII character"), "{}", e);
+    }
+
+    /// The empty block that bgzip writes at the end of every file, as given in the SAM specification
+    const BGZF_EOF: [u8; 28] = [
+        0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+        0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// Compress data into a BGZF block like bgzip does
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        use flate2::{write::DeflateEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let mut crc = Crc::new();
+        crc.update(data);
+
+        let block_size = (18 + deflated.len() + 8 - 1) as u16;
+        let mut block = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0];
+        block.extend_from_slice(&block_size.to_le_bytes());
+        block.extend_from_slice(&deflated);
+        block.extend_from_slice(&crc.sum().to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn bgzf_file_is_read_block_by_block_up_to_the_eof_marker() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "gene"
+                type = "volatile-string""#),
+        ];
+
+        let mut tsv = String::from("pos\tgene\n");
+        for i in 0..1000 {
+            tsv.push_str(&format!("{}\tGENE{}\n", i, i));
+        }
+
+        // Split the blocks in the middle of lines, as bgzip does not align them to lines
+        let mut bgzf: Vec<u8> = tsv.as_bytes().chunks(1000).flat_map(bgzf_block).collect();
+        bgzf.extend_from_slice(&BGZF_EOF);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.tsv.gz");
+        std::fs::write(&path, &bgzf).unwrap();
+
+        assert!(matches!(FileReader::new(File::open(&path).unwrap()), FileReader::Bgzf(_)));
+
+        let mut reader = TabSeparatedFileReader::new(File::open(&path).unwrap());
+        assert_eq!(reader.read_header().unwrap(), vec!["pos", "gene"]);
+
+        let columns: Vec<(usize, &Column)> = columns.iter().enumerate().collect();
+        let rows = reader.read_all(&columns).unwrap();
+        assert_eq!(rows.len(), 1000);
+        assert!(matches!(&rows[999][..], [CellValue::Integer(999), CellValue::String(gene)] if &**gene == "GENE999"), "{:?}", rows[999]);
+    }
+
+    #[test]
+    fn byte_order_mark_is_not_part_of_the_first_column_name() {
+        use std::io::Write;
+        use flate2::{write::GzEncoder, Compression};
+
+        let tsv = "\u{feff}pos\tgene\n1\tBRCA1\n";
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        gzipped.write_all(tsv.as_bytes()).unwrap();
+
+        let dir = write_files(&[("data.tsv", tsv)]);
+        std::fs::write(dir.path().join("data.tsv.gz"), gzipped.finish().unwrap()).unwrap();
+
+        for name in ["data.tsv", "data.tsv.gz"] {
+            let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join(name)).unwrap());
+            let column_indices = reader.find_column_indices(&vec!["pos".to_string(), "gene".to_string()]).unwrap();
+            assert_eq!(column_indices, [("pos".to_string(), 0), ("gene".to_string(), 1)], "{}", name);
+        }
+    }
+
+    #[test]
+    fn parse_error_includes_the_line_number_and_raw_line() {
+        let columns = [
+            column(r#"name = "pos"
+                type = "integer"
+                role = "position""#),
+            column(r#"name = "score"
+                type = "float""#),
+        ];
+
+        let dir = write_files(&[("data.tsv", "## comment\npos\tscore\n1\t0.5\n2\tabc\n")]);
+        let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+        reader.skip_lines(1).unwrap();
+        reader.read_header().unwrap();
+
+        let columns: Vec<(usize, &Column)> = columns.iter().enumerate().collect();
+        let e = reader.read_all(&columns).unwrap_err();
+        assert!(e.starts_with("Line 4: Column 'score': Failed to parse value"), "{}", e);
+        assert!(e.ends_with("\n\t\"2\\tabc\""), "{}", e);
+    }
+
+    #[test]
+    fn inverted_interval_is_rejected_under_throw_policy() {
+        let columns = [
+            column(r#"name = "start"
+                type = "integer"
+                role = "position-start""#),
+            column(r#"name = "end"
+                type = "integer"
+                role = "position-end""#),
+        ];
+
+        assert_eq!(read_all("start\tend\n1\t5\n7\t7\n", &columns).unwrap().len(), 2);
+
+        let e = read_all("start\tend\n1\t5\n9\t3\n", &columns).unwrap_err();
+        assert!(e.starts_with("Line 3: Value Integer(3) in column 'end' violates a constraint: interval must not end before it starts at 9."), "{}", e);
+    }
+
+    #[test]
+    fn guesser_keeps_the_flags_of_later_columns_aligned() {
+        // Every column is ruled out as a different type, which must not shift the flags of the columns after it
+        let mut tsv = String::from("count\tscore\tname\tflag\n");
+        for i in 0..200 {
+            tsv.push_str(&format!("{}\t{}.5\tname-{}\t{}\n", i, i, i, i % 3));
+        }
+        let dir = write_files(&[("data.tsv", &tsv)]);
+
+        let mut reader = TabSeparatedFileReader::new(File::open(dir.path().join("data.tsv")).unwrap());
+        reader.read_header().unwrap();
+
+        let columns = (0..4).map(|i| (i, MissingValuePolicy::Throw)).collect();
+        let column_types = reader.guess_column_types_but_better(columns, &[], 0.2, 100, None, None).unwrap();
+
+        assert_eq!(column_types[&0], ColumnType::Integer);
+        assert_eq!(column_types[&1], ColumnType::Float);
+        assert_eq!(column_types[&2], ColumnType::VolatileString);
+        assert_eq!(column_types[&3], ColumnType::Integer);
+        assert_eq!(column_types.len(), 4);
+    }
 }